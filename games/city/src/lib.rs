@@ -2,6 +2,7 @@ mod model;
 mod render;
 
 use crate::{model::CityModel, render::CityRender};
+use log::info;
 use rust_pixel::game::Game;
 
 #[cfg(target_arch = "wasm32")]
@@ -89,9 +90,24 @@ impl CityGame {
     }
 }
 
+// with --resume the game is resumed from this file on start,
+// and suspended to it on quit
+const SAVE_FILE: &str = "tmp/city.sav";
+
 pub fn run() -> Result<(), JsValue> {
+    let resume = std::env::args().any(|a| a == "--resume");
     let mut g = init_game().g;
+    if resume && g.load_file(SAVE_FILE).is_ok() {
+        info!("resume from {}", SAVE_FILE);
+        let _ = std::fs::remove_file(SAVE_FILE);
+    }
     g.run().unwrap();
     g.render.panel.reset(&mut g.context);
+    if resume {
+        let _ = std::fs::create_dir_all("tmp");
+        if let Err(e) = g.save_file(SAVE_FILE) {
+            info!("suspend failed: {}", e);
+        }
+    }
     Ok(())
 }
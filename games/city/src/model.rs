@@ -3,10 +3,11 @@ use log::info;
 use std::any::Any;
 use std::collections::HashMap;
 use std::fmt;
+use serde::{Deserialize, Serialize};
 use rust_pixel::{
     context::Context,
-    event::{event_check, event_emit, timer_fire, timer_register, timer_set_time},
-    game::Model,
+    event::TimerEvent,
+    game::{save::SaveModel, Model},
     algorithm::union_find::{UnionFind, UF},
     util::Rand,
 };
@@ -19,6 +20,11 @@ pub const CELLH: usize = 5;
 pub const COLOR_COUNT: usize = 5;
 pub const LEVELUP_TIME: f32 = 0.03;
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum CityEvent {
+    RedrawGrid,
+}
+
 #[derive(PartialEq)]
 pub enum CityState {
     Normal,
@@ -41,7 +47,7 @@ impl From<u8> for CityState {
     }
 }
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Serialize, Deserialize)]
 pub struct CityCell {
     pub id: i16,
     pub from_id: Option<i16>,
@@ -112,7 +118,7 @@ impl fmt::Display for CityCell {
     }
 }
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Serialize, Deserialize)]
 pub struct CityUnit {
     cells: Vec<i16>,
     merging: bool,
@@ -133,6 +139,7 @@ impl CityUnit {
     }
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct CityLevelup {
     pub cellid: i16,
     pub from: i16,
@@ -149,7 +156,7 @@ impl CityLevelup {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct CityMerge {
     object_id: i16,
     merge_cells: Vec<i16>,
@@ -164,6 +171,7 @@ impl CityMerge {
     }
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct CityModel {
     pub rand: Rand,
     pub grid: [[CityCell; NCOL]; NROW],
@@ -239,7 +247,7 @@ impl CityModel {
         }
     }
 
-    pub fn reset(&mut self) {
+    pub fn reset(&mut self, ctx: &mut Context) {
         self.rand.srand_now();
 
         //固定种子用于debug
@@ -257,30 +265,37 @@ impl CityModel {
             }
         }
         self.get_units_two_pass(0, NCOL);
-        timer_register("merge", 0.2, "_");
-        timer_register("levelup", 0.2, "_");
-        timer_register("drop", 0.2, "_");
-        event_emit("redraw_grid");
+        ctx.timers.register("merge", 0.2);
+        ctx.timers.register("levelup", 0.2);
+        ctx.timers.register("drop", 0.2);
+        ctx.events.emit(CityEvent::RedrawGrid);
     }
 
     pub fn timer_process(&mut self, ctx: &mut Context) {
-        if event_check("merge", "_") {
+        let fired: Vec<String> = ctx
+            .events
+            .drain::<TimerEvent>("model")
+            .into_iter()
+            .map(|e| e.0)
+            .collect();
+        let fired = |name: &str| fired.iter().any(|f| f == name);
+        if fired("merge") {
             ctx.state = CityState::LevelUpMovie as u8;
             let lc = self.post_merge();
             //lc有时候返回为0或1，导致set_time中timer.count未0
-            //在timer_set_time中加了保护，让count至少为1
-            timer_set_time("levelup", lc as f32 * LEVELUP_TIME);
-            timer_fire("levelup", 0);
+            //在Timers::set_time中加了保护，让count至少为1
+            ctx.timers.set_time("levelup", lc as f32 * LEVELUP_TIME);
+            ctx.timers.fire("levelup", 0);
         }
-        if event_check("levelup", "_") {
+        if fired("levelup") {
             ctx.state = CityState::DropMovie as u8;
             self.drop();
-            timer_fire("drop", 0);
+            ctx.timers.fire("drop", 0);
         }
-        if event_check("drop", "_") {
+        if fired("drop") {
             ctx.state = CityState::Normal as u8;
             self.get_units_two_pass(0, NCOL);
-            event_emit("redraw_grid");
+            ctx.events.emit(CityEvent::RedrawGrid);
         }
     }
 
@@ -562,14 +577,14 @@ impl CityModel {
     pub fn act(&mut self, row: i16, col: i16, ctx: &mut Context) {
         let cid = row * NCOL as i16 + col;
         if self.merge_cell(cid) {
-            event_emit("redraw_grid");
-            timer_fire("merge", 0);
+            ctx.events.emit(CityEvent::RedrawGrid);
+            ctx.timers.fire("merge", 0);
             ctx.state = CityState::MergeMovie as u8;
         } else {
             if !self.del_cell(cid) {
-                event_emit("redraw_grid");
+                ctx.events.emit(CityEvent::RedrawGrid);
             } else {
-                timer_fire("drop", 0);
+                ctx.timers.fire("drop", 0);
                 ctx.state = CityState::DropMovie as u8;
             }
         }
@@ -577,8 +592,9 @@ impl CityModel {
 }
 
 impl Model for CityModel {
-    fn init(&mut self, _ctx: &mut Context) {
-        self.reset();
+    fn init(&mut self, ctx: &mut Context) {
+        ctx.events.subscribe::<TimerEvent>("model");
+        self.reset(ctx);
     }
 
     fn handle_input(&mut self, ctx: &mut Context, _dt: f32) {
@@ -615,3 +631,15 @@ impl Model for CityModel {
         self
     }
 }
+
+impl SaveModel for CityModel {
+    fn save_model(&self) -> Result<Vec<u8>, String> {
+        bincode::serialize(self).map_err(|e| e.to_string())
+    }
+
+    fn load_model(&mut self, ctx: &mut Context, data: &[u8]) -> Result<(), String> {
+        *self = bincode::deserialize(data).map_err(|e| e.to_string())?;
+        ctx.events.emit(CityEvent::RedrawGrid);
+        Ok(())
+    }
+}
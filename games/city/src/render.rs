@@ -1,11 +1,9 @@
-use crate::model::{get_xy, CityModel, CityState, CELLH, CELLW, LEVELUP_TIME, NCOL, NROW};
+use crate::model::{get_xy, CityEvent, CityModel, CityState, CELLH, CELLW, LEVELUP_TIME, NCOL, NROW};
 use log::info;
 use rust_pixel::{
-    GAME_FRAME,
     asset::AssetType,
     asset2sprite,
     context::Context,
-    event::{event_check, event_register, timer_percent, timer_rstage},
     game::{Model, Render},
     render::sprite::{Sprites, Sprite},
     render::style::{Color, Style},
@@ -75,9 +73,6 @@ impl CityRender {
             "msg",
         );
 
-        //注册重绘事件
-        event_register("redraw_grid", "draw_grid");
-
         Self {
             panel: t,
             main_scene: s,
@@ -90,15 +85,18 @@ impl CityRender {
         match ss {
             //飞行合并
             CityState::MergeMovie => {
-                self.draw_moving(ctx, data, ss, timer_percent("merge"));
+                let p = ctx.timers.percent("merge");
+                self.draw_moving(ctx, data, ss, p);
             }
             //数字升级
             CityState::LevelUpMovie => {
-                self.draw_moving(ctx, data, ss, timer_percent("levelup"));
+                let p = ctx.timers.percent("levelup");
+                self.draw_moving(ctx, data, ss, p);
             }
             //掉落补齐
             CityState::DropMovie => {
-                self.draw_moving(ctx, data, ss, timer_percent("drop"));
+                let p = ctx.timers.percent("drop");
+                self.draw_moving(ctx, data, ss, p);
             }
             _ => {
                 //当model.ready2t时工作
@@ -167,8 +165,8 @@ impl CityRender {
                 //升级变换数字
                 if state == CityState::LevelUpMovie {
                     let l;
-                    let s = timer_rstage("levelup") as f32;
-                    let step = GAME_FRAME as f32 * LEVELUP_TIME;
+                    let s = ctx.timers.elapsed("levelup");
+                    let step = LEVELUP_TIME;
                     if d.levelup.from == 30 {
                         l = d.levelup.from + ((s / step).floor() * 30.0) as i16;
                     } else {
@@ -264,10 +262,13 @@ impl Render for CityRender {
         self.panel.init(ctx);
         let l = self.main_scene.get_by_tag("back");
         asset2sprite!(l, ctx, &format!("back.txt"));
+        //订阅重绘事件
+        ctx.events.subscribe::<CityEvent>("render");
     }
 
     fn handle_event<G: Model>(&mut self, ctx: &mut Context, data: &mut G, _dt: f32) {
-        if event_check("redraw_grid", "draw_grid") {
+        let es = ctx.events.drain::<CityEvent>("render");
+        if es.contains(&CityEvent::RedrawGrid) {
             self.draw_grid(ctx, data);
         }
     }
@@ -2,7 +2,7 @@ use gin_rummy_lib::cards::*;
 use rust_pixel::event::Event;
 use log::info;
 use std::any::Any;
-use rust_pixel::{context::Context, event::event_emit, game::Model, util::Rand};
+use rust_pixel::{context::Context, game::Model, util::Rand};
 
 pub const CARDW: usize = 7;
 #[cfg(any(feature = "sdl", target_arch = "wasm32"))]
@@ -16,6 +16,11 @@ pub const CARDH: usize = 5;
 //     OverBorder,
 // }
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum GinRummyEvent {
+    RedrawTile,
+}
+
 pub struct GinRummyModel {
     pub rand: Rand,
     pub cards_a: GinRummyCards,
@@ -45,7 +50,7 @@ impl GinRummyModel {
 }
 
 impl Model for GinRummyModel {
-    fn init(&mut self, _context: &mut Context) {
+    fn init(&mut self, context: &mut Context) {
         self.rand.srand_now();
         for _i in 0..500000000 {
             self.shuffle_tiles();
@@ -82,7 +87,7 @@ impl Model for GinRummyModel {
                 break;
             }
         }
-        event_emit("GinRummy.RedrawTile");
+        context.events.emit(GinRummyEvent::RedrawTile);
     }
 
     fn handle_input(&mut self, context: &mut Context, _dt: f32) {
@@ -1,10 +1,9 @@
-use crate::model::{GinRummyModel, CARDH, CARDW};
+use crate::model::{GinRummyEvent, GinRummyModel, CARDH, CARDW};
 // use log::info;
 use rust_pixel::{
     asset::AssetType,
     asset2sprite,
     context::Context,
-    event::{event_check, event_register},
     game::{Model, Render},
     render::sprite::{Sprite, Sprites},
     render::style::{Color, Style},
@@ -38,8 +37,6 @@ impl GinRummyRender {
         let msgblack = Sprite::new(0 + adj, 6 + CARDH as u16, 40, 1);
         s.add_by_tag(msgblack, "msgblack");
 
-        event_register("GinRummy.RedrawTile", "draw_tile");
-
         Self {
             panel: t,
             sprites: s,
@@ -109,10 +106,12 @@ impl Render for GinRummyRender {
             .adapter
             .init(65, 25, 1.0, 1.0, "gin_rummy".to_string());
         self.panel.init(context);
+        context.events.subscribe::<GinRummyEvent>("render");
     }
 
     fn handle_event<G: Model>(&mut self, context: &mut Context, data: &mut G, _dt: f32) {
-        if event_check("GinRummy.RedrawTile", "draw_tile") {
+        let es = context.events.drain::<GinRummyEvent>("render");
+        if es.contains(&GinRummyEvent::RedrawTile) {
             self.draw_tile(context, data);
         }
     }
@@ -2,7 +2,7 @@ use rust_pixel::event::Event;
 use log::info;
 use std::any::Any;
 use texas_lib::*;
-use rust_pixel::{context::Context, event::event_emit, game::Model, util::Rand};
+use rust_pixel::{context::Context, game::Model, util::Rand};
 
 pub const CARDW: usize = 7;
 #[cfg(any(feature = "sdl", target_arch = "wasm32"))]
@@ -16,6 +16,11 @@ pub const CARDH: usize = 5;
 //     OverBorder,
 // }
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum RedBlackEvent {
+    RedrawTile,
+}
+
 pub struct RedBlackModel {
     pub rand: Rand,
     pub texas_cards_red: TexasCards,
@@ -45,14 +50,14 @@ impl RedBlackModel {
 }
 
 impl Model for RedBlackModel {
-    fn init(&mut self, _context: &mut Context) {
+    fn init(&mut self, context: &mut Context) {
         self.rand.srand_now();
         self.shuffle_tiles();
         self.texas_cards_red.assign(&self.pool[0..5]).unwrap();
         self.texas_cards_black.assign(&self.pool[5..10]).unwrap();
         info!("red:{}", self.texas_cards_red);
         info!("black:{}", self.texas_cards_black);
-        event_emit("RedBlack.RedrawTile");
+        context.events.emit(RedBlackEvent::RedrawTile);
     }
 
     fn handle_input(&mut self, context: &mut Context, _dt: f32) {
@@ -1,10 +1,9 @@
-use crate::model::{RedBlackModel, CARDH, CARDW};
+use crate::model::{RedBlackEvent, RedBlackModel, CARDH, CARDW};
 // use log::info;
 use rust_pixel::{
     asset::AssetType,
     asset2sprite,
     context::Context,
-    event::{event_check, event_register},
     game::{Model, Render},
     render::panel::Panel,
     render::sprite::{Sprite, Sprites},
@@ -37,8 +36,6 @@ impl RedBlackRender {
         let msgblack = Sprite::new(40 + adj, 14, 40, 1);
         s.add_by_tag(msgblack, "msgblack");
 
-        event_register("RedBlack.RedrawTile", "draw_tile");
-
         Self {
             panel: t,
             sprites: s,
@@ -85,12 +82,14 @@ impl Render for RedBlackRender {
             .adapter
             .init(82, 20, 1.2, 1.2, "redblack".to_string());
         self.panel.init(context);
+        context.events.subscribe::<RedBlackEvent>("render");
         let gb = self.sprites.get_by_tag("back");
         asset2sprite!(gb, context, "back.txt");
     }
 
     fn handle_event<G: Model>(&mut self, context: &mut Context, data: &mut G, _dt: f32) {
-        if event_check("RedBlack.RedrawTile", "draw_tile") {
+        let es = context.events.drain::<RedBlackEvent>("render");
+        if es.contains(&RedBlackEvent::RedrawTile) {
             self.draw_tile(context, data);
         }
     }
@@ -1,10 +1,9 @@
 use log::debug;
 use rand::prelude::*;
 use std::any::Any;
-use rust_pixel::event::{Event, KeyCode};
+use rust_pixel::event::ActionMap;
 use rust_pixel::{
     context::Context,
-    event::event_emit,
     game::Model,
     util::{Dir, Point},
 };
@@ -12,13 +11,26 @@ use rust_pixel::{
 pub const SNAKEW: usize = 60;
 pub const SNAKEH: usize = 36;
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum SnakeEvent {
+    RedrawGrid,
+}
+
 enum SnakeState {
     Normal,
     OverSelf,
     OverBorder,
 }
 
+const SNAKE_KEYS: &str = "
+up = w, Up
+left = a, Left
+down = s, Down
+right = d, Right
+";
+
 pub struct SnakeModel {
+    pub actions: ActionMap,
     pub grid: [[i16; SNAKEW]; SNAKEH],
     pub seed: Point,
     pub body: Vec<Point>,
@@ -29,6 +41,7 @@ pub struct SnakeModel {
 impl SnakeModel {
     pub fn new() -> Self {
         Self {
+            actions: ActionMap::from_config(SNAKE_KEYS).unwrap(),
             grid: [[0i16; SNAKEW]; SNAKEH],
             seed: Point { x: 0, y: 0 },
             body: vec![],
@@ -92,7 +105,7 @@ impl SnakeModel {
         cy = self.body[0].y as i16 + dy;
         if cx >= SNAKEW as i16 || cy >= SNAKEH as i16 || cx < 0 || cy < 0 {
             context.state = SnakeState::OverBorder as u8;
-            event_emit("Snake.RedrawGrid");
+            context.events.emit(SnakeEvent::RedrawGrid);
             return;
         }
         if self.grid[cy as usize][cx as usize] == 10000 {
@@ -115,7 +128,7 @@ impl SnakeModel {
         } else {
             if self.grid[cy as usize][cx as usize] != 0 {
                 context.state = SnakeState::OverSelf as u8;
-                event_emit("Snake.RedrawGrid");
+                context.events.emit(SnakeEvent::RedrawGrid);
                 return;
             }
             self.body.pop();
@@ -129,7 +142,7 @@ impl SnakeModel {
         );
         self.dir = d;
         self.make_grid();
-        event_emit("Snake.RedrawGrid");
+        context.events.emit(SnakeEvent::RedrawGrid);
     }
 }
 
@@ -147,30 +160,24 @@ impl Model for SnakeModel {
         self.dir = Dir::Down;
         context.input_events.clear();
         context.state = SnakeState::Normal as u8;
-        event_emit("Snake.RedrawGrid");
+        context.events.emit(SnakeEvent::RedrawGrid);
     }
 
-    fn handle_input(&mut self, context: &mut Context, _dt: f32) {
-        let es = context.input_events.clone();
-        for e in &es {
-            match e {
-                Event::Key(key) => {
-                    let mut d: Option<Dir> = None;
-                    match key.code {
-                        KeyCode::Char('w') => d = Some(Dir::Up),
-                        KeyCode::Char('a') => d = Some(Dir::Left),
-                        KeyCode::Char('s') => d = Some(Dir::Down),
-                        KeyCode::Char('d') => d = Some(Dir::Right),
-                        _ => {}
-                    }
-                    if d != None {
-                        self.act(d.unwrap(), context);
-                    }
-                }
-                _ => {}
+    fn handle_input(&mut self, context: &mut Context, dt: f32) {
+        self.actions.update(&context.input_events, dt);
+        context.input_events.clear();
+        let dirs = [
+            ("up", Dir::Up),
+            ("left", Dir::Left),
+            ("down", Dir::Down),
+            ("right", Dir::Right),
+        ];
+        // every press of the frame moves, not just the first
+        for (a, d) in dirs {
+            for _ in 0..self.actions.triggers(a) {
+                self.act(d, context);
             }
         }
-        context.input_events.clear();
     }
 
     fn handle_auto(&mut self, context: &mut Context, dt: f32) {
@@ -1,4 +1,4 @@
-use crate::model::{SnakeModel, SNAKEH, SNAKEW};
+use crate::model::{SnakeEvent, SnakeModel, SNAKEH, SNAKEW};
 use log::info;
 #[cfg(any(feature = "sdl", target_arch = "wasm32"))]
 use rust_pixel::render::cell::cellsym;
@@ -6,7 +6,7 @@ use rust_pixel::{
     asset::AssetType,
     asset2sprite,
     context::Context,
-    event::{event_check, event_register, timer_fire, timer_register},
+    event::TimerEvent,
     game::{Model, Render},
     render::panel::Panel,
     render::sprite::{Sprite, Sprites},
@@ -146,10 +146,6 @@ impl SnakeRender {
             "SNAKE-MSG",
         );
 
-        event_register("Snake.RedrawGrid", "draw_grid");
-        timer_register("Snake.TestTimer", 0.1, "test_timer");
-        timer_fire("Snake.TestTimer", 8u8);
-
         Self {
             panel: t,
             // #[cfg(feature = "sdl")]
@@ -238,16 +234,22 @@ impl Render for SnakeRender {
             "snake".to_string(),
         );
         self.panel.init(context);
+        context.events.subscribe::<SnakeEvent>("render");
+        context.events.subscribe::<TimerEvent>("render");
+        context.timers.register("Snake.TestTimer", 0.1);
+        context.timers.fire("Snake.TestTimer", 8u8);
     }
 
     fn handle_event<G: Model>(&mut self, context: &mut Context, data: &mut G, _dt: f32) {
-        if event_check("Snake.RedrawGrid", "draw_grid") {
+        let es = context.events.drain::<SnakeEvent>("render");
+        if es.contains(&SnakeEvent::RedrawGrid) {
             self.draw_grid(context, data);
         }
     }
 
     fn handle_timer<G: Model>(&mut self, context: &mut Context, _model: &mut G, _dt: f32) {
-        if event_check("Snake.TestTimer", "test_timer") {
+        let es = context.events.drain::<TimerEvent>("render");
+        if es.contains(&TimerEvent("Snake.TestTimer".to_string())) {
             let ml = self.main_scene.get_by_tag("SNAKE-MSG");
             ml.content.set_str(
                 (context.stage / 6) as u16 % SNAKEW as u16,
@@ -255,7 +257,7 @@ impl Render for SnakeRender {
                 "snake",
                 Style::default().fg(Color::Yellow),
             );
-            timer_fire("Snake.TestTimer", 8u8);
+            context.timers.fire("Snake.TestTimer", 8u8);
         }
     }
 
@@ -5,6 +5,7 @@ use crate::{
     cell::{Move, MoveRet, TetrisCell, TetrisCore},
     constant::*,
 };
+use rust_pixel::event::Timers;
 //use std::any::Any;
 use std::cmp::min;
 //use tge::{
@@ -31,6 +32,8 @@ pub struct TetrisAi {
     pub work2idx: i64,
     pub ms_scan: Vec<AiDat>,
     pub mode: usize,
+    // timers of the simulated moves, so they never touch the game timers
+    pub timers: Timers,
 }
 
 pub struct AiScore {
@@ -100,6 +103,7 @@ impl TetrisAi {
             work2idx: -1,
             ms_scan: vec![],
             mode: 0,
+            timers: Timers::new(),
         }
     }
 
@@ -238,7 +242,7 @@ impl TetrisAi {
             self.tact_queue = bq.clone();
             if s2 != 0 {
                 self.tact_queue.push('S');
-                tc.save_block(blocks, true);
+                tc.save_block(&mut self.timers, blocks, true);
             }
             let b2 = tc.core;
             let bq1 = self.tact_queue.clone();
@@ -250,7 +254,7 @@ impl TetrisAi {
                 //旋转
                 for _n in 0..nz {
                     self.tact_queue.push('T');
-                    tc.move_block(Move::TurnCw, true);
+                    tc.move_block(&mut self.timers, Move::TurnCw, true);
                 }
 
                 let b3 = tc.core;
@@ -268,7 +272,9 @@ impl TetrisAi {
                             }
                         }
                         1 => {
-                            while tc.move_block(Move::Left, true) != MoveRet::ReachBorder {
+                            while tc.move_block(&mut self.timers, Move::Left, true)
+                                != MoveRet::ReachBorder
+                            {
                                 self.tact_queue.push('L');
                                 if fs {
                                     self.ai_f2(blocks, tc, cx, cy, cf, combo, scan);
@@ -278,7 +284,9 @@ impl TetrisAi {
                             }
                         }
                         2 => {
-                            while tc.move_block(Move::Right, true) != MoveRet::ReachBorder {
+                            while tc.move_block(&mut self.timers, Move::Right, true)
+                                != MoveRet::ReachBorder
+                            {
                                 self.tact_queue.push('R');
                                 if fs {
                                     self.ai_f2(blocks, tc, cx, cy, cf, combo, scan);
@@ -311,14 +319,14 @@ impl TetrisAi {
 
         //直接下落
         //fall
-        while tg.move_block(Move::DropDown, true) != MoveRet::ReachBottom {}
+        while tg.move_block(&mut self.timers, Move::DropDown, true) != MoveRet::ReachBottom {}
         self.tact_queue.push('W');
         let ccombo = tg.core.combo;
         let cf = tg.core.full_row_count;
-        tg.clear_row(true);
+        tg.clear_row(&mut self.timers, true);
         let cx = tg.core.cur_x;
         let cy = tg.core.cur_y;
-        tg.next_block(blocks, true, false);
+        tg.next_block(&mut self.timers, blocks, true, false);
         self.tact_queue.push('N');
 
         let s = self.get_cell_score(&tg.core, cx, cy, cf, 0, combo);
@@ -365,14 +373,14 @@ impl TetrisAi {
                 mx = tg.core.col_top[xx as usize];
             }
         }
-        tg.move_block(Move::Set, true);
+        tg.move_block(&mut self.timers, Move::Set, true);
 
         //直接下落
         //fall
-        while tg.move_block(Move::DropDown, true) != MoveRet::ReachBottom {}
+        while tg.move_block(&mut self.timers, Move::DropDown, true) != MoveRet::ReachBottom {}
         self.tact_queue.push('W');
         let nf = tg.core.full_row_count;
-        tg.clear_row(true);
+        tg.clear_row(&mut self.timers, true);
         let s = self.get_cell_score(&tg.core, cx, cy, cf, nf, combo);
         if s > self.max_score {
             self.mact_queue = self.tact_queue.clone();
@@ -401,7 +409,7 @@ impl TetrisAi {
             //遍历第一块获取分数和ms_scan列表
             //search the score and ms_scan list of the 1st block
             self.tact_queue = String::from("");
-            tg.clear_row(true);
+            tg.clear_row(&mut self.timers, true);
             self.get_mode(&tg.core);
             self.ms_scan.clear();
             self.ai_f1(blocks, tg, 0, 0, 0, 0, true, false, true);
@@ -1,10 +1,11 @@
 use crate::constant::*;
 //use crossterm::event::{Event, KeyCode};
 use log::debug;
+use serde::{Deserialize, Serialize};
 //use rand::prelude::*;
 //use std::any::Any;
 use rust_pixel::{
-    event::{event_check, event_emit, timer_cancel, timer_fire, timer_register, timer_stage},
+    event::Timers,
     //game::{Context, Model, Render},
     util::{Point, Rand},
 };
@@ -36,7 +37,7 @@ pub enum MoveRet {
     ReachBottom, //到底 reach bottom
 }
 
-#[derive(Debug, Default, Copy, Clone)]
+#[derive(Debug, Default, Copy, Clone, Serialize, Deserialize)]
 pub struct TetrisCore {
     pub grid: [[u8; (HENG + 4) as usize]; (ZONG + 2) as usize],
     pub col_top: [i16; HENG as usize],
@@ -85,7 +86,7 @@ impl TetrisCore {
     }
 }
 
-#[derive(Default, Copy, Clone)]
+#[derive(Default, Copy, Clone, Serialize, Deserialize)]
 pub struct TetrisStat {
     pub combo_total: u8,
     pub combo_max: u8,
@@ -106,7 +107,7 @@ impl TetrisStat {
     }
 }
 
-#[derive(Default, Copy, Clone)]
+#[derive(Default, Copy, Clone, Serialize, Deserialize)]
 pub struct TetrisCell {
     pub index: u8,
     pub core: TetrisCore,
@@ -114,6 +115,12 @@ pub struct TetrisCell {
     pub active: bool,
     pub need_draw: bool,
     pub need_stable: bool,
+    // the next or hold block of player cell 0 changed,
+    // cleared by the model after it tells the render
+    #[serde(skip)]
+    pub redraw_next: bool,
+    #[serde(skip)]
+    pub redraw_hold: bool,
 }
 
 impl TetrisCell {
@@ -125,10 +132,12 @@ impl TetrisCell {
             active: true,
             need_draw: false,
             need_stable: false,
+            redraw_next: false,
+            redraw_hold: false,
         }
     }
 
-    pub fn reset(&mut self, blocks: &[i8]) {
+    pub fn reset(&mut self, timers: &mut Timers, blocks: &[i8]) {
         //初始化各种变量
         //init
         for i in 0..(ZONG + 2) as usize {
@@ -151,33 +160,36 @@ impl TetrisCell {
         self.core.game_over = false;
         self.core.block_index = 0;
 
-        self.make_shadow();
+        self.make_shadow(timers);
 
-        timer_register(&format!("next-block{}", self.index), 0.8, "_");
-        timer_register(&format!("pre-stable{}", self.index), 0.8, "_");
-        timer_register(&format!("clear-row{}", self.index), 0.3, "_");
-        timer_register(&format!("game-over{}", self.index), 0.12, "_");
-        timer_register(&format!("fall{}", self.index), 0.1, "_");
-        timer_register(&format!("combo{}", self.index), 0.8, "_");
-        timer_register(&format!("attack{}", self.index), 0.8, "_");
+        timers.register(&format!("next-block{}", self.index), 0.8);
+        timers.register(&format!("pre-stable{}", self.index), 0.8);
+        timers.register(&format!("clear-row{}", self.index), 0.3);
+        timers.register(&format!("game-over{}", self.index), 0.12);
+        timers.register(&format!("fall{}", self.index), 0.1);
+        timers.register(&format!("combo{}", self.index), 0.8);
+        timers.register(&format!("attack{}", self.index), 0.8);
     }
 
-    pub fn timer_process(&mut self, blocks: &[i8]) {
-        if event_check(&format!("pre-stable{}", self.index), "_") {
+    // fired holds the names of the timers fired in this step
+    pub fn timer_process(&mut self, timers: &mut Timers, fired: &[String], blocks: &[i8]) {
+        let index = self.index;
+        let fired = |name: &str| fired.contains(&format!("{}{}", name, index));
+        if fired("pre-stable") {
             self.need_stable = true;
         }
-        if event_check(&format!("clear-row{}", self.index), "_") {
-            self.clear_row(false);
+        if fired("clear-row") {
+            self.clear_row(timers, false);
         }
-        if event_check(&format!("game-over{}", self.index), "_") {
+        if fired("game-over") {
             self.core.game_over = true;
         }
-        if event_check(&format!("fall{}", self.index), "_") {
-            self.fall(blocks);
+        if fired("fall") {
+            self.fall(timers, blocks);
         }
     }
 
-    pub fn next_block(&mut self, blocks: &[i8], ai: bool, save: bool) {
+    pub fn next_block(&mut self, timers: &mut Timers, blocks: &[i8], ai: bool, save: bool) {
         if !ai {
             self.stat.add_score(10);
         }
@@ -185,16 +197,16 @@ impl TetrisCell {
         self.core.block_index += 1;
         self.core.cur_block = self.core.next_block;
         if !save {
-            timer_fire(&format!("next-block{}", self.index), 0);
+            timers.fire(&format!("next-block{}", self.index), 0);
         }
         self.core.cur_x = 5;
         self.core.cur_y = 0;
         self.core.cur_z = 0;
-        self.move_block(Move::Set, ai);
+        self.move_block(timers, Move::Set, ai);
         self.core.next_block = blocks[((self.core.block_index + 1) % BLKQUEUE) as usize];
 
         if self.index == 0 {
-            event_emit("Tetris.RedrawNext");
+            self.redraw_next = true;
         }
 
         /*if  !ai && self.index==0{
@@ -204,10 +216,10 @@ impl TetrisCell {
 
     //暂存块,每次确认下落后才能再次存(save_lock)
     //save the block, every time needs to confirm fall to save again
-    pub fn save_block(&mut self, blocks: &[i8], ai: bool) {
+    pub fn save_block(&mut self, timers: &mut Timers, blocks: &[i8], ai: bool) {
         if !self.core.save_lock {
             self.core.save_lock = true;
-            self.move_block(Move::Clear, ai);
+            self.move_block(timers, Move::Clear, ai);
             if self.core.save_block >= 0 {
                 let blktmp = self.core.cur_block;
                 self.core.cur_block = self.core.save_block;
@@ -215,23 +227,23 @@ impl TetrisCell {
                 self.core.cur_x = 5;
                 self.core.cur_y = 0;
                 self.core.cur_z = 0;
-                self.move_block(Move::Set, ai);
+                self.move_block(timers, Move::Set, ai);
             } else {
                 self.core.save_block = self.core.cur_block;
-                self.next_block(blocks, ai, false);
+                self.next_block(timers, blocks, ai, false);
             }
             if self.index == 0 {
-                event_emit("Tetris.RedrawHold");
+                self.redraw_hold = true;
             }
             //触发保存块动画
             //this.mtimer.save_block = 10;
         }
     }
 
-    pub fn clear_row(&mut self, ai: bool) {
+    pub fn clear_row(&mut self, timers: &mut Timers, ai: bool) {
         if !ai {
-            if timer_stage(&format!("game-over{}", self.index)) != 0 {
-                timer_cancel(&format!("game-over{}", self.index), false);
+            if timers.stage(&format!("game-over{}", self.index)) != 0 {
+                timers.cancel(&format!("game-over{}", self.index), false);
                 self.core.game_over = false;
             }
         }
@@ -297,22 +309,22 @@ impl TetrisCell {
 
     //直落
     //fall
-    pub fn fall(&mut self, blocks: &[i8]) {
-        while self.move_block(Move::DropDown, false) != MoveRet::ReachBottom {
+    pub fn fall(&mut self, timers: &mut Timers, blocks: &[i8]) {
+        while self.move_block(timers, Move::DropDown, false) != MoveRet::ReachBottom {
             if self.core.game_over {
                 break;
             }
         }
-        self.next_block(blocks, false, false);
-        self.make_shadow();
+        self.next_block(timers, blocks, false, false);
+        self.make_shadow(timers);
     }
 
     //用于预先绘制下落到底部的虚影，用于更好的瞄准
     //draw the shadow while falling down, for better aiming
-    pub fn make_shadow(&mut self) {
+    pub fn make_shadow(&mut self, timers: &mut Timers) {
         let tmp = self.core;
         loop {
-            if self.move_block(Move::DropDown, true) == MoveRet::ReachBottom {
+            if self.move_block(timers, Move::DropDown, true) == MoveRet::ReachBottom {
                 break;
             }
         }
@@ -351,13 +363,22 @@ impl TetrisCell {
         l
     }
 
-    fn reach_bottom(&mut self, dir: Move, ai: bool, blk: i8, cx: i8, cy: i8, z: i8) -> MoveRet {
+    fn reach_bottom(
+        &mut self,
+        timers: &mut Timers,
+        dir: Move,
+        ai: bool,
+        cx: i8,
+        cy: i8,
+        z: i8,
+    ) -> MoveRet {
+        let blk = self.core.cur_block;
         if dir == Move::Down {
             //普通下落（非直落）还没粘住的情况
             //normal fall
             if !self.need_stable {
-                if timer_stage(&format!("pre-stable{}", self.index)) == 0 {
-                    timer_fire(&format!("pre-stable{}", self.index), 0u8);
+                if timers.stage(&format!("pre-stable{}", self.index)) == 0 {
+                    timers.fire(&format!("pre-stable{}", self.index), 0u8);
                 }
                 for (m, n, my, mx) in self.inner_rect4x4(cy, cx) {
                     if self.get_md(blk, z, m * 4 + n) != 0 {
@@ -418,8 +439,8 @@ impl TetrisCell {
         //if the row is full, set full_rows_count
         if self.core.full_row_count > 0 {
             if !ai {
-                if timer_stage(&format!("game-over{}", self.index)) != 0 {
-                    timer_cancel(&format!("game-over{}", self.index), false);
+                if timers.stage(&format!("game-over{}", self.index)) != 0 {
+                    timers.cancel(&format!("game-over{}", self.index), false);
                     self.core.game_over = false;
                 }
             }
@@ -433,7 +454,7 @@ impl TetrisCell {
                     }
                     self.stat.combo_current = self.core.combo;
                     self.core.attack[0] += 1; // 如果连击数大于等于3   再给别人加一行
-                    timer_fire(&format!("combo{}", self.index), self.core.combo);
+                    timers.fire(&format!("combo{}", self.index), self.core.combo);
                     self.stat.add_score(self.core.combo as i64 * 100 as i64);
                 }
                 self.core.attack[1] = self.core.block_index;
@@ -445,7 +466,7 @@ impl TetrisCell {
                 for f in 0..self.core.full_row_count {
                     fv.push(self.core.full_rows[f as usize]);
                 }
-                timer_fire(&format!("clear-row{}", self.index), fv);
+                timers.fire(&format!("clear-row{}", self.index), fv);
             }
         } else {
             self.core.combo = 0;
@@ -457,20 +478,27 @@ impl TetrisCell {
         MoveRet::ReachBottom
     }
 
-    fn update_block_xyz(&mut self, dir: Move, cx: i8, cy: i8, cz: i8) -> (i8, i8, i8) {
+    fn update_block_xyz(
+        &mut self,
+        timers: &mut Timers,
+        dir: Move,
+        cx: i8,
+        cy: i8,
+        cz: i8,
+    ) -> (i8, i8, i8) {
         let (mut x, mut y, mut z) = (0i8, 0i8, 0i8);
         match dir {
             Move::TurnCw => {
                 x = cx;
                 y = cy;
                 z = (cz + 5) % 4;
-                timer_cancel(&format!("pre-stable{}", self.index), true);
+                timers.cancel(&format!("pre-stable{}", self.index), true);
             }
             Move::TurnCcw => {
                 x = cx;
                 y = cy;
                 z = (cz + 3) % 4;
-                timer_cancel(&format!("pre-stable{}", self.index), true);
+                timers.cancel(&format!("pre-stable{}", self.index), true);
             }
             Move::Down | Move::DropDown => {
                 x = cx;
@@ -481,13 +509,13 @@ impl TetrisCell {
                 x = cx - 1;
                 y = cy;
                 z = cz;
-                timer_cancel(&format!("pre-stable{}", self.index), true);
+                timers.cancel(&format!("pre-stable{}", self.index), true);
             }
             Move::Right => {
                 x = cx + 1;
                 y = cy;
                 z = cz;
-                timer_cancel(&format!("pre-stable{}", self.index), true);
+                timers.cancel(&format!("pre-stable{}", self.index), true);
             }
             Move::Set | Move::Clear => {
                 x = cx;
@@ -499,7 +527,7 @@ impl TetrisCell {
         (x, y, z)
     }
 
-    pub fn move_block(&mut self, dir: Move, ai: bool) -> MoveRet {
+    pub fn move_block(&mut self, timers: &mut Timers, dir: Move, ai: bool) -> MoveRet {
         if self.core.game_over {
             if dir == Move::Left || dir == Move::Right {
                 return MoveRet::ReachBorder;
@@ -516,7 +544,7 @@ impl TetrisCell {
         let cx = self.core.cur_x;
         let cy = self.core.cur_y;
         let cz = self.core.cur_z;
-        let (x, y, z) = self.update_block_xyz(dir, cx, cy, cz);
+        let (x, y, z) = self.update_block_xyz(timers, dir, cx, cy, cz);
 
         //不稳定块置0,100以上为已经下落稳定的块
         for (_i, _j, iy, ix) in self.inner_rect4x4(cy, cx) {
@@ -535,7 +563,7 @@ impl TetrisCell {
             if self.get_gd(iy, ix) != 0 && self.get_md(blk, z, i * 4 + j) != 0 {
                 match dir {
                     Move::Down | Move::DropDown => {
-                        return self.reach_bottom(dir, ai, blk, cx, cy, z);
+                        return self.reach_bottom(timers, dir, ai, cx, cy, z);
                     }
                     Move::Left | Move::Right | Move::TurnCw | Move::TurnCcw => {
                         for (i, j, iy, ix) in self.inner_rect4x4(cy, cx) {
@@ -549,7 +577,7 @@ impl TetrisCell {
                     }
                     _ => {
                         if dir == Move::Set && !ai {
-                            timer_fire(&format!("game-over{}", self.index), 0u8);
+                            timers.fire(&format!("game-over{}", self.index), 0u8);
                         }
                         return MoveRet::Normal;
                     }
@@ -568,7 +596,7 @@ impl TetrisCell {
         self.core.cur_y = y;
         self.core.cur_z = z;
         if !ai {
-            self.make_shadow();
+            self.make_shadow(timers);
         }
 
         MoveRet::Normal
@@ -576,20 +604,20 @@ impl TetrisCell {
 
     //旋转动作的辅助函数
     //helping function for doing rotation
-    pub fn help_turn(&mut self, d: Move, cmd: &str) -> bool {
+    pub fn help_turn(&mut self, timers: &mut Timers, d: Move, cmd: &str) -> bool {
         let tcore = self.core.clone();
 
         for c in cmd.bytes() {
             if c == b'L' {
-                self.move_block(Move::Left, false);
+                self.move_block(timers, Move::Left, false);
             }
             if c == b'R' {
-                self.move_block(Move::Right, false);
+                self.move_block(timers, Move::Right, false);
             }
         }
-        let mret = self.move_block(d, false);
+        let mret = self.move_block(timers, d, false);
         if mret == MoveRet::Normal {
-            self.make_shadow();
+            self.make_shadow(timers);
             return true;
         } else {
             self.core = tcore;
@@ -597,19 +625,19 @@ impl TetrisCell {
         return false;
     }
 
-    pub fn attacked(&mut self, tr: &mut Rand, line: u16, space_seed: u16) {
+    pub fn attacked(&mut self, timers: &mut Timers, tr: &mut Rand, line: u16, space_seed: u16) {
         let mut flowflag = 0u8;
 
         if self.core.game_over || line <= 0 {
             return;
         }
 
-        if timer_stage(&format!("clear-row{}", self.index)) != 0 {
-            timer_cancel(&format!("clear-row{}", self.index), false);
+        if timers.stage(&format!("clear-row{}", self.index)) != 0 {
+            timers.cancel(&format!("clear-row{}", self.index), false);
         }
 
-        if timer_stage(&format!("fall{}", self.index)) != 0 {
-            timer_cancel(&format!("fall{}", self.index), false);
+        if timers.stage(&format!("fall{}", self.index)) != 0 {
+            timers.cancel(&format!("fall{}", self.index), false);
         }
 
         tr.srand(space_seed as u64);
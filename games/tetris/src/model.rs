@@ -1,4 +1,4 @@
-use rust_pixel::event::{Event, KeyCode};
+use rust_pixel::event::ActionMap;
 use log::debug;
 //use rand::prelude::*;
 use tetris_lib::{
@@ -6,15 +6,23 @@ use tetris_lib::{
     cell::{Move, MoveRet, TetrisCell},
     constant::*,
 };
+use serde::{Deserialize, Serialize};
 use std::any::Any;
 use rust_pixel::{
     context::Context,
-    event::{event_emit, timer_fire},
-    game::Model,
+    event::TimerEvent,
+    game::{save::SaveModel, Model},
     //timer_cancel, timer_fire, timer_register,
     util::Rand,
 };
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum TetrisEvent {
+    RedrawGrid,
+    RedrawNext,
+    RedrawHold,
+}
+
 //https://harddrop.com/wiki/T-Spin_Triple_Setups
 //https://katyscode.wordpress.com/2012/10/13/tetris-aside-coding-for-t-spins/
 
@@ -24,7 +32,22 @@ use rust_pixel::{
     OverBorder,
 }*/
 
+// default key bindings,
+// players can override them by writing games/tetris/assets/keys.cfg
+// in the format of ActionMap::to_config
+const TETRIS_KEYS: &str = "
+left = j, Left
+right = l, Right
+down = k, Down
+turn_cw = i, Up
+turn_ccw = o
+drop = Space
+save = s
+restart = r
+";
+
 pub struct TetrisModel {
+    pub actions: ActionMap,
     pub cells: [TetrisCell; 2],
     pub block_queue: [i8; BLKQUEUE as usize],
     pub trand: Rand,
@@ -33,10 +56,19 @@ pub struct TetrisModel {
     pub timeout_ai: f32,
 }
 
+// saved part of TetrisModel, ai state is rebuilt while playing
+#[derive(Serialize, Deserialize)]
+struct TetrisSave {
+    cells: [TetrisCell; 2],
+    block_queue: Vec<i8>,
+    trand: Rand,
+}
+
 impl TetrisModel {
     pub fn new() -> Self {
         let c: [TetrisCell; 2] = [TetrisCell::new(0), TetrisCell::new(1)];
         Self {
+            actions: ActionMap::from_config(TETRIS_KEYS).unwrap(),
             cells: c,
             block_queue: [0i8; BLKQUEUE as usize],
             trand: Rand::new(),
@@ -46,116 +78,150 @@ impl TetrisModel {
         }
     }
 
-    fn random_block_queue(&mut self, seed: u32) {
-        self.trand.srand(seed as u64);
-        self.trand.srand_now();
+    fn random_block_queue(&mut self, seed: u64) {
+        self.trand.srand(seed);
         for i in 0..BLKQUEUE {
             self.block_queue[i as usize] = (self.trand.rand() % 7) as i8;
         }
     }
 
-    fn reset(&mut self) {
-        self.random_block_queue(0);
-        self.cells[0].reset(&self.block_queue);
-        self.cells[1].reset(&self.block_queue);
-        event_emit("Tetris.RedrawGrid");
+    // block queue is seeded from context.rand,
+    // so a recorded session replays the same blocks
+    fn reset(&mut self, context: &mut Context) {
+        let seed = context.rand.rand() as u64;
+        self.random_block_queue(seed);
+        self.cells[0].reset(&mut context.timers, &self.block_queue);
+        self.cells[1].reset(&mut context.timers, &self.block_queue);
+        context.events.emit(TetrisEvent::RedrawGrid);
     }
 
-    pub fn act(&mut self, index: usize, d: Move, _context: &mut Context) {
+    // tells the render about next and hold block changes of cell 0
+    fn emit_redraws(&mut self, context: &mut Context) {
+        let c = &mut self.cells[0];
+        if c.redraw_next {
+            c.redraw_next = false;
+            context.events.emit(TetrisEvent::RedrawNext);
+        }
+        if c.redraw_hold {
+            c.redraw_hold = false;
+            context.events.emit(TetrisEvent::RedrawHold);
+        }
+    }
+
+    pub fn act(&mut self, index: usize, d: Move, context: &mut Context) {
         if d == Move::Restart {
-            self.reset();
+            self.reset(context);
         }
         if self.cells[0].core.game_over || self.cells[1].core.game_over {
             return;
         }
+        let ts = &mut context.timers;
         match d {
             Move::TurnCw | Move::TurnCcw => {
-                if self.cells[index].move_block(d, false) == MoveRet::Normal {
-                    self.cells[index].make_shadow();
+                if self.cells[index].move_block(ts, d, false) == MoveRet::Normal {
+                    self.cells[index].make_shadow(ts);
                 } else {
                     //开始尝试左右移动再转...
                     let cmds = ["L", "LL", "R", "RR"];
                     for c in cmds {
-                        if self.cells[index].help_turn(d, c) {
+                        if self.cells[index].help_turn(ts, d, c) {
                             return;
                         }
                     }
                 }
             }
             Move::DropDown => {
-                timer_fire(&format!("fall{}", index), 0);
+                ts.fire(&format!("fall{}", index), 0);
                 debug!("fire fall{}", index);
             }
             Move::Down => {
-                if self.cells[index].move_block(d, false) == MoveRet::ReachBottom {
-                    self.cells[index].next_block(&self.block_queue, false, false);
+                if self.cells[index].move_block(ts, d, false) == MoveRet::ReachBottom {
+                    self.cells[index].next_block(ts, &self.block_queue, false, false);
                 }
             }
             Move::Left | Move::Right => {
-                self.cells[index].move_block(d, false);
-                self.cells[index].make_shadow();
+                self.cells[index].move_block(ts, d, false);
+                self.cells[index].make_shadow(ts);
             }
             Move::Save => {
-                self.cells[index].save_block(&self.block_queue, false);
-                self.cells[index].make_shadow();
+                self.cells[index].save_block(ts, &self.block_queue, false);
+                self.cells[index].make_shadow(ts);
             }
             _ => {}
         }
+        self.emit_redraws(context);
     }
 }
 
 impl Model for TetrisModel {
-    fn init(&mut self, _context: &mut Context) {
-        self.reset();
+    fn init(&mut self, context: &mut Context) {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let kc = format!("games/{}/assets/keys.cfg", context.game_name);
+            if let Err(e) = self.actions.load(&kc) {
+                log::info!("use default keys, {}", e);
+            }
+        }
+        context.rand.srand_now();
+        context.events.subscribe::<TimerEvent>("model");
+        self.reset(context);
     }
 
-    fn handle_input(&mut self, context: &mut Context, _dt: f32) {
-        let es = context.input_events.clone();
-        for e in &es {
-            match e {
-                Event::Key(key) => {
-                    let mut d: Option<Move> = None;
-                    match key.code {
-                        KeyCode::Char(' ') => {
-                            d = Some(Move::DropDown);
-                        }
-                        KeyCode::Char('o') => d = Some(Move::TurnCcw),
-                        KeyCode::Char('i') => d = Some(Move::TurnCw),
-                        KeyCode::Char('j') => d = Some(Move::Left),
-                        KeyCode::Char('k') => d = Some(Move::Down),
-                        KeyCode::Char('l') => d = Some(Move::Right),
-                        KeyCode::Char('s') => d = Some(Move::Save),
-                        KeyCode::Char('r') => d = Some(Move::Restart),
-                        _ => {}
-                    }
-                    if d != None {
-                        self.act(0, d.unwrap(), context);
-                    }
-                }
-                _ => {}
+    fn handle_input(&mut self, context: &mut Context, dt: f32) {
+        self.actions.update(&context.input_events, dt);
+        context.input_events.clear();
+        // moves repeat while the key is held, others act once per press
+        let repeats = [
+            ("left", Move::Left),
+            ("right", Move::Right),
+            ("down", Move::Down),
+        ];
+        let presses = [
+            ("turn_cw", Move::TurnCw),
+            ("turn_ccw", Move::TurnCcw),
+            ("drop", Move::DropDown),
+            ("save", Move::Save),
+            ("restart", Move::Restart),
+        ];
+        for (a, d) in repeats {
+            if self.actions.triggered(a) {
+                self.act(0, d, context);
+            }
+        }
+        for (a, d) in presses {
+            if self.actions.pressed(a) {
+                self.act(0, d, context);
             }
         }
-        context.input_events.clear();
     }
 
     fn handle_event(&mut self, _context: &mut Context, _dt: f32) {}
 
-    fn handle_timer(&mut self, _context: &mut Context, _dt: f32) {
+    fn handle_timer(&mut self, context: &mut Context, _dt: f32) {
+        let fired: Vec<String> = context
+            .events
+            .drain::<TimerEvent>("model")
+            .into_iter()
+            .map(|e| e.0)
+            .collect();
+        let ts = &mut context.timers;
         for i in 0..2 as usize {
             if self.cells[i].core.game_over {
                 continue;
             }
-            self.cells[i].timer_process(&self.block_queue);
+            self.cells[i].timer_process(ts, &fired, &self.block_queue);
             if self.cells[i].core.attack[0] != 0 {
                 self.cells[1 - i].attacked(
+                    ts,
                     &mut self.trand,
                     self.cells[i].core.attack[0],
                     self.cells[i].core.attack[1],
                 );
-                self.cells[1 - i].make_shadow();
+                self.cells[1 - i].make_shadow(ts);
                 self.cells[i].core.attack[0] = 0;
             }
         }
+        self.emit_redraws(context);
     }
 
     fn handle_auto(&mut self, context: &mut Context, dt: f32) {
@@ -195,3 +261,27 @@ impl Model for TetrisModel {
         self
     }
 }
+
+impl SaveModel for TetrisModel {
+    fn save_model(&self) -> Result<Vec<u8>, String> {
+        let ts = TetrisSave {
+            cells: self.cells,
+            block_queue: self.block_queue.to_vec(),
+            trand: self.trand.clone(),
+        };
+        bincode::serialize(&ts).map_err(|e| e.to_string())
+    }
+
+    fn load_model(&mut self, context: &mut Context, data: &[u8]) -> Result<(), String> {
+        let ts: TetrisSave = bincode::deserialize(data).map_err(|e| e.to_string())?;
+        if ts.block_queue.len() != BLKQUEUE as usize {
+            return Err(format!("tetris save: bad block queue {}", ts.block_queue.len()));
+        }
+        self.cells = ts.cells;
+        self.block_queue.copy_from_slice(&ts.block_queue);
+        self.trand = ts.trand;
+        context.events.emit(TetrisEvent::RedrawNext);
+        context.events.emit(TetrisEvent::RedrawHold);
+        Ok(())
+    }
+}
@@ -1,4 +1,4 @@
-use crate::model::TetrisModel;
+use crate::model::{TetrisEvent, TetrisModel};
 use tetris_lib::constant::*;
 //use std::fs::File;
 //use std::io::Write;
@@ -8,7 +8,6 @@ use rust_pixel::{
     asset::AssetType,
     asset2sprite,
     context::Context,
-    event::{event_check, event_register, timer_exdata, timer_stage},
     game::{Model, Render},
     render::panel::Panel,
     render::sprite::{Sprite, Sprites},
@@ -55,10 +54,6 @@ impl TetrisRender {
         let l3 = Sprite::new(17, 8, 4, 4);
         s.add_by_tag(l3, "hold");
 
-        event_register("Tetris.RedrawNext", "redraw_next");
-        event_register("Tetris.RedrawHold", "redraw_hold");
-        event_register("Tetris.RedrawMsg", "redraw_msg");
-
         Self {
             panel: t,
             main_scene: s,
@@ -170,7 +165,9 @@ impl TetrisRender {
                 let rx = j * 2;
                 #[cfg(any(feature = "sdl", target_arch = "wasm32"))]
                 let rx = j;
-                if d.cells[0].get_md(d.cells[0].core.save_block, 0, i * 4 + j) != 0 {
+                // save_block is -1 until a block is held
+                let blk = d.cells[0].core.save_block;
+                if blk >= 0 && d.cells[0].get_md(blk, 0, i * 4 + j) != 0 {
                     self.set_block(
                         "hold",
                         rx as u16,
@@ -206,13 +203,13 @@ impl TetrisRender {
         }
     }
 
-    pub fn draw_grid<G: Model>(&mut self, _context: &mut Context, model: &mut G) {
+    pub fn draw_grid<G: Model>(&mut self, context: &mut Context, model: &mut G) {
         let d = model.as_any().downcast_mut::<TetrisModel>().unwrap();
         for n in 0..2 {
-            let frs = timer_stage(&format!("clear-row{}", n));
+            let frs = context.timers.stage(&format!("clear-row{}", n));
             let mut fri: Vec<i8> = vec![];
             if frs != 0 {
-                let fr = timer_exdata(&format!("clear-row{}", n)).unwrap();
+                let fr = context.timers.exdata(&format!("clear-row{}", n)).unwrap();
                 fri = bincode::deserialize(&fr).unwrap();
                 //info!("frs..{} fri..{:?}", frs, fri);
             }
@@ -278,6 +275,7 @@ impl Render for TetrisRender {
         #[cfg(not(any(feature = "sdl", target_arch = "wasm32")))]
         context.adapter.init(80, 30, 1.0, 1.0, "tetris".to_string());
         self.panel.init(context);
+        context.events.subscribe::<TetrisEvent>("render");
         let l = self.main_scene.get_by_tag("back");
         #[cfg(any(feature = "sdl", target_arch = "wasm32"))]
         let bp = "back.pix";
@@ -298,12 +296,17 @@ impl Render for TetrisRender {
             .unwrap();
     }
 
-    fn handle_event<G: Model>(&mut self, _context: &mut Context, data: &mut G, _dt: f32) {
-        if event_check("Tetris.RedrawNext", "redraw_next") {
-            self.redraw_next(data);
-        }
-        if event_check("Tetris.RedrawHold", "redraw_hold") {
-            self.redraw_hold(data);
+    fn handle_event<G: Model>(&mut self, context: &mut Context, data: &mut G, _dt: f32) {
+        for e in context.events.drain::<TetrisEvent>("render") {
+            match e {
+                TetrisEvent::RedrawNext => self.redraw_next(data),
+                TetrisEvent::RedrawHold => self.redraw_hold(data),
+                // a new game, next and hold blocks are reset too
+                TetrisEvent::RedrawGrid => {
+                    self.redraw_next(data);
+                    self.redraw_hold(data);
+                }
+            }
         }
     }
 
@@ -2,7 +2,7 @@ use rust_pixel::event::Event;
 // use log::info;
 use rust_pixel::{
     context::Context,
-    event::{event_check, event_emit, timer_fire, timer_register},
+    event::TimerEvent,
     game::Model,
     util::{objpool::GameObjPool, Point},
 };
@@ -13,6 +13,11 @@ use tower_lib::{
     MAX_LASER_COUNT, MAX_MONSTER_COUNT, MAX_TOWER_COUNT, TOWERH, TOWERW,
 };
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum TowerEvent {
+    RedrawGrid,
+}
+
 enum TowerState {
     Normal,
 }
@@ -69,6 +74,7 @@ impl Model for TowerModel {
     fn init(&mut self, ctx: &mut Context) {
         ctx.rand.srand_now();
         ctx.input_events.clear();
+        ctx.events.subscribe::<TimerEvent>("model");
         ctx.state = TowerState::Normal as u8;
         // 创建路障
         let bps = vec![
@@ -109,15 +115,15 @@ impl Model for TowerModel {
         // 注册创建怪物定时器，以便延迟创建怪物
         for i in 0..8 {
             let tstr = format!("Tower.CreatMonster{}", i);
-            timer_register(&tstr, 0.1 + 1.5 * i as f32, "_");
-            timer_fire(&tstr, 0u8);
+            ctx.timers.register(&tstr, 0.1 + 1.5 * i as f32);
+            ctx.timers.fire(&tstr, 0u8);
         }
 
         // 更新grid
         self.make_grid();
 
         // 发射重绘事件
-        event_emit("Tower.RedrawGrid");
+        ctx.events.emit(TowerEvent::RedrawGrid);
     }
 
     fn handle_input(&mut self, ctx: &mut Context, _dt: f32) {
@@ -184,9 +190,10 @@ impl Model for TowerModel {
             x: ctx.adapter.cell_width() as u16,
             y: ctx.adapter.cell_height() as u16,
         };
+        let fired = ctx.events.drain::<TimerEvent>("model");
         for i in 0..8 {
             let tstr = format!("Tower.CreatMonster{}", i);
-            if event_check(&tstr, "_") {
+            if fired.contains(&TimerEvent(tstr)) {
                 if i > 3 {
                     self.monsters.create(1, &vec![csp]);
                 } else {
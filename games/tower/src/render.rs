@@ -1,11 +1,11 @@
-use crate::model::TowerModel;
+use crate::model::{TowerEvent, TowerModel};
 #[cfg(any(feature = "sdl", target_arch = "wasm32"))]
 use rust_pixel::render::cell::cellsym;
 use rust_pixel::{
     asset::AssetType,
     asset2sprite,
     context::Context,
-    event::{event_check, event_register, timer_fire, timer_register},
+    event::TimerEvent,
     game::{Model, Render},
     render::sprite::{Sprite, Sprites},
     render::style::{Color, Style},
@@ -31,9 +31,6 @@ impl TowerRender {
             Sprite::new(0, (TOWERH + 3) as u16, TOWERW as u16, 1u16),
             "TOWER-MSG",
         );
-        event_register("Tower.RedrawGrid", "draw_grid");
-        timer_register("Tower.TestTimer", 0.1, "test_timer");
-        timer_fire("Tower.TestTimer", 8u8);
 
         Self {
             panel: t,
@@ -204,16 +201,22 @@ impl Render for TowerRender {
         }
         self.create_sprites(ctx, data);
         self.panel.init(ctx);
+        ctx.events.subscribe::<TowerEvent>("render");
+        ctx.events.subscribe::<TimerEvent>("render");
+        ctx.timers.register("Tower.TestTimer", 0.1);
+        ctx.timers.fire("Tower.TestTimer", 8u8);
     }
 
     fn handle_event<G: Model>(&mut self, ctx: &mut Context, data: &mut G, _dt: f32) {
-        if event_check("Tower.RedrawGrid", "draw_grid") {
+        let es = ctx.events.drain::<TowerEvent>("render");
+        if es.contains(&TowerEvent::RedrawGrid) {
             self.draw_grid(ctx, data);
         }
     }
 
     fn handle_timer<G: Model>(&mut self, ctx: &mut Context, _model: &mut G, _dt: f32) {
-        if event_check("Tower.TestTimer", "test_timer") {
+        let es = ctx.events.drain::<TimerEvent>("render");
+        if es.contains(&TimerEvent("Tower.TestTimer".to_string())) {
             let ml = self.sprites.get_by_tag("TOWER-MSG");
             ml.content.set_str(
                 (ctx.stage / 6) as u16 % TOWERW as u16,
@@ -221,7 +224,7 @@ impl Render for TowerRender {
                 "tower",
                 Style::default().fg(Color::Yellow),
             );
-            timer_fire("Tower.TestTimer", 0u8);
+            ctx.timers.fire("Tower.TestTimer", 0u8);
         }
     }
 
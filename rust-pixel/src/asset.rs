@@ -4,25 +4,75 @@
 //! asset provides the resource manager.
 //! It supports async load. It calls JavaScript methods to load resources asynchronously when runs in wasm mode.
 //! https://www.reddit.com/r/rust/comments/8ymzwg/common_data_and_behavior/
+//!
+//! Hot reload is opt-in for development: after asset_manager.watch(true)
+//! the game loop polls the modified time of loaded files every
+//! watch_interval seconds, changed files are read and parsed again, and
+//! sprites showing them redraw in their next check_asset_request.
+//! Not available in wasm.
+//!
+//! Files are read from the asset packs mounted on the manager first,
+//! see pack.rs.
+//!
+//! Loading never panics on a bad file: a missing file or one that fails to
+//! parse puts the asset in AssetState::Failed with an AssetError, get returns
+//! it as usual so callers can check get_state. Sprites requesting a failed
+//! asset draw missing_buffer instead, a visible marker.
 
 #[cfg(not(target_arch = "wasm32"))]
 use crate::util::get_abs_path;
 use crate::{
+    asset::pack::AssetPack,
+    audio::SoundAsset,
     render::buffer::Buffer,
     render::image::{EscAsset, PixAsset, SeqFrameAsset},
     render::sprite::Sprite,
+    render::style::{Color, Style},
+    util::Rect,
 };
+#[cfg(not(target_arch = "wasm32"))]
+use log::info;
+use log::warn;
 use std::collections::HashMap;
+use std::fmt;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::SystemTime;
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
-#[cfg(not(target_arch = "wasm32"))]
-use log::info;
 
-#[derive(PartialEq, Clone, Copy)]
+/// single file asset packs, mounted as a virtual file system
+pub mod pack;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum AssetError {
+    // file can not be read
+    NotFound(String),
+    MalformedHeader(String),
+    // frame lengths in the header do not match the frame data
+    BadFrameOffsets(String),
+    Decompress(String),
+    // cell data does not fit the header
+    BadData(String),
+}
+
+impl fmt::Display for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AssetError::NotFound(s) => write!(f, "asset not found: {}", s),
+            AssetError::MalformedHeader(s) => write!(f, "malformed header: {}", s),
+            AssetError::BadFrameOffsets(s) => write!(f, "bad frame offsets: {}", s),
+            AssetError::Decompress(s) => write!(f, "decompression failed: {}", s),
+            AssetError::BadData(s) => write!(f, "bad data: {}", s),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub enum AssetState {
     Loading,
     Parsing,
     Ready,
+    Failed(AssetError),
 }
 
 #[derive(PartialEq, Clone, Copy)]
@@ -30,6 +80,8 @@ pub enum AssetType {
     ImgPix,
     ImgEsc,
     ImgSsf,
+    // sound file played by audio, raw data only
+    Sound,
 }
 
 pub struct AssetBase {
@@ -43,6 +95,8 @@ pub struct AssetBase {
     pub parsed_buffers: Vec<Buffer>,
     pub frame_count: usize,
     pub state: AssetState,
+    // bumped on every set_data, sprites redraw when it changes
+    pub version: u32,
 }
 
 impl AssetBase {
@@ -54,6 +108,7 @@ impl AssetBase {
             parsed_buffers: vec![],
             frame_count: 1,
             state: AssetState::Loading,
+            version: 0,
         }
     }
 }
@@ -80,6 +135,7 @@ pub trait Asset {
         bs.raw_data.clear();
         bs.raw_data.extend(data);
         bs.state = AssetState::Parsing;
+        bs.version = bs.version.wrapping_add(1);
     }
 
     fn set_state(&mut self, st: AssetState) {
@@ -87,10 +143,11 @@ pub trait Asset {
     }
 
     fn get_state(&mut self) -> AssetState {
-        self.get_base().state
+        self.get_base().state.clone()
     }
 
-    fn parse(&mut self);
+    /// parses raw_data into parsed_buffers
+    fn parse(&mut self) -> Result<(), AssetError>;
 
     fn save(&mut self, buf: &Buffer);
 }
@@ -98,6 +155,16 @@ pub trait Asset {
 pub struct AssetManager {
     pub assets: Vec<Box<dyn Asset>>,
     pub assets_index: HashMap<String, usize>,
+    // hot reload of changed files, see watch
+    pub watching: bool,
+    // seconds between checks of the files
+    pub watch_interval: f32,
+    watch_timer: f32,
+    // modified time of the loaded files
+    #[cfg(not(target_arch = "wasm32"))]
+    mtimes: HashMap<String, SystemTime>,
+    // mounted asset packs, searched in mount order
+    pub packs: Vec<AssetPack>,
 }
 
 impl AssetManager {
@@ -105,6 +172,117 @@ impl AssetManager {
         Self {
             assets: vec![],
             assets_index: HashMap::new(),
+            watching: false,
+            watch_interval: 0.5,
+            watch_timer: 0.0,
+            #[cfg(not(target_arch = "wasm32"))]
+            mtimes: HashMap::new(),
+            packs: vec![],
+        }
+    }
+
+    /// turns hot reload on or off
+    pub fn watch(&mut self, on: bool) {
+        self.watching = on;
+        self.watch_timer = 0.0;
+    }
+
+    /// called by the game loop, reloads changed files every watch_interval
+    /// seconds while watching
+    pub fn update(&mut self, dt: f32) {
+        if !self.watching {
+            return;
+        }
+        self.watch_timer += dt;
+        if self.watch_timer >= self.watch_interval {
+            self.watch_timer = 0.0;
+            self.reload_changed();
+        }
+    }
+
+    /// reads and parses again the files modified since they were read,
+    /// or missing then, returns their locations
+    pub fn reload_changed(&mut self) -> Vec<String> {
+        #[allow(unused_mut)]
+        let mut changed = vec![];
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            for loc in self.assets_index.keys() {
+                if self.in_packs(loc) {
+                    continue;
+                }
+                let m = std::fs::metadata(get_abs_path(loc)).and_then(|m| m.modified());
+                if let Ok(m) = m {
+                    if self.mtimes.get(loc) != Some(&m) {
+                        changed.push(loc.clone());
+                    }
+                }
+            }
+            changed.sort();
+            for loc in &changed {
+                info!("asset reload:{}", loc);
+                self.load_file(loc);
+            }
+        }
+        changed
+    }
+
+    /// bumped each time the data of loc is set
+    pub fn version(&mut self, loc: &str) -> Option<u32> {
+        self.get(loc).map(|a| a.get_base().version)
+    }
+
+    /// mounts an asset pack, files in it are read from the pack instead of
+    /// the disk, see pack.rs
+    pub fn mount(&mut self, p: AssetPack) {
+        self.packs.push(p);
+    }
+
+    pub fn unmount_all(&mut self) {
+        self.packs.clear();
+    }
+
+    /// true if a mounted pack has path
+    pub fn in_packs(&self, path: &str) -> bool {
+        self.packs.iter().any(|p| p.contains(path))
+    }
+
+    /// reads path from the mounted packs, None if none of them has it
+    pub fn read_packed(&self, path: &str) -> Option<Result<Vec<u8>, AssetError>> {
+        self.packs
+            .iter()
+            .find(|p| p.contains(path))
+            .map(|p| p.read(path))
+    }
+
+    /// reads path from the mounted packs, or the file relative to the
+    /// pixel root path
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn read_file(&self, path: &str) -> Result<Vec<u8>, AssetError> {
+        if let Some(r) = self.read_packed(path) {
+            return r;
+        }
+        let fpstr = get_abs_path(path);
+        std::fs::read(&fpstr).map_err(|e| AssetError::NotFound(format!("{} {}", fpstr, e)))
+    }
+
+    // reads and parses the file of asset loc
+    #[cfg(not(target_arch = "wasm32"))]
+    fn load_file(&mut self, loc: &str) {
+        let mtime = std::fs::metadata(get_abs_path(loc)).and_then(|m| m.modified());
+        match self.read_file(loc) {
+            Ok(fdata) => {
+                if let (Ok(m), false) = (mtime, self.in_packs(loc)) {
+                    self.mtimes.insert(loc.to_string(), m);
+                }
+                self.set_data(loc, &fdata[..]);
+            }
+            Err(err) => {
+                warn!("asset {}: {}", loc, err);
+                if let Some(ast) = self.get(loc) {
+                    ast.set_state(AssetState::Failed(err));
+                }
+            }
         }
     }
 
@@ -114,25 +292,27 @@ impl AssetManager {
             Some(_) => {}
             None => {
                 let mut ab = AssetBase::new(t, loc);
-                #[cfg(target_arch = "wasm32")]
-                {
-                    js_load_asset(loc);
-                }
-                #[cfg(not(target_arch = "wasm32"))]
-                {}
                 let mut ast: Box<dyn Asset> = match t {
                     AssetType::ImgPix => Box::new(PixAsset::new(ab)),
                     AssetType::ImgEsc => Box::new(EscAsset::new(ab)),
                     AssetType::ImgSsf => Box::new(SeqFrameAsset::new(ab)),
+                    AssetType::Sound => Box::new(SoundAsset::new(ab)),
                 };
                 self.assets.push(ast);
                 self.assets_index.insert(loc.to_string(), self.assets.len());
+                // assets in mounted packs are ready at once
+                #[cfg(target_arch = "wasm32")]
+                match self.read_packed(loc) {
+                    Some(Ok(data)) => self.set_data(loc, &data),
+                    Some(Err(e)) => {
+                        self.assets.last_mut().unwrap().set_state(AssetState::Failed(e))
+                    }
+                    None => js_load_asset(loc),
+                }
                 #[cfg(not(target_arch = "wasm32"))]
                 {
-                    let fpstr = get_abs_path(loc);
-                    info!("asset load:{:?}", fpstr);
-                    let fdata = std::fs::read(fpstr).expect("read file error");
-                    self.set_data(loc, &fdata[..]);
+                    info!("asset load:{:?}", get_abs_path(loc));
+                    self.load_file(loc);
                 }
             }
         }
@@ -151,14 +331,48 @@ impl AssetManager {
     pub fn set_data(&mut self, loc: &str, data: &[u8]) {
         match self.assets_index.get(loc) {
             Some(idx) => {
-                self.assets[*idx - 1].set_data(data);
-                self.assets[*idx - 1].set_state(AssetState::Parsing);
-                self.assets[*idx - 1].parse();
-                self.assets[*idx - 1].set_state(AssetState::Ready);
+                let ast = &mut self.assets[*idx - 1];
+                ast.set_data(data);
+                ast.set_state(AssetState::Parsing);
+                match ast.parse() {
+                    Ok(()) => ast.set_state(AssetState::Ready),
+                    Err(e) => {
+                        warn!("asset {}: {}", loc, e);
+                        ast.get_base().parsed_buffers.clear();
+                        ast.set_state(AssetState::Failed(e));
+                    }
+                }
             }
             None => {}
         }
     }
+
+    /// error of a failed asset, None if it is fine or not loaded
+    pub fn error(&mut self, loc: &str) -> Option<AssetError> {
+        match self.get(loc)?.get_state() {
+            AssetState::Failed(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// placeholder of an asset that failed to load, red ▒ over area
+/// with "missing" in the middle row
+pub fn missing_buffer(area: Rect) -> Buffer {
+    let mut buf = Buffer::empty(area);
+    let style = Style::default().fg(Color::Red);
+    for y in 0..area.height {
+        for x in 0..area.width {
+            buf.set_str(x, y, "▒", style);
+        }
+    }
+    let text = "missing";
+    let w = text.len().min(area.width as usize);
+    let x = (area.width - w as u16) / 2;
+    if area.height > 0 {
+        buf.set_str(x, area.height / 2, &text[..w], style);
+    }
+    buf
 }
 
 // refer to rust-pixel/web-templates/index.js
@@ -167,3 +381,112 @@ impl AssetManager {
 extern "C" {
     fn js_load_asset(url: &str);
 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_reports_missing_files() {
+        let mut am = AssetManager::new();
+        am.load(AssetType::ImgPix, "no/such/file.pix");
+        assert!(matches!(am.error("no/such/file.pix"), Some(AssetError::NotFound(_))));
+    }
+
+    #[test]
+    fn it_reports_bad_pix_data_until_fixed() {
+        let mut am = AssetManager::new();
+        am.load(AssetType::ImgPix, "no/such/file.pix");
+        am.set_data("no/such/file.pix", b"width=2,height=1,texture=255\n1,2,3 4,5,6 7,8,9\n");
+        let bad = AssetError::BadData("line 1,2,3 4,5,6 7,8,9".to_string());
+        assert_eq!(am.error("no/such/file.pix"), Some(bad));
+        am.set_data("no/such/file.pix", b"width=2,height=1,texture=255\n1,2,3 4,5,6\n");
+        assert_eq!(am.get("no/such/file.pix").unwrap().get_state(), AssetState::Ready);
+    }
+
+    #[test]
+    fn it_reports_bad_ssf_files() {
+        let mut am = AssetManager::new();
+        am.load(AssetType::ImgSsf, "no/such/file.ssf");
+        am.set_data("no/such/file.ssf", b"width=2,height=1,texture=255\n");
+        assert!(matches!(am.error("no/such/file.ssf"), Some(AssetError::MalformedHeader(_))));
+        am.set_data("no/such/file.ssf", b"width=2,height=1,texture=255,frame_count=2\n10,\n");
+        assert!(matches!(am.error("no/such/file.ssf"), Some(AssetError::BadFrameOffsets(_))));
+        am.set_data("no/such/file.ssf", b"width=2,height=1,texture=255,frame_count=1\n3,\nabc");
+        assert!(matches!(am.error("no/such/file.ssf"), Some(AssetError::Decompress(_))));
+    }
+
+    #[test]
+    fn it_draws_a_marker_for_failed_assets() {
+        let mut am = AssetManager::new();
+        let mut sp = Sprite::new(0, 0, 9, 3);
+        sp.set_content_by_asset(&mut am, AssetType::ImgSsf, "no/such/file.ssf", 0, 0, 0);
+        assert_eq!(sp.content.get(1, 1).symbol, "m");
+        assert_eq!(sp.content.get(0, 0).symbol, "▒");
+        assert!(sp.asset_request.is_some());
+    }
+
+    // writes a 1x1 pix file with the given symbol and modification time
+    fn write_pix(path: &std::path::Path, sym: u8, secs: u64) {
+        std::fs::write(path, format!("width=1,height=1,texture=255\n{},15,0\n", sym)).unwrap();
+        let f = std::fs::File::options().write(true).open(path).unwrap();
+        let t = std::time::UNIX_EPOCH + std::time::Duration::from_secs(secs);
+        f.set_modified(t).unwrap();
+    }
+
+    fn temp_pix(name: &str) -> (std::path::PathBuf, String) {
+        let path = std::env::temp_dir().join(format!("rp_{}_{}.pix", name, std::process::id()));
+        let loc = path.to_str().unwrap().to_string();
+        (path, loc)
+    }
+
+    #[test]
+    fn it_polls_watched_files_every_interval() {
+        let (path, loc) = temp_pix("poll");
+        write_pix(&path, 1, 1000);
+        let mut am = AssetManager::new();
+        am.load(AssetType::ImgPix, &loc);
+        assert_eq!(am.version(&loc), Some(1));
+        assert!(am.reload_changed().is_empty());
+        write_pix(&path, 2, 2000);
+        // not watching
+        am.update(1.0);
+        assert_eq!(am.version(&loc), Some(1));
+        am.watch(true);
+        am.update(0.1);
+        assert_eq!(am.version(&loc), Some(1));
+        am.update(0.5);
+        assert_eq!(am.version(&loc), Some(2));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn it_reloads_changed_files_into_sprites() {
+        let (path, loc) = temp_pix("reload");
+        write_pix(&path, 1, 1000);
+        let mut am = AssetManager::new();
+        let mut sp = Sprite::new(0, 0, 1, 1);
+        sp.set_content_by_asset(&mut am, AssetType::ImgPix, &loc, 0, 0, 0);
+        assert_eq!(sp.content.get(0, 0).symbol, "∁");
+        // sprites follow reloads only while watching
+        am.watch(true);
+        write_pix(&path, 2, 2000);
+        assert_eq!(am.reload_changed(), vec![loc.clone()]);
+        sp.check_asset_request(&mut am);
+        assert_eq!(sp.content.get(0, 0).symbol, "∂");
+        let _ = std::fs::remove_file(&path);
+    }
+    #[test]
+    fn it_reads_mounted_packs_before_the_disk() {
+        let (path, loc) = temp_pix("packed");
+        write_pix(&path, 1, 1000);
+        let data = b"width=1,height=1,texture=0\n2,7,0\n".to_vec();
+        let packed = pack::build_pack(&[(loc.clone(), data.clone())]).unwrap();
+        let mut am = AssetManager::new();
+        am.mount(AssetPack::from_bytes(packed).unwrap());
+        assert!(am.in_packs(&loc));
+        assert_eq!(am.read_file(&loc).unwrap(), data);
+        am.unmount_all();
+        assert_ne!(am.read_file(&loc).unwrap(), data);
+        let _ = std::fs::remove_file(&path);
+    }
+}
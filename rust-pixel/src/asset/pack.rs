@@ -0,0 +1,254 @@
+// RustPixel
+// copyright zhouxin@tuyoogame.com 2022~2024
+
+//! Asset packs put the files of a game into one file, so a game can ship
+//! as one executable instead of finding an assets folder next to Cargo.lock.
+//!
+//! A pack is an index of paths followed by the deflate compressed files:
+//!
+//! "PIXPACK\0" | version u32 | count u32 |
+//! count * (path_len u16, path, offset u32, size u32, raw_size u32) | data
+//!
+//! Numbers are little endian, offsets start at data. Paths use '/' and are
+//! the locations assets are loaded with, e.g. games/snake/assets/back.pix.
+//!
+//! Packs are mounted on an AssetManager and work as its virtual file
+//! system: AssetManager::read_file looks in them first, in mount order,
+//! then on disk. Loading assets, Audio::play_file and the SDL textures
+//! all read through the asset manager of the context.
+//!
+//! Packs are made with tools/tpack, and embedded with embed_pack!
+//!
+//! # Example
+//!
+//! ```ignore
+//! // $ cargo run -p tpack games/snake/assets snake.pack
+//! ctx.asset_manager.mount(embed_pack!("../../../snake.pack")?);
+//! ```
+
+use crate::asset::AssetError;
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+
+const PACK_MAGIC: &[u8; 8] = b"PIXPACK\0";
+const PACK_VERSION: u32 = 1;
+
+/// loads a pack built into the binary, the path is relative to the
+/// source file like include_bytes!
+#[macro_export]
+macro_rules! embed_pack {
+    ($path:expr) => {
+        $crate::asset::pack::AssetPack::from_static(include_bytes!($path))
+    };
+}
+
+// paths in packs use '/'
+fn normalize(path: &str) -> String {
+    path.replace('\\', "/").trim_start_matches("./").to_string()
+}
+
+pub struct AssetPack {
+    // path to (offset, size, raw size)
+    pub entries: HashMap<String, (usize, usize, usize)>,
+    // the whole pack
+    data: Cow<'static, [u8]>,
+    // offset of the first file
+    start: usize,
+}
+
+impl AssetPack {
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self, AssetError> {
+        Self::parse(Cow::Owned(data))
+    }
+
+    /// a pack in static memory, see embed_pack!
+    pub fn from_static(data: &'static [u8]) -> Result<Self, AssetError> {
+        Self::parse(Cow::Borrowed(data))
+    }
+
+    pub fn open(path: &str) -> Result<Self, AssetError> {
+        let data = fs::read(path).map_err(|e| AssetError::NotFound(format!("{} {}", path, e)))?;
+        Self::from_bytes(data)
+    }
+
+    fn parse(data: Cow<'static, [u8]>) -> Result<Self, AssetError> {
+        fn bad(s: &str) -> AssetError {
+            AssetError::MalformedHeader(format!("pack: {}", s))
+        }
+        // next n bytes of the index
+        fn next<'a>(data: &'a [u8], pos: &mut usize, n: usize) -> Result<&'a [u8], AssetError> {
+            let b = data.get(*pos..*pos + n).ok_or(bad("truncated index"))?;
+            *pos += n;
+            Ok(b)
+        }
+        let mut pos = 0usize;
+        let mut take = |n: usize| next(&data, &mut pos, n);
+        let u32_of = |b: &[u8]| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize;
+        if take(8)? != PACK_MAGIC {
+            return Err(bad("bad magic"));
+        }
+        let version = u32_of(take(4)?);
+        if version != PACK_VERSION as usize {
+            return Err(bad(&format!("unknown version {}", version)));
+        }
+        let count = u32_of(take(4)?);
+        let mut entries = HashMap::new();
+        for _ in 0..count {
+            let b = take(2)?;
+            let n = u16::from_le_bytes([b[0], b[1]]) as usize;
+            let name = String::from_utf8(take(n)?.to_vec()).map_err(|_| bad("bad path"))?;
+            let (off, size, raw) = (u32_of(take(4)?), u32_of(take(4)?), u32_of(take(4)?));
+            entries.insert(name, (off, size, raw));
+        }
+        let start = pos;
+        for (name, (off, size, _)) in &entries {
+            let end = start.checked_add(*off).and_then(|e| e.checked_add(*size));
+            if end.is_none_or(|e| e > data.len()) {
+                return Err(AssetError::BadFrameOffsets(format!("pack: {}", name)));
+            }
+        }
+        Ok(Self {
+            entries,
+            data,
+            start,
+        })
+    }
+
+    pub fn contains(&self, path: &str) -> bool {
+        self.entries.contains_key(&normalize(path))
+    }
+
+    /// paths in the pack, sorted
+    pub fn paths(&self) -> Vec<&str> {
+        let mut v: Vec<&str> = self.entries.keys().map(|k| k.as_str()).collect();
+        v.sort();
+        v
+    }
+
+    /// decompressed content of path
+    pub fn read(&self, path: &str) -> Result<Vec<u8>, AssetError> {
+        let (off, size, raw) = *self
+            .entries
+            .get(&normalize(path))
+            .ok_or(AssetError::NotFound(format!("pack: {}", path)))?;
+        let blob = &self.data[self.start + off..self.start + off + size];
+        // raw comes from the index, it only bounds the read
+        let mut out = vec![];
+        DeflateDecoder::new(blob)
+            .take(raw as u64 + 1)
+            .read_to_end(&mut out)
+            .map_err(|e| AssetError::Decompress(format!("pack: {} {}", path, e)))?;
+        if out.len() != raw {
+            return Err(AssetError::Decompress(format!("pack: {} size", path)));
+        }
+        Ok(out)
+    }
+}
+
+/// builds a pack of files given as (path, content)
+pub fn build_pack(files: &[(String, Vec<u8>)]) -> Result<Vec<u8>, String> {
+    let mut index = vec![];
+    let mut data = vec![];
+    index.extend_from_slice(PACK_MAGIC);
+    index.extend_from_slice(&PACK_VERSION.to_le_bytes());
+    index.extend_from_slice(&(files.len() as u32).to_le_bytes());
+    for (path, content) in files {
+        let name = normalize(path);
+        let n = u16::try_from(name.len()).map_err(|_| format!("pack: path too long {}", path))?;
+        let mut enc = DeflateEncoder::new(vec![], Compression::best());
+        enc.write_all(content).map_err(|e| e.to_string())?;
+        let blob = enc.finish().map_err(|e| e.to_string())?;
+        let off = u32::try_from(data.len()).map_err(|_| "pack: more than 4GB".to_string())?;
+        index.extend_from_slice(&n.to_le_bytes());
+        index.extend_from_slice(name.as_bytes());
+        for v in [off, blob.len() as u32, content.len() as u32] {
+            index.extend_from_slice(&v.to_le_bytes());
+        }
+        data.extend(blob);
+    }
+    index.extend(data);
+    Ok(index)
+}
+
+/// packs all files under dir, named prefix/relative path
+pub fn pack_dir(dir: &str, prefix: &str) -> Result<Vec<u8>, String> {
+    let mut files = vec![];
+    let mut stack = vec![Path::new(dir).to_path_buf()];
+    while let Some(d) = stack.pop() {
+        let rd = fs::read_dir(&d).map_err(|e| format!("pack: {} {}", d.display(), e))?;
+        for e in rd {
+            let p = e.map_err(|e| e.to_string())?.path();
+            if p.is_dir() {
+                stack.push(p);
+                continue;
+            }
+            let rel = p.strip_prefix(dir).map_err(|e| e.to_string())?;
+            let name = Path::new(prefix).join(rel);
+            let content = fs::read(&p).map_err(|e| format!("pack: {} {}", p.display(), e))?;
+            files.push((name.to_string_lossy().to_string(), content));
+        }
+    }
+    files.sort();
+    build_pack(&files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_pack() -> Vec<u8> {
+        let files = vec![
+            (
+                "games/x/assets/a.pix".to_string(),
+                b"width=1,height=1".to_vec(),
+            ),
+            ("games\\x\\assets\\b.txt".to_string(), vec![7u8; 1000]),
+        ];
+        build_pack(&files).unwrap()
+    }
+
+    #[test]
+    fn it_packs_and_reads_files() {
+        let pack = AssetPack::from_bytes(test_pack()).unwrap();
+        assert_eq!(
+            pack.paths(),
+            vec!["games/x/assets/a.pix", "games/x/assets/b.txt"]
+        );
+        assert_eq!(pack.read("games/x/assets/b.txt").unwrap(), vec![7u8; 1000]);
+        assert_eq!(
+            pack.read("./games\\x/assets/a.pix").unwrap(),
+            b"width=1,height=1"
+        );
+        assert!(matches!(pack.read("nope"), Err(AssetError::NotFound(_))));
+    }
+
+    #[test]
+    fn it_rejects_bad_packs() {
+        let data = test_pack();
+        assert!(AssetPack::from_bytes(data[..data.len() - 1].to_vec()).is_err());
+        assert!(AssetPack::from_bytes(b"PIXPACK\0\x02\0\0\0".to_vec()).is_err());
+        // offset + size past the end, it would wrap around on wasm32
+        let mut huge = b"PIXPACK\0\x01\0\0\0\x01\0\0\0\x01\0a".to_vec();
+        for v in [u32::MAX, u32::MAX, 1] {
+            huge.extend_from_slice(&v.to_le_bytes());
+        }
+        assert!(AssetPack::from_bytes(huge).is_err());
+    }
+
+    #[test]
+    fn it_checks_the_decompressed_size() {
+        let files = vec![("a".to_string(), vec![7u8; 1000])];
+        let mut data = build_pack(&files).unwrap();
+        // raw size of the only entry, after magic, version, count, name and off, size
+        let at = 16 + 2 + 1 + 8;
+        for raw in [999u32, u32::MAX] {
+            data[at..at + 4].copy_from_slice(&raw.to_le_bytes());
+            let pack = AssetPack::from_bytes(data.clone()).unwrap();
+            assert!(matches!(pack.read("a"), Err(AssetError::Decompress(_))));
+        }
+    }
+}
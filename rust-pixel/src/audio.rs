@@ -4,46 +4,462 @@
 
 //! audio provides playing music and sound effect, reference
 //! https://docs.rs/rodio
+//!
+//! Audio is a small mixer. Every sound plays on a named channel,
+//! CHANNEL_MUSIC and CHANNEL_SFX are created by default, others are created
+//! on first use. The volume of a sound is master * channel * sound volume.
+//! Playing returns a SoundHandle to stop, pause, resume or fade the sound.
+//! Call update(dt) every frame to advance fades and drop finished sounds,
+//! Game does it for context.audio in every fixed step.
+//!
+//! Sounds play through an AudioBackend. RodioBackend opens the default
+//! output device at the first play, if there is no device sounds are muted.
+//! On wasm/android/ios the silent NullBackend is used,
+//! as well as in headless contexts, so games and tests still run.
+//!
+//! Sound files can be preloaded as AssetType::Sound and played with
+//! play_asset, so the file is read only once. play_file reads the file
+//! directly and is not available on web, use play_asset there.
+//! Retro sound effects can be generated instead with the synth module.
+//!
+//! # Example
+//!
+//! ```ignore
+//! ctx.asset_manager.load(AssetType::Sound, "games/tetris/assets/back.mp3");
+//! let bgm = ctx.audio.play_asset(&mut ctx.asset_manager,
+//!     "games/tetris/assets/back.mp3", CHANNEL_MUSIC, true)?;
+//! ctx.audio.set_channel_volume(CHANNEL_SFX, 0.5);
+//! ctx.audio.fade_out(bgm, 2.0);
+//! ```
 
+mod backend;
+#[cfg(all(
+    feature = "rodio",
+    not(any(target_os = "android", target_os = "ios", target_arch = "wasm32"))
+))]
+pub use backend::RodioBackend;
+pub use backend::{AudioBackend, NullBackend};
+/// chiptune synthesizer, renders retro sounds to WAV
+pub mod synth;
 
-use crate::util::get_abs_path;
-#[cfg(not(any(target_os = "android", target_os = "ios", target_arch = "wasm32")))]
-use rodio::{source::Source, Decoder, OutputStream, OutputStreamHandle};
-use std::fs::File;
-use std::io::BufReader;
+use crate::{
+    asset::{Asset, AssetBase, AssetError, AssetManager},
+    render::{buffer::Buffer, sprite::Sprite},
+};
+use log::info;
+use std::collections::HashMap;
+
+pub const CHANNEL_MUSIC: &str = "music";
+pub const CHANNEL_SFX: &str = "sfx";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SoundHandle(pub u64);
+
+#[derive(Debug, Clone)]
+pub struct AudioChannel {
+    pub volume: f32,
+    pub paused: bool,
+}
+
+impl Default for AudioChannel {
+    fn default() -> Self {
+        Self {
+            volume: 1.0,
+            paused: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Fade {
+    from: f32,
+    to: f32,
+    time: f32,
+    elapsed: f32,
+    // stops the sound when the fade ends
+    stop: bool,
+}
+
+#[derive(Debug, Clone)]
+struct Sound {
+    channel: String,
+    volume: f32,
+    paused: bool,
+    fade: Option<Fade>,
+}
 
 pub struct Audio {
-    #[cfg(not(any(target_os = "android", target_os = "ios", target_arch = "wasm32")))]
-    _out: OutputStream,
-    #[cfg(not(any(target_os = "android", target_os = "ios", target_arch = "wasm32")))]
-    handle: OutputStreamHandle,
+    pub backend: Box<dyn AudioBackend>,
+    pub master_volume: f32,
+    pub channels: HashMap<String, AudioChannel>,
+    sounds: HashMap<u64, Sound>,
+    next_id: u64,
 }
 
 impl Audio {
+    /// plays through the default output device, opened at the first play
     pub fn new() -> Self {
-        #[cfg(any(target_os = "android", target_os = "ios", target_arch = "wasm32"))]
-        {
-            Self {}
-        }
-        #[cfg(not(any(target_os = "android", target_os = "ios", target_arch = "wasm32")))]
-        {
-            let (s, h) = OutputStream::try_default().unwrap();
-            Self { _out: s, handle: h }
-        }
-    }
-    #[allow(unused)]
-    pub fn play_file(&self, fpath: &str, is_loop: bool) {
-        let fpstr = get_abs_path(fpath);
-        let file = BufReader::new(File::open(fpstr).unwrap());
-        #[cfg(not(any(target_os = "android", target_os = "ios", target_arch = "wasm32")))]
-        {
-            if is_loop {
-                let source = Decoder::new(file).unwrap().repeat_infinite();
-                self.handle.play_raw(source.convert_samples()).unwrap();
-            } else {
-                let source = Decoder::new(file).unwrap();
-                self.handle.play_raw(source.convert_samples()).unwrap();
-            };
+        #[cfg(all(
+            feature = "rodio",
+            not(any(target_os = "android", target_os = "ios", target_arch = "wasm32"))
+        ))]
+        let backend: Box<dyn AudioBackend> = Box::new(RodioBackend::new());
+        #[cfg(not(all(
+            feature = "rodio",
+            not(any(target_os = "android", target_os = "ios", target_arch = "wasm32"))
+        )))]
+        let backend: Box<dyn AudioBackend> = Box::new(NullBackend::new());
+        Self::with_backend(backend)
+    }
+
+    /// silent audio, sounds are tracked but not played
+    pub fn new_null() -> Self {
+        Self::with_backend(Box::new(NullBackend::new()))
+    }
+
+    pub fn with_backend(backend: Box<dyn AudioBackend>) -> Self {
+        let mut channels = HashMap::new();
+        channels.insert(CHANNEL_MUSIC.to_string(), AudioChannel::default());
+        channels.insert(CHANNEL_SFX.to_string(), AudioChannel::default());
+        Self {
+            backend,
+            master_volume: 1.0,
+            channels,
+            sounds: HashMap::new(),
+            next_id: 1,
         }
     }
+
+    fn effective_volume(&self, s: &Sound) -> f32 {
+        let cv = self.channels.get(&s.channel).map_or(1.0, |c| c.volume);
+        self.master_volume * cv * s.volume
+    }
+
+    fn is_paused(&self, s: &Sound) -> bool {
+        s.paused || self.channels.get(&s.channel).is_some_and(|c| c.paused)
+    }
+
+    // pushes volume and pause state of the sound to the backend
+    fn sync(&mut self, id: u64) {
+        if let Some(s) = self.sounds.get(&id) {
+            let v = self.effective_volume(s);
+            let p = self.is_paused(s);
+            self.backend.set_volume(id, v);
+            self.backend.set_paused(id, p);
+        }
+    }
+
+    fn sync_channel(&mut self, channel: &str) {
+        let ids: Vec<u64> = self
+            .sounds
+            .iter()
+            .filter(|(_, s)| s.channel == channel)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in ids {
+            self.sync(id);
+        }
+    }
+
+    /// plays encoded sound data (wav, mp3, ogg, flac) on channel
+    pub fn play(&mut self, data: Vec<u8>, channel: &str, is_loop: bool) -> Result<SoundHandle, String> {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.channels.entry(channel.to_string()).or_default();
+        let s = Sound {
+            channel: channel.to_string(),
+            volume: 1.0,
+            paused: false,
+            fade: None,
+        };
+        let v = self.effective_volume(&s);
+        self.backend.play(id, data, is_loop, v)?;
+        self.sounds.insert(id, s);
+        // the channel may be paused
+        self.sync(id);
+        Ok(SoundHandle(id))
+    }
+
+    /// plays a sound file read through the packs of am,
+    /// looped sounds go to the music channel, others to sfx
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn play_file(
+        &mut self,
+        am: &AssetManager,
+        fpath: &str,
+        is_loop: bool,
+    ) -> Result<SoundHandle, String> {
+        let data = am.read_file(fpath).map_err(|e| e.to_string())?;
+        let ch = if is_loop { CHANNEL_MUSIC } else { CHANNEL_SFX };
+        self.play(data, ch, is_loop)
+    }
+
+    /// plays a sound preloaded by asset manager as AssetType::Sound
+    pub fn play_asset(
+        &mut self,
+        am: &mut AssetManager,
+        loc: &str,
+        channel: &str,
+        is_loop: bool,
+    ) -> Result<SoundHandle, String> {
+        let data = match am.get(loc) {
+            Some(a) => a.get_base().raw_data.clone(),
+            None => return Err(format!("audio: {} not loaded", loc)),
+        };
+        if data.is_empty() {
+            return Err(format!("audio: {} not ready", loc));
+        }
+        self.play(data, channel, is_loop)
+    }
+
+    pub fn stop(&mut self, h: SoundHandle) {
+        self.sounds.remove(&h.0);
+        self.backend.stop(h.0);
+    }
+
+    pub fn pause(&mut self, h: SoundHandle) {
+        if let Some(s) = self.sounds.get_mut(&h.0) {
+            s.paused = true;
+            self.sync(h.0);
+        }
+    }
+
+    pub fn resume(&mut self, h: SoundHandle) {
+        if let Some(s) = self.sounds.get_mut(&h.0) {
+            s.paused = false;
+            self.sync(h.0);
+        }
+    }
+
+    /// false once the sound ended or was stopped
+    pub fn is_playing(&self, h: SoundHandle) -> bool {
+        self.sounds.contains_key(&h.0) && !self.backend.is_finished(h.0)
+    }
+
+    pub fn set_volume(&mut self, h: SoundHandle, volume: f32) {
+        if let Some(s) = self.sounds.get_mut(&h.0) {
+            s.volume = volume.max(0.0);
+            s.fade = None;
+            self.sync(h.0);
+        }
+    }
+
+    pub fn volume(&self, h: SoundHandle) -> Option<f32> {
+        self.sounds.get(&h.0).map(|s| s.volume)
+    }
+
+    /// changes the sound volume to `to` in secs seconds,
+    /// the sound is stopped at the end if stop is true
+    pub fn fade(&mut self, h: SoundHandle, to: f32, secs: f32, stop: bool) {
+        if let Some(s) = self.sounds.get_mut(&h.0) {
+            s.fade = Some(Fade {
+                from: s.volume,
+                to: to.max(0.0),
+                time: secs.max(0.0),
+                elapsed: 0.0,
+                stop,
+            });
+        }
+    }
+
+    pub fn fade_in(&mut self, h: SoundHandle, secs: f32) {
+        self.set_volume(h, 0.0);
+        self.fade(h, 1.0, secs, false);
+    }
+
+    pub fn fade_out(&mut self, h: SoundHandle, secs: f32) {
+        self.fade(h, 0.0, secs, true);
+    }
+
+    pub fn set_master_volume(&mut self, volume: f32) {
+        self.master_volume = volume.max(0.0);
+        let ids: Vec<u64> = self.sounds.keys().copied().collect();
+        for id in ids {
+            self.sync(id);
+        }
+    }
+
+    pub fn set_channel_volume(&mut self, channel: &str, volume: f32) {
+        self.channels.entry(channel.to_string()).or_default().volume = volume.max(0.0);
+        self.sync_channel(channel);
+    }
+
+    pub fn channel_volume(&self, channel: &str) -> f32 {
+        self.channels.get(channel).map_or(1.0, |c| c.volume)
+    }
+
+    pub fn pause_channel(&mut self, channel: &str) {
+        self.channels.entry(channel.to_string()).or_default().paused = true;
+        self.sync_channel(channel);
+    }
+
+    pub fn resume_channel(&mut self, channel: &str) {
+        self.channels.entry(channel.to_string()).or_default().paused = false;
+        self.sync_channel(channel);
+    }
+
+    pub fn stop_channel(&mut self, channel: &str) {
+        let ids: Vec<u64> = self
+            .sounds
+            .iter()
+            .filter(|(_, s)| s.channel == channel)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in ids {
+            self.stop(SoundHandle(id));
+        }
+    }
+
+    pub fn stop_all(&mut self) {
+        let ids: Vec<u64> = self.sounds.keys().copied().collect();
+        for id in ids {
+            self.stop(SoundHandle(id));
+        }
+    }
+
+    /// advances fades and drops finished sounds
+    pub fn update(&mut self, dt: f32) {
+        self.backend.update();
+        let mut changed = vec![];
+        let mut stopped = vec![];
+        let ids: Vec<u64> = self.sounds.keys().copied().collect();
+        for id in ids {
+            if self.backend.is_finished(id) {
+                stopped.push(id);
+                continue;
+            }
+            // fades wait while the sound or its channel is paused
+            if self.is_paused(&self.sounds[&id]) {
+                continue;
+            }
+            let s = self.sounds.get_mut(&id).unwrap();
+            if let Some(f) = &mut s.fade {
+                f.elapsed += dt;
+                let k = if f.time > 0.0 {
+                    (f.elapsed / f.time).min(1.0)
+                } else {
+                    1.0
+                };
+                s.volume = f.from + (f.to - f.from) * k;
+                changed.push(id);
+                if k >= 1.0 {
+                    if f.stop {
+                        stopped.push(id);
+                    }
+                    s.fade = None;
+                }
+            }
+        }
+        for id in changed {
+            self.sync(id);
+        }
+        for id in stopped {
+            self.stop(SoundHandle(id));
+        }
+    }
+}
+
+impl Default for Audio {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// sound file preloaded by AssetManager, raw_data keeps the encoded bytes
+pub struct SoundAsset {
+    base: AssetBase,
+}
+
+impl Asset for SoundAsset {
+    fn new(ab: AssetBase) -> Self {
+        Self { base: ab }
+    }
+
+    // a sound has no image
+    fn set_sprite(&mut self, _sp: &mut Sprite, _frame_idx: usize, _off_x: u16, _off_y: u16) {}
+
+    fn get_base(&mut self) -> &mut AssetBase {
+        &mut self.base
+    }
+
+    fn parse(&mut self) -> Result<(), AssetError> {
+        info!("sound asset {} bytes:{}", self.base.location, self.base.raw_data.len());
+        Ok(())
+    }
+
+    fn save(&mut self, _buf: &Buffer) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_mixes_master_channel_and_sound_volume() {
+        let mut a = Audio::new_null();
+        let bgm = a.play(vec![], CHANNEL_MUSIC, true).unwrap();
+        let hit = a.play(vec![], CHANNEL_SFX, false).unwrap();
+        assert_ne!(bgm, hit);
+        assert!(a.is_playing(bgm) && a.is_playing(hit));
+        a.set_master_volume(0.5);
+        a.set_channel_volume(CHANNEL_SFX, 0.5);
+        assert_eq!(a.effective_volume(&a.sounds[&hit.0]), 0.25);
+        assert_eq!(a.effective_volume(&a.sounds[&bgm.0]), 0.5);
+        a.stop(hit);
+        assert!(!a.is_playing(hit));
+    }
+
+    #[test]
+    fn it_pauses_sounds_and_channels() {
+        let mut a = Audio::new_null();
+        let bgm = a.play(vec![], CHANNEL_MUSIC, true).unwrap();
+        let hit = a.play(vec![], CHANNEL_SFX, false).unwrap();
+        a.pause_channel(CHANNEL_MUSIC);
+        assert!(a.is_paused(&a.sounds[&bgm.0]));
+        a.resume_channel(CHANNEL_MUSIC);
+        a.pause(hit);
+        assert!(!a.is_paused(&a.sounds[&bgm.0]) && a.is_paused(&a.sounds[&hit.0]));
+    }
+
+    #[test]
+    fn it_fades_out_and_stops() {
+        let mut a = Audio::new_null();
+        let bgm = a.play(vec![], CHANNEL_MUSIC, true).unwrap();
+        a.fade_out(bgm, 1.0);
+        a.update(0.5);
+        assert_eq!(a.volume(bgm), Some(0.5));
+        a.update(0.5);
+        assert!(!a.is_playing(bgm));
+    }
+
+    #[test]
+    fn it_holds_fades_on_paused_channels() {
+        let mut a = Audio::new_null();
+        let bgm = a.play(vec![], CHANNEL_MUSIC, true).unwrap();
+        a.fade_out(bgm, 1.0);
+        a.pause_channel(CHANNEL_MUSIC);
+        a.update(0.5);
+        assert_eq!(a.volume(bgm), Some(1.0));
+        a.resume_channel(CHANNEL_MUSIC);
+        a.update(0.5);
+        assert_eq!(a.volume(bgm), Some(0.5));
+    }
+
+    #[test]
+    fn it_drops_one_shot_sounds_of_the_null_backend() {
+        let mut a = Audio::new_null();
+        let bgm = a.play(vec![], CHANNEL_MUSIC, true).unwrap();
+        let hit = a.play(vec![], CHANNEL_SFX, false).unwrap();
+        a.update(0.1);
+        assert!(!a.is_playing(hit) && !a.sounds.contains_key(&hit.0));
+        assert!(a.is_playing(bgm));
+        assert_eq!(a.sounds.len(), 1);
+    }
+
+    #[test]
+    fn it_fails_to_play_a_missing_file() {
+        let mut a = Audio::new_null();
+        let am = AssetManager::new();
+        assert!(a.play_file(&am, "no/such/file.mp3", false).is_err());
+    }
 }
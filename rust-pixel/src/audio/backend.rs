@@ -0,0 +1,174 @@
+// RustPixel
+// copyright zhouxin@tuyoogame.com 2022~2024
+
+//! Audio backends play encoded sound data (wav, mp3, ogg, flac).
+//! RodioBackend plays through the default output device,
+//! which is opened at the first play, so a Context without sounds
+//! never touches the audio device.
+//! NullBackend is silent, it is used on platforms without rodio,
+//! in headless contexts and in tests.
+
+#[cfg(all(
+    feature = "rodio",
+    not(any(target_os = "android", target_os = "ios", target_arch = "wasm32"))
+))]
+use {
+    log::info,
+    rodio::{source::Source, Decoder, OutputStream, OutputStreamHandle, Sink},
+};
+use std::collections::HashMap;
+
+pub trait AudioBackend {
+    /// starts playing data as sound id
+    fn play(&mut self, id: u64, data: Vec<u8>, is_loop: bool, volume: f32) -> Result<(), String>;
+    fn stop(&mut self, id: u64);
+    fn set_paused(&mut self, id: u64, paused: bool);
+    fn set_volume(&mut self, id: u64, volume: f32);
+    /// true if the sound has ended or was stopped
+    fn is_finished(&self, id: u64) -> bool;
+    /// called by Audio::update every frame before is_finished
+    fn update(&mut self) {}
+}
+
+/// silent backend, looped sounds play until stopped,
+/// others end at the next update as their length is unknown
+#[derive(Default)]
+pub struct NullBackend {
+    // id -> (paused, volume, is_loop)
+    pub sounds: HashMap<u64, (bool, f32, bool)>,
+}
+
+impl NullBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl AudioBackend for NullBackend {
+    fn play(&mut self, id: u64, _data: Vec<u8>, is_loop: bool, volume: f32) -> Result<(), String> {
+        self.sounds.insert(id, (false, volume, is_loop));
+        Ok(())
+    }
+
+    fn stop(&mut self, id: u64) {
+        self.sounds.remove(&id);
+    }
+
+    fn set_paused(&mut self, id: u64, paused: bool) {
+        if let Some(s) = self.sounds.get_mut(&id) {
+            s.0 = paused;
+        }
+    }
+
+    fn set_volume(&mut self, id: u64, volume: f32) {
+        if let Some(s) = self.sounds.get_mut(&id) {
+            s.1 = volume;
+        }
+    }
+
+    fn is_finished(&self, id: u64) -> bool {
+        !self.sounds.contains_key(&id)
+    }
+
+    fn update(&mut self) {
+        self.sounds.retain(|_, s| s.2);
+    }
+}
+
+/// plays every sound in its own rodio Sink
+#[cfg(all(
+    feature = "rodio",
+    not(any(target_os = "android", target_os = "ios", target_arch = "wasm32"))
+))]
+pub struct RodioBackend {
+    // None until the first play, or if the device failed to open
+    out: Option<(OutputStream, OutputStreamHandle)>,
+    opened: bool,
+    sinks: HashMap<u64, Sink>,
+}
+
+#[cfg(all(
+    feature = "rodio",
+    not(any(target_os = "android", target_os = "ios", target_arch = "wasm32"))
+))]
+impl RodioBackend {
+    /// the default output device is opened at the first play
+    pub fn new() -> Self {
+        Self {
+            out: None,
+            opened: false,
+            sinks: HashMap::new(),
+        }
+    }
+
+    fn open(&mut self) {
+        if self.opened {
+            return;
+        }
+        self.opened = true;
+        match OutputStream::try_default() {
+            Ok(o) => self.out = Some(o),
+            Err(e) => info!("audio: no output device ({}), sounds are muted", e),
+        }
+    }
+}
+
+#[cfg(all(
+    feature = "rodio",
+    not(any(target_os = "android", target_os = "ios", target_arch = "wasm32"))
+))]
+impl Default for RodioBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(
+    feature = "rodio",
+    not(any(target_os = "android", target_os = "ios", target_arch = "wasm32"))
+))]
+impl AudioBackend for RodioBackend {
+    fn play(&mut self, id: u64, data: Vec<u8>, is_loop: bool, volume: f32) -> Result<(), String> {
+        self.open();
+        // without a device the sound has no sink and ends at once
+        let Some((_, handle)) = &self.out else {
+            return Ok(());
+        };
+        let sink = Sink::try_new(handle).map_err(|e| e.to_string())?;
+        let source = Decoder::new(std::io::Cursor::new(data)).map_err(|e| e.to_string())?;
+        sink.set_volume(volume);
+        if is_loop {
+            sink.append(source.repeat_infinite());
+        } else {
+            sink.append(source);
+        }
+        self.sinks.insert(id, sink);
+        Ok(())
+    }
+
+    fn stop(&mut self, id: u64) {
+        if let Some(s) = self.sinks.remove(&id) {
+            s.stop();
+        }
+    }
+
+    fn set_paused(&mut self, id: u64, paused: bool) {
+        if let Some(s) = self.sinks.get(&id) {
+            if paused {
+                s.pause();
+            } else {
+                s.play();
+            }
+        }
+    }
+
+    fn set_volume(&mut self, id: u64, volume: f32) {
+        if let Some(s) = self.sinks.get(&id) {
+            s.set_volume(volume);
+        }
+    }
+
+    fn is_finished(&self, id: u64) -> bool {
+        self.sinks.get(&id).is_none_or(|s| s.empty())
+    }
+}
@@ -0,0 +1,515 @@
+// RustPixel
+// copyright zhouxin@tuyoogame.com 2022~2024
+
+//! A tiny SID-like synthesizer for retro sound effects and tunes,
+//! so games don't have to ship an mp3 for every jump or explosion.
+//!
+//! An Instrument is an oscillator (square with duty, saw, triangle, noise)
+//! shaped by an ADSR envelope, with an optional pitch slide.
+//! A Song is a small tracker pattern: rows of notes played on several
+//! channels, one row every row_time seconds. Songs are written in code or
+//! in a text asset, and rendered offline to samples or a WAV buffer,
+//! which Audio::play accepts like any other sound data.
+//!
+//! # Text format
+//!
+//! # comments start with #
+//! row 0.05                        # seconds per row
+//! inst lead square duty=0.25 adsr=0.005,0.05,0.6,0.1 vol=0.6 slide=0
+//! inst boom noise adsr=0,0.3,0,0.2
+//! # one column per channel
+//! C-4:lead  C-2:boom
+//! E-4:lead  ...
+//! ===       ...
+//!
+//! A note is name, '-' or '#', octave then ':' and the instrument,
+//! e.g. C-4:lead or F#3:bass. "..." keeps the last note playing,
+//! "===" releases it. Missing columns are "...".
+//! adsr is attack, decay, sustain, release: times in seconds,
+//! the sustain level in [0, 1].
+//! slide bends the pitch in semitones per second.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let song = Song::parse(SFX_JUMP)?;
+//! ctx.audio.play(song.to_wav(SYNTH_RATE), CHANNEL_SFX, false)?;
+//!
+//! // a text asset loaded as AssetType::Sound
+//! let raw = &ctx.asset_manager.get("games/snake/assets/eat.sfx").unwrap().get_base().raw_data;
+//! let song = Song::parse(&String::from_utf8_lossy(raw))?;
+//! ```
+
+use std::collections::HashMap;
+
+/// default sample rate of rendered sounds
+pub const SYNTH_RATE: u32 = 22050;
+
+pub const SFX_JUMP: &str = "row 0.04
+inst jump square duty=0.5 adsr=0.002,0.12,0.3,0.05 vol=0.5 slide=36
+C-4:jump
+...
+...
+===
+";
+
+pub const SFX_EXPLOSION: &str = "row 0.05
+inst boom noise adsr=0.002,0.4,0,0.3 vol=0.8 slide=-12
+inst thud triangle adsr=0.002,0.2,0,0.1 vol=0.6 slide=-24
+C-5:boom C-2:thud
+...      ===
+...
+...
+===
+";
+
+pub const SFX_LINE_CLEAR: &str = "row 0.05
+inst blip square duty=0.25 adsr=0.002,0.04,0.5,0.05 vol=0.4
+inst bass saw adsr=0.002,0.1,0.3,0.1 vol=0.3
+C-5:blip C-3:bass
+E-5:blip ...
+G-5:blip ...
+C-6:blip ===
+...
+===
+";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Waveform {
+    Square,
+    Saw,
+    Triangle,
+    Noise,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Adsr {
+    pub attack: f32,
+    pub decay: f32,
+    pub sustain: f32,
+    pub release: f32,
+}
+
+impl Default for Adsr {
+    fn default() -> Self {
+        Self {
+            attack: 0.005,
+            decay: 0.05,
+            sustain: 0.7,
+            release: 0.1,
+        }
+    }
+}
+
+impl Adsr {
+    pub fn new(attack: f32, decay: f32, sustain: f32, release: f32) -> Self {
+        Self {
+            attack: attack.max(0.0),
+            decay: decay.max(0.0),
+            sustain: sustain.clamp(0.0, 1.0),
+            release: release.max(0.0),
+        }
+    }
+
+    // level while the note is held, t seconds after note on
+    fn held_level(&self, t: f32) -> f32 {
+        if t < self.attack {
+            t / self.attack
+        } else if t < self.attack + self.decay {
+            1.0 - (1.0 - self.sustain) * (t - self.attack) / self.decay
+        } else {
+            self.sustain
+        }
+    }
+
+    /// level t seconds after note on, the note was released at off seconds
+    pub fn level(&self, t: f32, off: Option<f32>) -> f32 {
+        match off {
+            Some(o) if t >= o => {
+                if self.release <= 0.0 {
+                    return 0.0;
+                }
+                (self.held_level(o) * (1.0 - (t - o) / self.release)).max(0.0)
+            }
+            _ => self.held_level(t),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instrument {
+    pub wave: Waveform,
+    // high part of a square period, in (0, 1)
+    pub duty: f32,
+    pub adsr: Adsr,
+    pub volume: f32,
+    // semitones per second
+    pub slide: f32,
+}
+
+impl Instrument {
+    pub fn new(wave: Waveform) -> Self {
+        Self {
+            wave,
+            duty: 0.5,
+            adsr: Adsr::default(),
+            volume: 1.0,
+            slide: 0.0,
+        }
+    }
+
+    /// renders one note of freq Hz held for secs seconds, plus its release
+    pub fn render_note(&self, freq: f32, secs: f32, rate: u32) -> Vec<f32> {
+        let mut v = Voice::new(self.clone(), freq, 0);
+        v.off = Some(secs);
+        let n = ((secs + self.adsr.release) * rate as f32).ceil() as usize;
+        (0..n).map(|_| v.next(rate)).collect()
+    }
+}
+
+// a playing note
+struct Voice {
+    inst: Instrument,
+    freq: f32,
+    // samples since note on
+    pos: u64,
+    // seconds after note on when released
+    off: Option<f32>,
+    phase: f32,
+    lfsr: u16,
+    noise: f32,
+}
+
+impl Voice {
+    fn new(inst: Instrument, freq: f32, seed: u16) -> Self {
+        Self {
+            inst,
+            freq,
+            pos: 0,
+            off: None,
+            phase: 0.0,
+            lfsr: 0x4000 | seed,
+            noise: 1.0,
+        }
+    }
+
+    fn finished(&self, rate: u32) -> bool {
+        match self.off {
+            Some(o) => self.pos as f32 / rate as f32 >= o + self.inst.adsr.release,
+            None => false,
+        }
+    }
+
+    fn next(&mut self, rate: u32) -> f32 {
+        let t = self.pos as f32 / rate as f32;
+        self.pos += 1;
+        // a long slide would grow without bound, keep it below nyquist
+        let freq = (self.freq * (self.inst.slide * t / 12.0).exp2()).min(rate as f32 * 0.5);
+        let p = self.phase;
+        let s = match self.inst.wave {
+            Waveform::Square => {
+                if p < self.inst.duty {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Saw => 2.0 * p - 1.0,
+            Waveform::Triangle => 1.0 - 4.0 * (p - 0.5).abs(),
+            Waveform::Noise => self.noise,
+        };
+        // noise is clocked faster than the note, like the SID noise channel
+        let step = if self.inst.wave == Waveform::Noise {
+            freq * 8.0 / rate as f32
+        } else {
+            freq / rate as f32
+        };
+        self.phase += step;
+        if self.phase >= 1.0 {
+            if self.inst.wave == Waveform::Noise {
+                // 15 bit lfsr, as in the NES noise channel
+                for _ in 0..(self.phase as u32).min(16) {
+                    let bit = (self.lfsr ^ (self.lfsr >> 1)) & 1;
+                    self.lfsr = (self.lfsr >> 1) | (bit << 14);
+                }
+                self.noise = if self.lfsr & 1 == 1 { 1.0 } else { -1.0 };
+            }
+            self.phase = self.phase.fract();
+        }
+        s * self.inst.adsr.level(t, self.off) * self.inst.volume
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SongCell {
+    /// frequency in Hz and instrument name
+    Note(f32, String),
+    Hold,
+    Off,
+}
+
+#[derive(Debug, Clone)]
+pub struct Song {
+    // seconds per row
+    pub row_time: f32,
+    pub instruments: HashMap<String, Instrument>,
+    // rows of cells, one per channel
+    pub rows: Vec<Vec<SongCell>>,
+}
+
+/// frequency of a note name like C-4, F#3 or A-4 (440Hz)
+pub fn note_freq(name: &str) -> Result<f32, String> {
+    let bad = || format!("synth: bad note {}", name);
+    let cs: Vec<char> = name.chars().collect();
+    if cs.len() != 3 {
+        return Err(bad());
+    }
+    let base = match cs[0].to_ascii_uppercase() {
+        'C' => 0,
+        'D' => 2,
+        'E' => 4,
+        'F' => 5,
+        'G' => 7,
+        'A' => 9,
+        'B' => 11,
+        _ => return Err(bad()),
+    };
+    let sharp = match cs[1] {
+        '-' => 0,
+        '#' => 1,
+        _ => return Err(bad()),
+    };
+    let oct = cs[2].to_digit(10).ok_or_else(bad)? as i32;
+    let midi = (oct + 1) * 12 + base + sharp;
+    Ok(440.0 * ((midi - 69) as f32 / 12.0).exp2())
+}
+
+impl Song {
+    pub fn new(row_time: f32) -> Self {
+        Self {
+            row_time,
+            instruments: HashMap::new(),
+            rows: vec![],
+        }
+    }
+
+    /// parses the text format, see module doc
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mut song = Song::new(0.1);
+        for (i, l) in s.lines().enumerate() {
+            let l = strip_comment(l);
+            let ts: Vec<&str> = l.split_whitespace().collect();
+            if ts.is_empty() {
+                continue;
+            }
+            let err = |m: &str| format!("synth: line {} {}", i + 1, m);
+            match ts[0] {
+                "row" => {
+                    song.row_time = ts
+                        .get(1)
+                        .and_then(|v| v.parse::<f32>().ok())
+                        .filter(|v| *v > 0.0)
+                        .ok_or_else(|| err("bad row time"))?;
+                }
+                "inst" => {
+                    if ts.len() < 3 {
+                        return Err(err("needs name and wave"));
+                    }
+                    let wave = match ts[2] {
+                        "square" => Waveform::Square,
+                        "saw" => Waveform::Saw,
+                        "triangle" => Waveform::Triangle,
+                        "noise" => Waveform::Noise,
+                        w => return Err(err(&format!("bad wave {}", w))),
+                    };
+                    let mut inst = Instrument::new(wave);
+                    for kv in &ts[3..] {
+                        let (k, v) = kv
+                            .split_once('=')
+                            .ok_or_else(|| err(&format!("bad param {}", kv)))?;
+                        let nums: Vec<f32> = v
+                            .split(',')
+                            .map(|n| n.parse::<f32>())
+                            .collect::<Result<_, _>>()
+                            .map_err(|_| err(&format!("bad number {}", v)))?;
+                        match (k, nums.len()) {
+                            ("duty", 1) => inst.duty = nums[0].clamp(0.01, 0.99),
+                            ("vol", 1) => inst.volume = nums[0].max(0.0),
+                            ("slide", 1) => inst.slide = nums[0],
+                            ("adsr", 4) => {
+                                inst.adsr = Adsr::new(nums[0], nums[1], nums[2], nums[3])
+                            }
+                            _ => return Err(err(&format!("bad param {}", kv))),
+                        }
+                    }
+                    song.instruments.insert(ts[1].to_string(), inst);
+                }
+                _ => {
+                    let mut row = vec![];
+                    for t in ts {
+                        row.push(match t {
+                            "..." => SongCell::Hold,
+                            "===" => SongCell::Off,
+                            _ => {
+                                let (n, inst) = t
+                                    .split_once(':')
+                                    .ok_or_else(|| err(&format!("bad cell {}", t)))?;
+                                if !song.instruments.contains_key(inst) {
+                                    return Err(err(&format!("unknown instrument {}", inst)));
+                                }
+                                SongCell::Note(note_freq(n).map_err(|e| err(&e))?, inst.to_string())
+                            }
+                        });
+                    }
+                    song.rows.push(row);
+                }
+            }
+        }
+        Ok(song)
+    }
+
+    /// renders all channels mixed into mono samples in [-1, 1]
+    pub fn render(&self, rate: u32) -> Vec<f32> {
+        let row_len = (self.row_time * rate as f32).round().max(1.0) as usize;
+        let chs = self.rows.iter().map(|r| r.len()).max().unwrap_or(0);
+        let mut voices: Vec<Option<Voice>> = (0..chs).map(|_| None).collect();
+        let mut out = vec![];
+        let mut ri = 0;
+        loop {
+            if ri < self.rows.len() {
+                for (c, v) in voices.iter_mut().enumerate() {
+                    match self.rows[ri].get(c).unwrap_or(&SongCell::Hold) {
+                        SongCell::Note(f, name) => {
+                            *v = Some(Voice::new(self.instruments[name].clone(), *f, c as u16));
+                        }
+                        SongCell::Off => {
+                            if let Some(v) = v {
+                                if v.off.is_none() {
+                                    v.off = Some(v.pos as f32 / rate as f32);
+                                }
+                            }
+                        }
+                        SongCell::Hold => {}
+                    }
+                }
+            } else {
+                // the song ends, release everything and render the tails
+                for v in voices.iter_mut().flatten() {
+                    if v.off.is_none() {
+                        v.off = Some(v.pos as f32 / rate as f32);
+                    }
+                }
+                if voices.iter().flatten().all(|v| v.finished(rate)) {
+                    break;
+                }
+            }
+            for _ in 0..row_len {
+                let mut s = 0.0;
+                for v in voices.iter_mut().flatten() {
+                    if !v.finished(rate) {
+                        s += v.next(rate);
+                    }
+                }
+                out.push(s.clamp(-1.0, 1.0));
+            }
+            ri += 1;
+        }
+        out
+    }
+
+    /// renders the song as a 16 bit mono WAV file
+    pub fn to_wav(&self, rate: u32) -> Vec<u8> {
+        to_wav(&self.render(rate), rate)
+    }
+}
+
+// cuts a '#' comment, but keeps sharp notes like F#3
+fn strip_comment(l: &str) -> &str {
+    let bs = l.as_bytes();
+    for (i, c) in bs.iter().enumerate() {
+        if *c == b'#' && (i == 0 || bs[i - 1].is_ascii_whitespace()) {
+            return &l[..i];
+        }
+    }
+    l
+}
+
+/// encodes samples in [-1, 1] as a 16 bit mono PCM WAV file
+pub fn to_wav(samples: &[f32], rate: u32) -> Vec<u8> {
+    let data_len = samples.len() as u32 * 2;
+    let mut w = Vec::with_capacity(44 + data_len as usize);
+    w.extend(b"RIFF");
+    w.extend((36 + data_len).to_le_bytes());
+    w.extend(b"WAVEfmt ");
+    w.extend(16u32.to_le_bytes());
+    // pcm, mono
+    w.extend(1u16.to_le_bytes());
+    w.extend(1u16.to_le_bytes());
+    w.extend(rate.to_le_bytes());
+    w.extend((rate * 2).to_le_bytes());
+    w.extend(2u16.to_le_bytes());
+    w.extend(16u16.to_le_bytes());
+    w.extend(b"data");
+    w.extend(data_len.to_le_bytes());
+    for s in samples {
+        w.extend(((s.clamp(-1.0, 1.0) * i16::MAX as f32) as i16).to_le_bytes());
+    }
+    w
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_notes() {
+        assert!((note_freq("A-4").unwrap() - 440.0).abs() < 0.01);
+        assert!((note_freq("C#5").unwrap() - 554.37).abs() < 0.01);
+        assert!(note_freq("H-4").is_err());
+    }
+
+    #[test]
+    fn it_renders_square_waves() {
+        // a 100Hz square wave changes sign every 50 samples at 10000Hz
+        let mut inst = Instrument::new(Waveform::Square);
+        inst.adsr = Adsr::new(0.0, 0.0, 1.0, 0.0);
+        let s = inst.render_note(100.0, 0.1, 10000);
+        assert_eq!(s.len(), 1000);
+        assert_eq!(s.windows(2).filter(|w| w[0] != w[1]).count(), 19);
+    }
+
+    #[test]
+    fn it_keeps_long_slides_below_nyquist() {
+        // 36 semitones per second for 10 seconds would reach 2^30 times the note
+        for wave in [Waveform::Square, Waveform::Noise] {
+            let mut inst = Instrument::new(wave);
+            inst.adsr = Adsr::new(0.0, 0.0, 1.0, 0.0);
+            inst.slide = 36.0;
+            let s = inst.render_note(note_freq("C-4").unwrap(), 10.0, SYNTH_RATE);
+            assert_eq!(s.len(), SYNTH_RATE as usize * 10);
+            assert!(s.iter().all(|v| v.is_finite() && v.abs() <= 1.0));
+        }
+    }
+
+    #[test]
+    fn it_renders_songs_to_wav() {
+        for sfx in [SFX_JUMP, SFX_EXPLOSION, SFX_LINE_CLEAR] {
+            let song = Song::parse(sfx).unwrap();
+            let s = song.render(SYNTH_RATE);
+            assert!(s.len() as f32 >= song.rows.len() as f32 * song.row_time * SYNTH_RATE as f32);
+            assert!(s.iter().any(|v| v.abs() > 0.1));
+            assert_eq!(s, song.render(SYNTH_RATE));
+            let w = song.to_wav(SYNTH_RATE);
+            assert_eq!(&w[0..4], b"RIFF");
+            assert_eq!(&w[8..12], b"WAVE");
+            assert_eq!(w.len(), 44 + s.len() * 2);
+        }
+    }
+
+    #[test]
+    fn it_parses_songs() {
+        let song = Song::parse("row 0.1 # fast\ninst a saw\nF#3:a # sharp\n").unwrap();
+        assert_eq!(song.rows[0].len(), 1);
+        assert!(Song::parse("C-4:nope").is_err());
+    }
+}
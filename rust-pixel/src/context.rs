@@ -7,9 +7,19 @@
 //! Context also integrates an RNG for user's convenience
 //! An render adapter is also provided
 //! to make it compatible with web, SDL, or terminal modes.
+//! new_with_adapter creates a context with an explicitly chosen adapter,
+//! e.g. HeadlessAdapter for running games in tests without a terminal.
+//! fps, max_catch_up and alpha configure the fixed-timestep main loop,
+//! see Game::run for details.
+//! timers are the game timers, they notify through events, see event.rs.
+//! scene_cmds and fade are used by the scene stack in game::scene.
+//! audio is the sound mixer, see audio::Audio.
+//! palette is the active palette of indexed colors, see render::palette.
+//! atlases holds the glyph atlases of graphical mode, see render::atlas.
 //! Finally, an asset_manager is included as well.
 
-use crate::event::Event;
+use crate::event::{Event, EventBus, TimerEvent, Timers};
+use crate::game::scene::SceneCmd;
 #[cfg(all(not(target_arch = "wasm32"), not(feature = "sdl")))]
 use crate::render::adapter::cross::CrosstermAdapter;
 #[cfg(all(not(target_arch = "wasm32"), feature = "sdl"))]
@@ -17,8 +27,14 @@ use crate::render::adapter::sdl::SdlAdapter;
 #[cfg(target_arch = "wasm32")]
 use crate::render::adapter::web::WebAdapter;
 use crate::render::adapter::Adapter;
+use crate::render::{atlas::AtlasRegistry, palette::Palette};
 use crate::util::Rand;
 use crate::asset::AssetManager;
+use crate::audio::Audio;
+use crate::{GAME_FRAME, LOGO_SECS};
+
+/// max fixed steps run by one tick, the rest of a long stall is dropped
+pub const MAX_CATCH_UP: u32 = 5;
 
 pub struct Context {
     pub game_name: String,
@@ -26,25 +42,84 @@ pub struct Context {
     pub state: u8,
     pub rand: Rand,
     pub asset_manager: AssetManager,
+    pub audio: Audio,
+    pub palette: Palette,
+    pub atlases: AtlasRegistry,
     pub input_events: Vec<Event>,
+    // typed game events, see event::EventBus
+    pub events: EventBus,
+    // timers of this game, they fire TimerEvent on events
+    pub timers: Timers,
     pub adapter: Box<dyn Adapter>,
+    // fixed update steps per second
+    pub fps: u32,
+    // max fixed steps per tick when catching up
+    pub max_catch_up: u32,
+    // interpolation factor between the last two fixed steps, in [0, 1)
+    pub alpha: f32,
+    // scene transitions requested by scenes, handled by SceneStack
+    pub scene_cmds: Vec<SceneCmd>,
+    // screen fade level applied by Panel::draw, 0.0 none, 1.0 black
+    pub fade: f32,
 }
 
 impl Context {
     pub fn new(name: &str) -> Self {
+        #[cfg(target_arch = "wasm32")]
+        let adapter = Box::new(WebAdapter::new(name));
+        #[cfg(all(not(target_arch = "wasm32"), feature = "sdl"))]
+        let adapter = Box::new(SdlAdapter::new(name));
+        #[cfg(all(not(target_arch = "wasm32"), not(feature = "sdl")))]
+        let adapter = Box::new(CrosstermAdapter::new(name));
+        let mut ctx = Self::new_with_adapter(name, adapter);
+        ctx.audio = Audio::new();
+        ctx
+    }
+
+    pub fn new_with_adapter(name: &str, adapter: Box<dyn Adapter>) -> Self {
         Self {
             game_name: name.to_string(),
             stage: 0,
             state: 0,
             rand: Rand::new(),
             asset_manager: AssetManager::new(),
+            // explicit adapters are used by tests and tools, keep them silent
+            audio: Audio::new_null(),
+            palette: Palette::xterm(),
+            atlases: AtlasRegistry::new(),
             input_events: vec![],
-            #[cfg(target_arch = "wasm32")]
-            adapter: Box::new(WebAdapter::new(name)),
-            #[cfg(all(not(target_arch = "wasm32"), feature = "sdl"))]
-            adapter: Box::new(SdlAdapter::new(name)),
-            #[cfg(all(not(target_arch = "wasm32"), not(feature = "sdl")))]
-            adapter: Box::new(CrosstermAdapter::new(name)),
+            events: EventBus::new(),
+            timers: Timers::new(),
+            adapter,
+            fps: GAME_FRAME,
+            max_catch_up: MAX_CATCH_UP,
+            alpha: 0.0,
+            scene_cmds: vec![],
+            fade: 0.0,
+        }
+    }
+
+    /// sets the fixed update rate, e.g. 30 or 60
+    pub fn set_fps(&mut self, fps: u32) {
+        self.fps = fps.max(1);
+    }
+
+    /// seconds of one fixed update step
+    pub fn frame_dt(&self) -> f32 {
+        1.0 / self.fps as f32
+    }
+
+    /// fixed steps the logo is shown for, LOGO_SECS at the current fps
+    pub fn logo_frames(&self) -> u32 {
+        (LOGO_SECS / self.frame_dt()).round() as u32
+    }
+
+    /// advances the timers by dt seconds and sends a TimerEvent for each
+    /// timer fired since the last call, called by Model::update every step
+    pub fn update_timers(&mut self, dt: f32) {
+        self.timers.update(dt);
+        for name in self.timers.take_fired() {
+            self.events.emit(TimerEvent(name));
         }
     }
 }
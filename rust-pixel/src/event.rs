@@ -2,121 +2,68 @@
 // copyright zhouxin@tuyoogame.com 2022~2024
 
 
-//! this event module provides a global event centre and a global timer centre.
-//! It is based on Mutex. Mutex is easy to use despite a tiny loss of performance.
-//! Another way to achieve this is to put Event and Timer in context. However, it
-//! requires access to context from everywhere, which is again not ideal
+//! this event module provides the timers and the typed EventBus,
+//! both owned by Context (context.timers and context.events), so several
+//! Game instances in one process, e.g. parallel tests, never share them.
 //!
 //! one event in this class namely Event, describing I/Os from keyboard and mouse
 //! Input events triggered by renders such as web, sdl or cross are converted here to
 //! unified Event
-
+//!
+//! Game events use the EventBus, it carries payloads and keeps every
+//! emission for each subscriber. A timer that times out, or is cancelled
+//! with its call, is sent on the bus as a TimerEvent when the timers are
+//! updated by Model::update.
+//!
+//! # Example
+//!
+//! ```ignore
+//! // init
+//! ctx.timers.register("Snake.TestTimer", 0.1);
+//! ctx.timers.fire("Snake.TestTimer", 8u8);
+//! ctx.events.subscribe::<TimerEvent>("render");
+//! // handle_timer
+//! for TimerEvent(name) in ctx.events.drain::<TimerEvent>("render") { ... }
+//! ```
 
 use crate::GAME_FRAME;
-use lazy_static::lazy_static;
-use serde::Serialize;
-use std::{collections::HashMap, sync::Mutex};
-
-// uses global Mutex variable
-lazy_static! {
-    pub static ref GAME_TIMER: Mutex<Timers> = Mutex::new(Timers::new());
-    pub static ref EVENT_CENTER: Mutex<HashMap<String, HashMap<String, bool>>> =
-        Mutex::new(HashMap::new());
-}
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
-/// A global HashMap is used to save callbacks of events
-pub fn event_register(event: &str, func: &str) {
-    let mut ec = EVENT_CENTER.lock().unwrap();
-    match ec.get_mut(event) {
-        Some(ht) => {
-            ht.insert(func.to_string(), false);
-        }
-        None => {
-            let mut h: HashMap<String, bool> = HashMap::new();
-            h.insert(func.to_string(), false);
-            ec.insert(event.to_string(), h);
-        }
-    }
-}
+/// sent on context.events with the name of a timer that timed out,
+/// or was cancelled with its call
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimerEvent(pub String);
 
-pub fn event_check(event: &str, func: &str) -> bool {
-    let mut ec = EVENT_CENTER.lock().unwrap();
-    match ec.get_mut(event) {
-        Some(ht) => match ht.get_mut(func) {
-            Some(flag) => {
-                if *flag {
-                    *flag = false;
-                    return true;
-                }
-            }
-            None => {}
-        },
-        None => {}
-    }
-    false
-}
-
-pub fn event_emit(event: &str) {
-    match EVENT_CENTER.lock().unwrap().get_mut(event) {
-        Some(ht) => {
-            for (_key, value) in ht {
-                if *value == false {
-                    *value = true;
-                }
-            }
-        }
-        None => {}
-    }
-}
-
-pub fn timer_register(name: &str, time: f32, func: &str) {
-    GAME_TIMER.lock().unwrap().register(name, time, func);
-}
-
-pub fn timer_set_time(name: &str, time: f32) {
-    GAME_TIMER.lock().unwrap().set_time(name, time);
-}
-
-pub fn timer_stage(name: &str) -> u32 {
-    GAME_TIMER.lock().unwrap().stage(name)
-}
-
-pub fn timer_rstage(name: &str) -> u32 {
-    GAME_TIMER.lock().unwrap().rstage(name)
-}
-
-pub fn timer_percent(name: &str) -> f32 {
-    GAME_TIMER.lock().unwrap().percent(name)
-}
-
-pub fn timer_exdata(name: &str) -> Option<Vec<u8>> {
-    GAME_TIMER.lock().unwrap().exdata(name)
-}
-
-pub fn timer_fire<T>(name: &str, value: T)
-where
-    T: Serialize,
-{
-    GAME_TIMER.lock().unwrap().fire(name, value);
-}
-
-pub fn timer_cancel(name: &str, nall: bool) {
-    GAME_TIMER.lock().unwrap().cancel(name, nall);
-}
-
-pub fn timer_update() {
-    GAME_TIMER.lock().unwrap().update()
-}
+// tolerance of float rounding when counting down,
+// e.g. 0.1s must fire after exactly 6 steps of 1/60s
+const TIMER_EPSILON: f32 = 1e-4;
 
+/// time and count are in seconds, so timers keep the same duration
+/// whatever the fps of the game is
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Timer {
-    time: u32,
-    count: u32,
+    time: f32,
+    count: f32,
     exdata: Vec<u8>,
 }
 
-#[derive(Default)]
 pub struct Timers {
     pub timers: HashMap<String, Timer>,
+    // dt of the last update, used to convert seconds to frames
+    pub frame_dt: f32,
+    // timers timed out or cancelled with call, not yet sent as TimerEvent
+    fired: Vec<String>,
+}
+
+impl Default for Timers {
+    fn default() -> Self {
+        Self {
+            timers: HashMap::new(),
+            frame_dt: 1.0 / GAME_FRAME as f32,
+            fired: vec![],
+        }
+    }
 }
 
 impl Timers {
@@ -124,62 +71,69 @@ impl Timers {
         Self { ..Self::default() }
     }
 
-    pub fn register(&mut self, name: &str, time: f32, callback: &str) {
+    pub fn register(&mut self, name: &str, time: f32) {
         match self.timers.get_mut(name) {
             Some(_) => {}
             None => {
                 let timer = Timer {
-                    time: 0,
-                    count: (time * GAME_FRAME as f32) as u32,
+                    time: 0.0,
+                    count: time,
                     exdata: vec![],
                 };
                 self.timers.insert(name.to_string(), timer);
-                event_register(name, callback);
             }
         }
     }
 
+    fn to_frames(&self, t: f32) -> u32 {
+        ((t - TIMER_EPSILON) / self.frame_dt).ceil().max(0.0) as u32
+    }
+
     pub fn stage(&mut self, name: &str) -> u32 {
-        match self.timers.get_mut(name) {
-            Some(timer) => {
-                return timer.time;
-            }
-            None => {
-                return 0;
-            }
+        match self.timers.get(name) {
+            Some(timer) => self.to_frames(timer.time),
+            None => 0,
         }
     }
 
     pub fn rstage(&mut self, name: &str) -> u32 {
-        match self.timers.get_mut(name) {
-            Some(timer) => {
-                return timer.count - timer.time;
-            }
-            None => {
-                return 0;
-            }
+        match self.timers.get(name) {
+            Some(timer) => self
+                .to_frames(timer.count)
+                .saturating_sub(self.to_frames(timer.time)),
+            None => 0,
+        }
+    }
+
+    pub fn remaining(&mut self, name: &str) -> f32 {
+        match self.timers.get(name) {
+            Some(timer) => timer.time,
+            None => 0.0,
+        }
+    }
+
+    pub fn elapsed(&mut self, name: &str) -> f32 {
+        match self.timers.get(name) {
+            Some(timer) => timer.count - timer.time,
+            None => 0.0,
         }
     }
 
     pub fn percent(&mut self, name: &str) -> f32 {
-        match self.timers.get_mut(name) {
-            Some(timer) => {
-                return timer.time as f32 / timer.count as f32;
-            }
-            None => {
-                return 0f32;
-            }
+        match self.timers.get(name) {
+            Some(timer) => timer.time / timer.count,
+            None => 0f32,
         }
     }
 
     pub fn set_time(&mut self, name: &str, time: f32) {
         match self.timers.get_mut(name) {
             Some(timer) => {
-                timer.count = (time * GAME_FRAME as f32) as u32;
-                //may cause count equals 0 and therefore can not be triggered if time is too small
-                //to prevent this, reset the count to 1
-                if timer.count == 0 {
-                    timer.count += 1;
+                timer.count = time;
+                //a zero count can not be triggered if time is too small
+                //to prevent this, the count is at least one step
+                if timer.count < TIMER_EPSILON {
+                    timer.count = TIMER_EPSILON;
                 }
             }
             None => {}
@@ -213,26 +167,94 @@ impl Timers {
     pub fn cancel(&mut self, name: &str, nocall: bool) {
         match self.timers.get_mut(name) {
             Some(timer) => {
-                timer.time = 0;
+                timer.time = 0.0;
                 if !nocall {
-                    event_emit(name);
+                    self.fired.push(name.to_string());
                 }
             }
             None => {}
         }
     }
 
-    pub fn update(&mut self) {
+    pub fn save(&self) -> HashMap<String, Timer> {
+        self.timers.clone()
+    }
+
+    /// restores timers saved by save, pending TimerEvents are dropped
+    pub fn load(&mut self, ts: &HashMap<String, Timer>) {
+        self.timers.clone_from(ts);
+        self.fired.clear();
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        self.frame_dt = dt;
         for (name, timer) in &mut self.timers {
-            if timer.time > 0 {
-                timer.time -= 1;
-                if timer.time == 0 {
-                    event_emit(name);
+            if timer.time > 0.0 {
+                timer.time -= dt;
+                if timer.time < TIMER_EPSILON {
+                    timer.time = 0.0;
+                    self.fired.push(name.clone());
                 }
             }
         }
+        // timers are kept in a HashMap, sorts them for a stable order
+        self.fired.sort();
+    }
+
+    /// takes the names of the timers fired since the last call
+    pub fn take_fired(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.fired)
     }
 }
 
 mod input;
 pub use input::*;
+
+mod bus;
+pub use bus::EventBus;
+
+mod action;
+pub use action::{ActionMap, ActionState, InputBinding};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_counts_timer_stages_in_seconds() {
+        let mut ts = Timers::new();
+        ts.register("t.a", 0.1);
+        ts.fire("t.a", 0u8);
+        // 0.1s at 60fps
+        for _ in 0..5 {
+            ts.update(1.0 / 60.0);
+        }
+        assert_eq!(ts.stage("t.a"), 1);
+        assert_eq!(ts.rstage("t.a"), 5);
+        // count set below the running time
+        ts.set_time("t.a", 0.0);
+        assert_eq!(ts.rstage("t.a"), 0);
+        ts.update(1.0 / 60.0);
+        assert_eq!(ts.stage("t.a"), 0);
+        assert_eq!(ts.take_fired(), vec!["t.a".to_string()]);
+        assert!(ts.take_fired().is_empty());
+    }
+
+    #[test]
+    fn it_times_out_after_the_same_seconds_at_any_fps() {
+        let mut ts = Timers::new();
+        ts.register("t.b", 0.1);
+        ts.fire("t.b", 0u8);
+        for _ in 0..2 {
+            ts.update(1.0 / 30.0);
+        }
+        assert!(ts.remaining("t.b") > 0.0);
+        ts.update(1.0 / 30.0);
+        assert_eq!(ts.remaining("t.b"), 0.0);
+        assert_eq!(ts.take_fired(), vec!["t.b".to_string()]);
+        // a cancel with call fires too
+        ts.fire("t.b", 0u8);
+        ts.cancel("t.b", false);
+        assert_eq!(ts.take_fired(), vec!["t.b".to_string()]);
+    }
+}
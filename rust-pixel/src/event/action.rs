@@ -0,0 +1,525 @@
+// RustPixel
+// copyright zhouxin@tuyoogame.com 2022~2024
+
+//! Maps keys, mouse buttons and modifiers to named actions,
+//! so games check "left" or "rotate" instead of raw KeyCode values,
+//! and players can rebind controls in a config file.
+//!
+//! Call ActionMap::update with the input events once per frame,
+//! then query pressed / held / released / triggered / triggers for each action.
+//! triggered is true on press and then repeats every repeat_interval seconds
+//! after repeat_delay while the action is held.
+//!
+//! The SDL and web adapters send key Release events and drop the OS repeats,
+//! terminals only report key presses (repeated by the OS while a key is down).
+//! Until the adapter sends a key Release event, every key press is a fresh
+//! press and trigger, as a repeat can't be told from pressing the key again,
+//! and the key counts as held until no press arrives for hold_timeout seconds.
+//! Such a key triggers at the rate the OS repeats it, repeat_delay and
+//! repeat_interval only apply to keys with release events and mouse buttons.
+//!
+//! # Config file
+//!
+//! ```text
+//! # comments start with #
+//! repeat_delay = 0.2
+//! repeat_interval = 0.05
+//! hold_timeout = 0.6
+//! left = j, Left
+//! rotate = i, Ctrl+r, MouseLeft
+//! ```
+//!
+//! Key names: single chars, Space, Comma, Hash, Equals, Enter, Esc, Tab,
+//! BackTab, Backspace, Delete, Insert, Home, End, PageUp, PageDown, Left,
+//! Right, Up, Down, F1~F12, CapsLock, ScrollLock, NumLock, PrintScreen,
+//! Pause, Menu, KeypadBegin, Null, the modifier keys LeftShift~RightMeta,
+//! and U+ with the hex code of any other whitespace or control char.
+//! Modifier prefixes: Ctrl+, Alt+, Shift+. Mouse: MouseLeft, MouseRight, MouseMiddle.
+
+use crate::event::{
+    Event, KeyCode, KeyEventKind, KeyModifiers, ModifierKeyCode, MouseButton, MouseEventKind,
+};
+use std::collections::HashMap;
+
+// keys not written as their char, ',' '#' and '=' are part of the config format
+const KEY_NAMES: &[(&str, KeyCode)] = &[
+    ("Space", KeyCode::Char(' ')),
+    ("Comma", KeyCode::Char(',')),
+    ("Hash", KeyCode::Char('#')),
+    ("Equals", KeyCode::Char('=')),
+    ("Enter", KeyCode::Enter),
+    ("Esc", KeyCode::Esc),
+    ("Tab", KeyCode::Tab),
+    ("BackTab", KeyCode::BackTab),
+    ("Backspace", KeyCode::Backspace),
+    ("Delete", KeyCode::Delete),
+    ("Insert", KeyCode::Insert),
+    ("Home", KeyCode::Home),
+    ("End", KeyCode::End),
+    ("PageUp", KeyCode::PageUp),
+    ("PageDown", KeyCode::PageDown),
+    ("Left", KeyCode::Left),
+    ("Right", KeyCode::Right),
+    ("Up", KeyCode::Up),
+    ("Down", KeyCode::Down),
+    ("Null", KeyCode::Null),
+    ("CapsLock", KeyCode::CapsLock),
+    ("ScrollLock", KeyCode::ScrollLock),
+    ("NumLock", KeyCode::NumLock),
+    ("PrintScreen", KeyCode::PrintScreen),
+    ("Pause", KeyCode::Pause),
+    ("Menu", KeyCode::Menu),
+    ("KeypadBegin", KeyCode::KeypadBegin),
+    ("LeftShift", KeyCode::Modifier(ModifierKeyCode::LeftShift)),
+    ("LeftControl", KeyCode::Modifier(ModifierKeyCode::LeftControl)),
+    ("LeftAlt", KeyCode::Modifier(ModifierKeyCode::LeftAlt)),
+    ("LeftSuper", KeyCode::Modifier(ModifierKeyCode::LeftSuper)),
+    ("LeftHyper", KeyCode::Modifier(ModifierKeyCode::LeftHyper)),
+    ("LeftMeta", KeyCode::Modifier(ModifierKeyCode::LeftMeta)),
+    ("RightShift", KeyCode::Modifier(ModifierKeyCode::RightShift)),
+    ("RightControl", KeyCode::Modifier(ModifierKeyCode::RightControl)),
+    ("RightAlt", KeyCode::Modifier(ModifierKeyCode::RightAlt)),
+    ("RightSuper", KeyCode::Modifier(ModifierKeyCode::RightSuper)),
+    ("RightHyper", KeyCode::Modifier(ModifierKeyCode::RightHyper)),
+    ("RightMeta", KeyCode::Modifier(ModifierKeyCode::RightMeta)),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputBinding {
+    Key(KeyCode, KeyModifiers),
+    Mouse(MouseButton),
+}
+
+impl InputBinding {
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        let mut mods = KeyModifiers::NONE;
+        let mut key = name.trim();
+        loop {
+            if let Some(k) = key.strip_prefix("Ctrl+") {
+                mods |= KeyModifiers::CONTROL;
+                key = k;
+            } else if let Some(k) = key.strip_prefix("Alt+") {
+                mods |= KeyModifiers::ALT;
+                key = k;
+            } else if let Some(k) = key.strip_prefix("Shift+") {
+                mods |= KeyModifiers::SHIFT;
+                key = k;
+            } else {
+                break;
+            }
+        }
+        let bad = || format!("action: bad key {}", name);
+        let code = match key {
+            "MouseLeft" => return Ok(InputBinding::Mouse(MouseButton::Left)),
+            "MouseRight" => return Ok(InputBinding::Mouse(MouseButton::Right)),
+            "MouseMiddle" => return Ok(InputBinding::Mouse(MouseButton::Middle)),
+            _ => {
+                let mut cs = key.chars();
+                if let Some((_, code)) = KEY_NAMES.iter().find(|(n, _)| *n == key) {
+                    *code
+                } else if let Some(h) = key.strip_prefix("U+") {
+                    let c = u32::from_str_radix(h, 16).ok().and_then(char::from_u32);
+                    KeyCode::Char(c.ok_or_else(bad)?)
+                } else {
+                    match (cs.next(), cs.next()) {
+                        (Some(c), None) => KeyCode::Char(c),
+                        (Some('F'), Some(_)) => {
+                            KeyCode::F(key[1..].parse::<u8>().map_err(|_| bad())?)
+                        }
+                        _ => return Err(bad()),
+                    }
+                }
+            }
+        };
+        Ok(InputBinding::Key(code, mods))
+    }
+
+    pub fn to_name(&self) -> String {
+        match self {
+            InputBinding::Mouse(b) => format!("Mouse{:?}", b),
+            InputBinding::Key(code, mods) => {
+                let mut s = String::new();
+                if mods.contains(KeyModifiers::CONTROL) {
+                    s.push_str("Ctrl+");
+                }
+                if mods.contains(KeyModifiers::ALT) {
+                    s.push_str("Alt+");
+                }
+                if mods.contains(KeyModifiers::SHIFT) {
+                    s.push_str("Shift+");
+                }
+                match (KEY_NAMES.iter().find(|(_, k)| k == code), code) {
+                    (Some((n, _)), _) => s.push_str(n),
+                    (None, KeyCode::Char(c)) if c.is_whitespace() || c.is_control() => {
+                        s.push_str(&format!("U+{:X}", *c as u32))
+                    }
+                    (None, KeyCode::Char(c)) => s.push(*c),
+                    (None, KeyCode::F(n)) => s.push_str(&format!("F{}", n)),
+                    // every other key is in KEY_NAMES
+                    (None, _) => s.push_str(&format!("{:?}", code)),
+                }
+                s
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ActionState {
+    pub pressed: bool,
+    pub released: bool,
+    pub held: bool,
+    pub triggered: bool,
+    // times triggered this frame, several presses can arrive in one frame
+    pub triggers: u32,
+    // seconds since pressed
+    pub held_time: f32,
+    // seconds since the last press event of a key without release events,
+    // None if the binding reports releases
+    idle: Option<f32>,
+    // held_time of the next repeat
+    next_repeat: f32,
+}
+
+pub struct ActionMap {
+    pub bindings: HashMap<String, Vec<InputBinding>>,
+    pub repeat_delay: f32,
+    pub repeat_interval: f32,
+    pub hold_timeout: f32,
+    pub states: HashMap<String, ActionState>,
+    // set by the first key Release event, adapters without them
+    // can't tell a repeat from a new press
+    key_release: bool,
+}
+
+impl Default for ActionMap {
+    fn default() -> Self {
+        Self {
+            bindings: HashMap::new(),
+            repeat_delay: 0.2,
+            repeat_interval: 0.05,
+            // longer than the usual OS key repeat delay
+            hold_timeout: 0.6,
+            states: HashMap::new(),
+            key_release: false,
+        }
+    }
+}
+
+impl ActionMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn bind(&mut self, action: &str, b: InputBinding) {
+        let bs = self.bindings.entry(action.to_string()).or_default();
+        if !bs.contains(&b) {
+            bs.push(b);
+        }
+    }
+
+    pub fn unbind(&mut self, action: &str) {
+        self.bindings.remove(action);
+        self.states.remove(action);
+    }
+
+    /// parses bindings from config text, see module doc for the format
+    pub fn from_config(s: &str) -> Result<Self, String> {
+        let mut am = ActionMap::new();
+        am.merge_config(s)?;
+        Ok(am)
+    }
+
+    /// applies config text, actions in it replace existing bindings
+    pub fn merge_config(&mut self, s: &str) -> Result<(), String> {
+        for (i, l) in s.lines().enumerate() {
+            let l = l.trim();
+            if l.is_empty() || l.starts_with('#') {
+                continue;
+            }
+            let (k, v) = l
+                .split_once('=')
+                .ok_or_else(|| format!("action: line {} needs '='", i + 1))?;
+            let (k, v) = (k.trim(), v.trim());
+            let num = || {
+                v.parse::<f32>()
+                    .map_err(|_| format!("action: line {} bad number {}", i + 1, v))
+            };
+            match k {
+                "repeat_delay" => self.repeat_delay = num()?,
+                "repeat_interval" => self.repeat_interval = num()?,
+                "hold_timeout" => self.hold_timeout = num()?,
+                _ => {
+                    self.unbind(k);
+                    for n in v.split(',') {
+                        self.bind(k, InputBinding::from_name(n)?);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// loads and applies a config file
+    pub fn load(&mut self, path: &str) -> Result<(), String> {
+        let s = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        self.merge_config(&s)
+    }
+
+    pub fn to_config(&self) -> String {
+        let mut s = format!(
+            "repeat_delay = {}\nrepeat_interval = {}\nhold_timeout = {}\n",
+            self.repeat_delay, self.repeat_interval, self.hold_timeout
+        );
+        let mut names: Vec<&String> = self.bindings.keys().collect();
+        names.sort();
+        for n in names {
+            let bs: Vec<String> = self.bindings[n].iter().map(|b| b.to_name()).collect();
+            s.push_str(&format!("{} = {}\n", n, bs.join(", ")));
+        }
+        s
+    }
+
+    // (action, pressed(true) or released(false), has release event)
+    // mouse buttons always report release, keys only if the adapter
+    // sent a Release event before, else they are released by hold_timeout
+    fn match_event(&self, e: &Event) -> Vec<(String, bool, bool)> {
+        let mut r = vec![];
+        for (name, bs) in &self.bindings {
+            for b in bs {
+                let m = match (b, e) {
+                    (InputBinding::Key(code, mods), Event::Key(k))
+                        if k.code == *code && k.modifiers == *mods =>
+                    {
+                        Some((k.kind != KeyEventKind::Release, self.key_release))
+                    }
+                    (InputBinding::Mouse(mb), Event::Mouse(me)) => match me.kind {
+                        MouseEventKind::Down(b) if b == *mb => Some((true, true)),
+                        MouseEventKind::Up(b) if b == *mb => Some((false, true)),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                if let Some((down, rel)) = m {
+                    r.push((name.clone(), down, rel));
+                    break;
+                }
+            }
+        }
+        r
+    }
+
+    /// updates action states with the input events of this frame
+    pub fn update(&mut self, es: &[Event], dt: f32) {
+        for st in self.states.values_mut() {
+            st.pressed = false;
+            st.released = false;
+            st.triggered = false;
+            st.triggers = 0;
+            if st.held {
+                st.held_time += dt;
+                if let Some(i) = &mut st.idle {
+                    *i += dt;
+                }
+            }
+        }
+        for e in es {
+            if let Event::Key(k) = e {
+                if k.kind == KeyEventKind::Release {
+                    self.key_release = true;
+                }
+            }
+            for (name, down, rel) in self.match_event(e) {
+                let st = self.states.entry(name).or_default();
+                if down {
+                    if !st.held || !rel {
+                        st.held = true;
+                        st.pressed = true;
+                        st.triggered = true;
+                        st.triggers += 1;
+                        st.held_time = 0.0;
+                        st.next_repeat = self.repeat_delay;
+                    }
+                    // held until released, never time out
+                    st.idle = if rel { None } else { Some(0.0) };
+                } else if st.held {
+                    st.held = false;
+                    st.released = true;
+                }
+            }
+        }
+        for st in self.states.values_mut() {
+            if !st.held {
+                continue;
+            }
+            if let Some(i) = st.idle {
+                // the os repeats it, every press triggers
+                if i > self.hold_timeout {
+                    st.held = false;
+                    st.released = true;
+                }
+                continue;
+            }
+            if !st.pressed && st.held_time >= st.next_repeat {
+                st.triggered = true;
+                st.triggers += 1;
+                st.next_repeat += self.repeat_interval.max(0.001);
+            }
+        }
+    }
+
+    /// true in the frame the action starts
+    pub fn pressed(&self, action: &str) -> bool {
+        self.states.get(action).is_some_and(|s| s.pressed)
+    }
+
+    /// true while the action is down
+    pub fn held(&self, action: &str) -> bool {
+        self.states.get(action).is_some_and(|s| s.held)
+    }
+
+    /// true in the frame the action ends
+    pub fn released(&self, action: &str) -> bool {
+        self.states.get(action).is_some_and(|s| s.released)
+    }
+
+    /// true on press and on every key repeat
+    pub fn triggered(&self, action: &str) -> bool {
+        self.states.get(action).is_some_and(|s| s.triggered)
+    }
+
+    /// how many times the action triggered this frame,
+    /// games that act once per press use this instead of triggered
+    pub fn triggers(&self, action: &str) -> u32 {
+        self.states.get(action).map_or(0, |s| s.triggers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::KeyEvent;
+
+    fn key(c: char) -> Event {
+        Event::Key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE))
+    }
+
+    fn release(c: char) -> Event {
+        Event::Key(KeyEvent::new_with_kind(
+            KeyCode::Char(c),
+            KeyModifiers::NONE,
+            KeyEventKind::Release,
+        ))
+    }
+
+    fn test_map() -> ActionMap {
+        ActionMap::from_config(
+            "# test\nrepeat_delay = 0.2\nrepeat_interval = 0.1\nhold_timeout = 0.55\nleft = j, Left\nquit = Ctrl+q\n",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn it_reads_and_writes_config() {
+        let am = test_map();
+        assert_eq!(
+            ActionMap::from_config(&am.to_config()).unwrap().to_config(),
+            am.to_config()
+        );
+        assert_eq!(am.hold_timeout, 0.55);
+        assert_eq!(am.bindings["left"].len(), 2);
+        assert!(ActionMap::from_config("left = Foo").is_err());
+        assert!(ActionMap::from_config("left j").is_err());
+    }
+
+    #[test]
+    fn it_round_trips_keys_of_the_config_format() {
+        let mut am = ActionMap::new();
+        let keys = [
+            KeyCode::Char(','),
+            KeyCode::Char('#'),
+            KeyCode::Char('='),
+            KeyCode::Char('+'),
+            KeyCode::Char('\u{a0}'),
+            KeyCode::BackTab,
+            KeyCode::Modifier(ModifierKeyCode::LeftShift),
+        ];
+        for k in keys {
+            am.bind("a", InputBinding::Key(k, KeyModifiers::CONTROL));
+        }
+        let nam = ActionMap::from_config(&am.to_config()).unwrap();
+        assert_eq!(nam.bindings, am.bindings);
+        assert!(am.to_config().contains("Ctrl+Comma, Ctrl+Hash, Ctrl+Equals, Ctrl++, Ctrl+U+A0"));
+    }
+
+    #[test]
+    fn it_presses_again_within_hold_timeout() {
+        let mut am = test_map();
+        am.update(&[key('j')], 0.1);
+        assert!(am.pressed("left") && am.triggered("left") && am.held("left"));
+        assert!(!am.pressed("quit"));
+        am.update(&[], 0.1);
+        assert!(am.held("left") && !am.pressed("left") && !am.triggered("left"));
+        // without release events a second press can't be told from os repeat
+        am.update(&[key('j')], 0.1);
+        assert!(am.pressed("left") && am.triggered("left"));
+    }
+
+    #[test]
+    fn it_releases_keys_after_hold_timeout() {
+        let mut am = test_map();
+        am.update(&[key('j')], 0.1);
+        let mut frames = 0;
+        while !am.released("left") {
+            am.update(&[], 0.1);
+            frames += 1;
+        }
+        assert_eq!(frames, 6);
+        assert!(!am.held("left"));
+    }
+
+    #[test]
+    fn it_triggers_keys_without_release_events_on_each_press_only() {
+        let mut am = test_map();
+        am.update(&[key('j')], 0.1);
+        let mut ts = vec![];
+        for i in 0..6 {
+            // os repeat every 0.1 seconds after a pause
+            let es = if i >= 2 { vec![key('j')] } else { vec![] };
+            am.update(&es, 0.1);
+            ts.push(am.triggered("left"));
+        }
+        assert_eq!(ts, vec![false, false, true, true, true, true]);
+    }
+
+    #[test]
+    fn it_repeats_keys_held_until_release_events() {
+        let mut am = test_map();
+        am.update(&[release('x')], 0.1);
+        am.update(&[key('j')], 0.1);
+        assert!(am.pressed("left"));
+        let mut ts = vec![];
+        for _ in 0..4 {
+            am.update(&[], 0.1);
+            ts.push(am.triggered("left"));
+        }
+        assert_eq!(ts, vec![false, true, true, true]);
+        assert!(am.held("left") && !am.pressed("left"));
+        am.update(&[release('j')], 0.1);
+        assert!(am.released("left") && !am.held("left"));
+    }
+
+    #[test]
+    fn it_counts_presses_within_one_frame() {
+        let mut am = test_map();
+        am.update(&[key('j'), key('j'), key('x'), key('j')], 0.1);
+        assert!(am.triggered("left"));
+        assert_eq!(am.triggers("left"), 3);
+        assert_eq!(am.triggers("quit"), 0);
+        am.update(&[], 0.1);
+        assert_eq!(am.triggers("left"), 0);
+        // with release events a repeated press is a new press too
+        am.update(&[release('j'), key('j'), release('j'), key('j')], 0.1);
+        assert_eq!(am.triggers("left"), 2);
+    }
+}
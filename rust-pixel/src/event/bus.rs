@@ -0,0 +1,228 @@
+// RustPixel
+// copyright zhouxin@tuyoogame.com 2022~2024
+
+//! Typed event bus, owned by Context.
+//!
+//! Any Clone type can be an event, usually an enum with payloads defined by
+//! the game. Every subscriber has its own queue, so each emission is kept
+//! until the subscriber polls it, instead of collapsing into one flag.
+//! Events emitted before the first subscriber of a type appears are kept,
+//! and handed to that subscriber. So a model can emit in its init, before the
+//! render subscribes in its own init. The backlog keeps the last
+//! EVENT_BACKLOG_MAX events of a type, older ones are dropped.
+//!
+//! Each game defines such an enum next to its model, e.g. SnakeEvent:
+//! the model emits it, and the render drains it to redraw what changed.
+//!
+//! Timers send a TimerEvent on the bus of their Context, see event.rs.
+//!
+//! # Example
+//!
+//! ```ignore
+//! #[derive(Clone)]
+//! pub enum SnakeEvent { RedrawGrid, Score(u32) }
+//!
+//! // render init
+//! ctx.events.subscribe::<SnakeEvent>("render");
+//! // model
+//! ctx.events.emit(SnakeEvent::Score(10));
+//! // render handle_event
+//! for e in ctx.events.drain::<SnakeEvent>("render") { ... }
+//! ```
+
+use std::any::{Any, TypeId};
+use std::collections::{HashMap, VecDeque};
+
+/// max events of a type kept while it has no subscriber
+pub const EVENT_BACKLOG_MAX: usize = 64;
+
+// queues of one event type
+struct Channel<E> {
+    subscribers: HashMap<String, VecDeque<E>>,
+    // emitted before anyone subscribed
+    backlog: VecDeque<E>,
+}
+
+impl<E> Default for Channel<E> {
+    fn default() -> Self {
+        Self {
+            subscribers: HashMap::new(),
+            backlog: VecDeque::new(),
+        }
+    }
+}
+
+// a Channel of any event type
+trait AnyChannel {
+    fn clear_queues(&mut self);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<E: 'static> AnyChannel for Channel<E> {
+    fn clear_queues(&mut self) {
+        self.backlog.clear();
+        for q in self.subscribers.values_mut() {
+            q.clear();
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[derive(Default)]
+pub struct EventBus {
+    channels: HashMap<TypeId, Box<dyn AnyChannel>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn channel<E: Clone + 'static>(&mut self) -> &mut Channel<E> {
+        self.channels
+            .entry(TypeId::of::<E>())
+            .or_insert_with(|| Box::new(Channel::<E>::default()))
+            .as_any_mut()
+            .downcast_mut::<Channel<E>>()
+            .unwrap()
+    }
+
+    fn get_channel<E: Clone + 'static>(&self) -> Option<&Channel<E>> {
+        self.channels
+            .get(&TypeId::of::<E>())
+            .and_then(|c| c.as_any().downcast_ref::<Channel<E>>())
+    }
+
+    /// creates a queue named name for events of type E
+    pub fn subscribe<E: Clone + 'static>(&mut self, name: &str) {
+        let ch = self.channel::<E>();
+        if ch.subscribers.contains_key(name) {
+            return;
+        }
+        let q = if ch.subscribers.is_empty() {
+            std::mem::take(&mut ch.backlog)
+        } else {
+            VecDeque::new()
+        };
+        ch.subscribers.insert(name.to_string(), q);
+    }
+
+    pub fn unsubscribe<E: Clone + 'static>(&mut self, name: &str) {
+        self.channel::<E>().subscribers.remove(name);
+    }
+
+    /// pushes a copy of e to the queue of every subscriber of type E
+    pub fn emit<E: Clone + 'static>(&mut self, e: E) {
+        let ch = self.channel::<E>();
+        if ch.subscribers.is_empty() {
+            if ch.backlog.len() >= EVENT_BACKLOG_MAX {
+                ch.backlog.pop_front();
+            }
+            ch.backlog.push_back(e);
+            return;
+        }
+        for q in ch.subscribers.values_mut() {
+            q.push_back(e.clone());
+        }
+    }
+
+    /// takes the oldest pending event of the subscriber
+    pub fn poll<E: Clone + 'static>(&mut self, name: &str) -> Option<E> {
+        self.channel::<E>()
+            .subscribers
+            .get_mut(name)
+            .and_then(|q| q.pop_front())
+    }
+
+    /// takes all pending events of the subscriber, in emitting order
+    pub fn drain<E: Clone + 'static>(&mut self, name: &str) -> Vec<E> {
+        match self.channel::<E>().subscribers.get_mut(name) {
+            Some(q) => q.drain(..).collect(),
+            None => vec![],
+        }
+    }
+
+    /// number of pending events of the subscriber
+    pub fn pending<E: Clone + 'static>(&self, name: &str) -> usize {
+        self.get_channel::<E>()
+            .and_then(|ch| ch.subscribers.get(name))
+            .map_or(0, |q| q.len())
+    }
+
+    /// drops all pending events of all types, subscribers are kept
+    pub fn clear(&mut self) {
+        for ch in self.channels.values_mut() {
+            ch.clear_queues();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq)]
+    enum TEvent {
+        Redraw,
+        Score(u32),
+    }
+
+    #[test]
+    fn it_queues_every_emission_per_subscriber() {
+        let mut bus = EventBus::new();
+        bus.subscribe::<TEvent>("render");
+        bus.subscribe::<TEvent>("audio");
+        bus.emit(TEvent::Score(1));
+        bus.emit(TEvent::Score(2));
+        bus.emit(7u32);
+        assert_eq!(
+            bus.drain::<TEvent>("render"),
+            vec![TEvent::Score(1), TEvent::Score(2)]
+        );
+        assert_eq!(bus.pending::<TEvent>("audio"), 2);
+        assert_eq!(bus.poll::<TEvent>("audio"), Some(TEvent::Score(1)));
+        assert_eq!(bus.pending::<u32>("render"), 0);
+        assert_eq!(bus.poll::<TEvent>("render"), None);
+    }
+
+    #[test]
+    fn it_hands_the_backlog_to_the_first_subscriber() {
+        let mut bus = EventBus::new();
+        bus.emit(TEvent::Redraw);
+        bus.subscribe::<TEvent>("render");
+        bus.subscribe::<TEvent>("audio");
+        assert_eq!(bus.drain::<TEvent>("render"), vec![TEvent::Redraw]);
+        assert_eq!(bus.pending::<TEvent>("audio"), 0);
+    }
+
+    #[test]
+    fn it_keeps_the_newest_events_in_the_backlog() {
+        let mut bus = EventBus::new();
+        for i in 0..EVENT_BACKLOG_MAX as u32 + 10 {
+            bus.emit(TEvent::Score(i));
+        }
+        bus.subscribe::<TEvent>("render");
+        let es = bus.drain::<TEvent>("render");
+        assert_eq!(es.len(), EVENT_BACKLOG_MAX);
+        assert_eq!(es[0], TEvent::Score(10));
+    }
+
+    #[test]
+    fn it_clears_events_but_keeps_subscribers() {
+        let mut bus = EventBus::new();
+        bus.subscribe::<TEvent>("render");
+        bus.emit(TEvent::Redraw);
+        bus.clear();
+        assert_eq!(bus.pending::<TEvent>("render"), 0);
+        bus.emit(TEvent::Score(3));
+        assert_eq!(bus.drain::<TEvent>("render"), vec![TEvent::Score(3)]);
+    }
+}
@@ -5,23 +5,31 @@
 //! and implements the main loop
 //! Be aware that all the Game, Model and Render instances have the same lifetime
 //!
+//! The main loop uses a fixed timestep: model and timers are updated in steps
+//! of exactly 1/context.fps seconds, however long a real frame takes.
+//! A slow frame is caught up with at most context.max_catch_up steps,
+//! render runs once per tick and context.alpha tells how far the real time
+//! is between the last step and the next one, for smooth interpolation.
+//!
 //! # Example
 //!
 //! fn main() -> Result<(), Box<dyn Error>> {
 //!    init_log(log::LevelFilter::Info, "log/snake.log");
 //!    info!("Snake(rust_pixel) start...");
-//!    let ad = Audio::new();
-//!    ad.play_file("assets/snake/back.mp3", true);
 //!    let m = SnakeModel::new();
 //!    let r = SnakeRender::new();
 //!    let mut g = Game::new(m, r);
 //!    g.init();
+//!    let ctx = &mut g.context;
+//!    let _ = ctx.audio.play_file(&ctx.asset_manager, "assets/snake/back.mp3", true);
 //!    g.run()?;
 //!    g.render.term.reset(&mut g.context);
 //!    Ok(())
 //! }
 
-use crate::{context::Context, event::timer_update, log::init_log, GAME_FRAME, LOGO_FRAME};
+use crate::{context::Context, log::init_log};
+use record::{InputRecord, InputRecorder};
+use save::{GameSave, SaveModel, GAME_SAVE_VERSION};
 use log::info;
 use std::{
     any::Any,
@@ -35,10 +43,10 @@ pub trait Model {
     fn init(&mut self, ctx: &mut Context);
     fn update(&mut self, ctx: &mut Context, dt: f32) {
         //头几帧用于绘制logo
-        if ctx.stage <= LOGO_FRAME {
+        if ctx.stage <= ctx.logo_frames() {
             return;
         }
-        timer_update();
+        ctx.update_timers(dt);
         self.handle_event(ctx, dt);
         self.handle_timer(ctx, dt);
         self.handle_input(ctx, dt);
@@ -78,6 +86,10 @@ where
     pub context: Context,
     pub model: M,
     pub render: R,
+    // real seconds not yet consumed by fixed steps
+    pub accumulator: f32,
+    // input recording or replaying, see start_record and start_replay
+    pub recorder: Option<InputRecorder>,
 }
 
 impl<M, R> Game<M, R>
@@ -88,23 +100,34 @@ where
     pub fn new(m: M, r: R, name: &str) -> Self {
         init_log(log::LevelFilter::Info, &format!("log/{}.log", name));
         info!("{}(rust_pixel) start...", name);
+        Self::new_with_context(m, r, Context::new(name))
+    }
+
+    /// creates a game with a prepared context, no log is initialized here.
+    /// Use it with Context::new_with_adapter to choose the render adapter,
+    /// e.g. HeadlessAdapter for tests
+    pub fn new_with_context(m: M, r: R, ctx: Context) -> Self {
         Self {
-            context: Context::new(name),
+            context: ctx,
             model: m,
             render: r,
+            accumulator: 0.0,
+            recorder: None,
         }
     }
 
     /// Main loop, polling input events, processing timer and other events.
-    /// It also calls tick at a constant framerate per second, executing the
-    /// update method of model and render.
+    /// It calls on_tick with the real elapsed time whenever a fixed step
+    /// of 1/context.fps seconds is due.
     pub fn run(&mut self) -> io::Result<()> {
         info!("Begin run...");
 
         let mut last_tick = Instant::now();
-        let tick_rate = Duration::from_nanos(1000_000_000 / GAME_FRAME as u64);
 
         loop {
+            let tick_rate = Duration::from_secs_f32(
+                (self.context.frame_dt() - self.accumulator).max(0.0),
+            );
             let timeout = tick_rate
                 .checked_sub(last_tick.elapsed())
                 .unwrap_or_else(|| Duration::from_nanos(100));
@@ -119,18 +142,56 @@ where
 
             let et = last_tick.elapsed();
             if et >= tick_rate {
-                let dt = et.as_secs() as f32 + et.subsec_nanos() as f32 / 1000_000_000.0;
-                self.on_tick(dt);
                 last_tick = Instant::now();
+                self.on_tick(et.as_secs_f32());
             }
         }
     }
 
-    /// calls every frame, update timer, model logic and does rendering
+    /// feeds dt real seconds to the fixed-timestep loop,
+    /// runs the due model steps and renders once if any step ran.
+    /// It is also called by the web main loop
     pub fn on_tick(&mut self, dt: f32) {
+        let step = self.context.frame_dt();
+        self.accumulator += dt;
+        let mut n = 0;
+        while self.accumulator >= step {
+            if n >= self.context.max_catch_up {
+                // too far behind, drops the backlog instead of spiralling
+                self.accumulator %= step;
+                break;
+            }
+            self.update_model(step);
+            self.accumulator -= step;
+            n += 1;
+        }
+        if n > 0 {
+            self.context.alpha = self.accumulator / step;
+            self.render
+                .update(&mut self.context, &mut self.model, step * n as f32);
+        }
+    }
+
+    /// runs exactly one fixed step and renders it, ignoring real time.
+    /// Useful for deterministic tests
+    pub fn step(&mut self) {
+        let step = self.context.frame_dt();
+        self.update_model(step);
+        self.context.alpha = 0.0;
+        self.render.update(&mut self.context, &mut self.model, step);
+    }
+
+    fn update_model(&mut self, step: f32) {
         self.context.stage += 1;
-        self.model.update(&mut self.context, dt);
-        self.render.update(&mut self.context, &mut self.model, dt);
+        if let Some(r) = &mut self.recorder {
+            r.before_step(self.context.stage, &mut self.context.input_events);
+        }
+        self.model.update(&mut self.context, step);
+        if let Some(r) = &mut self.recorder {
+            r.after_step(&self.context.input_events);
+        }
+        self.context.audio.update(step);
+        self.context.asset_manager.update(step);
     }
 
     /// init render and model
@@ -139,4 +200,90 @@ where
         self.model.init(&mut self.context);
         self.render.init(&mut self.context, &mut self.model);
     }
+
+    /// starts recording input events, call it before init.
+    /// The seed of context.rand is pinned for the whole session
+    pub fn start_record(&mut self) {
+        let ctx = &mut self.context;
+        ctx.rand.set_fixed_seed(None);
+        ctx.rand.srand_now();
+        let seed = ctx.rand.seed();
+        ctx.rand.set_fixed_seed(Some(seed));
+        let rec = InputRecord::new(&ctx.game_name, seed);
+        self.recorder = Some(InputRecorder::new_record(rec));
+        info!("start record, seed={}", seed);
+    }
+
+    /// saves the events recorded so far to path
+    pub fn save_record(&mut self, path: &str) -> io::Result<()> {
+        match &self.recorder {
+            Some(r) if !r.replaying => r.record.save(path),
+            _ => Err(io::Error::other("game is not recording")),
+        }
+    }
+
+    /// replays a recorded session, call it before init
+    pub fn start_replay(&mut self, rec: InputRecord) {
+        let ctx = &mut self.context;
+        ctx.rand.set_fixed_seed(Some(rec.seed));
+        ctx.rand.srand(rec.seed);
+        info!("start replay, seed={} events={}", rec.seed, rec.events.len());
+        self.recorder = Some(InputRecorder::new_replay(rec));
+    }
 }
+
+impl<M, R> Game<M, R>
+where
+    M: SaveModel,
+    R: Render,
+{
+    /// collects model data and context state into a GameSave
+    pub fn save_game(&self) -> Result<GameSave, String> {
+        let ctx = &self.context;
+        Ok(GameSave {
+            version: GAME_SAVE_VERSION,
+            game_name: ctx.game_name.clone(),
+            stage: ctx.stage,
+            state: ctx.state,
+            rand: ctx.rand.clone(),
+            timers: ctx.timers.save(),
+            model: self.model.save_model()?,
+        })
+    }
+
+    /// restores a GameSave, call it after init
+    pub fn load_game(&mut self, gs: &GameSave) -> Result<(), String> {
+        let ctx = &mut self.context;
+        if gs.game_name != ctx.game_name {
+            return Err(format!("game save: belongs to {}", gs.game_name));
+        }
+        ctx.stage = gs.stage;
+        ctx.state = gs.state;
+        ctx.rand = gs.rand.clone();
+        ctx.timers.load(&gs.timers);
+        self.accumulator = 0.0;
+        self.model.load_model(ctx, &gs.model)
+    }
+
+    pub fn save_file(&self, path: &str) -> io::Result<()> {
+        self.save_game().map_err(io::Error::other)?.save(path)
+    }
+
+    pub fn load_file(&mut self, path: &str) -> io::Result<()> {
+        let gs = GameSave::load(path)?;
+        self.load_game(&gs).map_err(io::Error::other)
+    }
+}
+
+/// input recording and replay per fixed step
+pub mod record;
+
+/// game save, serializes model and context state for suspend/resume
+pub mod save;
+
+/// scene stack, hosts several model/render pairs as screens in one Game
+pub mod scene;
+
+/// a small headless game shared by the tests
+#[cfg(test)]
+pub(crate) mod test_util;
@@ -0,0 +1,208 @@
+// RustPixel
+// copyright zhouxin@tuyoogame.com 2022~2024
+
+//! Records input events of a game session and replays them frame-for-frame.
+//!
+//! InputRecorder runs inside every fixed step of Game::update_model, before
+//! the model update. Recording saves the new events of context.input_events
+//! with context.stage, the step that first sees them. Replaying drops live
+//! input and puts the recorded events into context.input_events at the same
+//! stages. Events are keyed by fixed steps, not by adapter polls, so a replay
+//! is exact however its ticks catch up.
+//!
+//! The seed of Context::rand is pinned during recording and replaying,
+//! so srand_now returns the same random sequence in both sessions.
+//! Games should draw their random numbers from Context::rand.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let mut g = Game::new(m, r, "tetris");
+//! g.start_record();
+//! g.init();
+//! g.run()?;
+//! g.save_record("tmp/tetris.rec")?;
+//!
+//! let mut g = Game::new(m, r, "tetris");
+//! g.start_replay(InputRecord::load("tmp/tetris.rec")?);
+//! g.init();
+//! g.run()?;
+//! ```
+
+use crate::event::Event;
+use serde::{Deserialize, Serialize};
+
+pub const INPUT_RECORD_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InputRecord {
+    pub version: u32,
+    pub game_name: String,
+    pub seed: u64,
+    // (stage of the fixed step, event)
+    pub events: Vec<(u32, Event)>,
+}
+
+impl InputRecord {
+    pub fn new(name: &str, seed: u64) -> Self {
+        Self {
+            version: INPUT_RECORD_VERSION,
+            game_name: name.to_string(),
+            seed,
+            events: vec![],
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).unwrap()
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, String> {
+        let r: InputRecord = bincode::deserialize(data).map_err(|e| e.to_string())?;
+        if r.version != INPUT_RECORD_VERSION {
+            return Err(format!("input record: unsupported version {}", r.version));
+        }
+        Ok(r)
+    }
+
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        std::fs::write(path, self.to_bytes())
+    }
+
+    pub fn load(path: &str) -> std::io::Result<Self> {
+        let data = std::fs::read(path)?;
+        Self::from_bytes(&data)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+}
+
+/// records or replays the input events of every fixed step, see Game::update_model
+pub struct InputRecorder {
+    pub record: InputRecord,
+    pub replaying: bool,
+    // next event of record to replay
+    pub pos: usize,
+    // events at the front of input_events that earlier steps already saw
+    seen: usize,
+}
+
+impl InputRecorder {
+    pub fn new_record(record: InputRecord) -> Self {
+        Self {
+            record,
+            replaying: false,
+            pos: 0,
+            seen: 0,
+        }
+    }
+
+    pub fn new_replay(record: InputRecord) -> Self {
+        Self {
+            record,
+            replaying: true,
+            pos: 0,
+            seen: 0,
+        }
+    }
+
+    /// true when all recorded events have been replayed
+    pub fn is_finished(&self) -> bool {
+        self.replaying && self.pos >= self.record.events.len()
+    }
+
+    /// called before the model update of fixed step stage
+    pub fn before_step(&mut self, stage: u32, es: &mut Vec<Event>) {
+        let seen = self.seen.min(es.len());
+        if !self.replaying {
+            for e in &es[seen..] {
+                self.record.events.push((stage, e.clone()));
+            }
+            return;
+        }
+        // live input is passed through only after the replay is finished
+        if !self.is_finished() {
+            es.truncate(seen);
+        }
+        while let Some((s, e)) = self.record.events.get(self.pos) {
+            if *s > stage {
+                break;
+            }
+            es.push(e.clone());
+            self.pos += 1;
+        }
+    }
+
+    /// called after the model update with the events it left unhandled
+    pub fn after_step(&mut self, es: &[Event]) {
+        self.seen = es.len();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        game::test_util::{game, key},
+        render::adapter::headless::HeadlessAdapter,
+    };
+
+    #[test]
+    fn it_records_each_event_once_at_the_step_seeing_it() {
+        let mut r = InputRecorder::new_record(InputRecord::new("t", 1));
+        let mut es = vec![key('a')];
+        r.before_step(1, &mut es);
+        // left unhandled, e.g. during the logo frames
+        r.after_step(&es);
+        es.push(key('b'));
+        r.before_step(2, &mut es);
+        assert_eq!(r.record.events, vec![(1, key('a')), (2, key('b'))]);
+    }
+
+    #[test]
+    fn it_drops_live_input_until_the_replay_is_finished() {
+        let mut rec = InputRecord::new("t", 1);
+        rec.events.push((2, key('a')));
+        let mut r = InputRecorder::new_replay(rec);
+        let mut es = vec![key('x')];
+        r.before_step(1, &mut es);
+        assert!(es.is_empty());
+        r.after_step(&es);
+        es.push(key('y'));
+        r.before_step(2, &mut es);
+        assert_eq!(es, vec![key('a')]);
+        assert!(r.is_finished());
+        r.after_step(&es);
+        es.push(key('z'));
+        r.before_step(3, &mut es);
+        assert_eq!(es, vec![key('a'), key('z')]);
+    }
+
+    #[test]
+    fn it_replays_under_a_different_catch_up() {
+        let mut g = game(HeadlessAdapter::new("t"));
+        g.start_record();
+        let step = g.context.frame_dt();
+        let mut tick = 0;
+        while g.context.stage < g.context.logo_frames() + 40 {
+            if tick % 3 == 1 {
+                g.context.input_events.push(key((b'a' + tick % 26) as char));
+            }
+            // one or two fixed steps per tick
+            let n = if tick % 2 == 0 { 1.0 } else { 2.0 };
+            g.on_tick(step * n + step * 0.01);
+            tick += 1;
+        }
+        let rec = InputRecord::from_bytes(&g.recorder.as_ref().unwrap().record.to_bytes());
+        assert!(g.model.log.len() > 5);
+
+        let mut r = game(HeadlessAdapter::new("t"));
+        r.start_replay(rec.unwrap());
+        r.context.input_events.push(key('x'));
+        while r.context.stage < g.context.stage {
+            // three fixed steps per tick
+            r.on_tick(step * 3.0 + step * 0.01);
+        }
+        assert_eq!(r.model.log, g.model.log);
+        assert!(r.recorder.as_ref().unwrap().is_finished());
+    }
+}
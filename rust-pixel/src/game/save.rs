@@ -0,0 +1,138 @@
+// RustPixel
+// copyright zhouxin@tuyoogame.com 2022~2024
+
+//! Saves and restores a running game, for suspend/resume, quick-save
+//! or attaching the game state to a bug report.
+//!
+//! A GameSave holds the model data plus the parts of Context the game logic
+//! depends on: stage, state, rand (including the generator state) and the
+//! timers with their exdata. It is serialized with bincode and versioned.
+//!
+//! Models opt in by implementing SaveModel, usually by serializing their
+//! fields with bincode. load_model gets the context, so a model can emit
+//! redraw events after it is restored.
+//!
+//! Load a save after Game::init, so init does not reset the restored state.
+//!
+//! # Example
+//!
+//! ```ignore
+//! g.save_file("tmp/tetris.sav")?;
+//!
+//! let mut g = Game::new(m, r, "tetris");
+//! g.init();
+//! g.load_file("tmp/tetris.sav")?;
+//! g.run()?;
+//! ```
+
+use crate::{
+    context::Context,
+    event::Timer,
+    game::Model,
+    util::Rand,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub const GAME_SAVE_VERSION: u32 = 1;
+
+/// the Model extension for saving and restoring game state
+pub trait SaveModel: Model {
+    fn save_model(&self) -> Result<Vec<u8>, String>;
+    fn load_model(&mut self, ctx: &mut Context, data: &[u8]) -> Result<(), String>;
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct GameSave {
+    pub version: u32,
+    pub game_name: String,
+    pub stage: u32,
+    pub state: u8,
+    pub rand: Rand,
+    pub timers: HashMap<String, Timer>,
+    pub model: Vec<u8>,
+}
+
+impl GameSave {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).unwrap()
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, String> {
+        let s: GameSave = bincode::deserialize(data).map_err(|e| e.to_string())?;
+        if s.version != GAME_SAVE_VERSION {
+            return Err(format!("game save: unsupported version {}", s.version));
+        }
+        Ok(s)
+    }
+
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        std::fs::write(path, self.to_bytes())
+    }
+
+    pub fn load(path: &str) -> std::io::Result<Self> {
+        let data = std::fs::read(path)?;
+        Self::from_bytes(&data)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        game::{
+            test_util::{game as test_game, TModel, TRender},
+            Game,
+        },
+        render::adapter::headless::HeadlessAdapter,
+    };
+
+    fn game() -> Game<TModel, TRender> {
+        let mut g = test_game(HeadlessAdapter::new("t"));
+        g.context.timers.register("t.save", 1.0);
+        g
+    }
+
+    #[test]
+    fn it_restores_model_and_context() {
+        let mut g = game();
+        g.context.rand.srand(42);
+        g.context.rand.rand();
+        g.context.stage = 100;
+        g.context.state = 3;
+        g.model.score = 7;
+        g.context.timers.fire("t.save", 5u8);
+        let gs = GameSave::from_bytes(&g.save_game().unwrap().to_bytes()).unwrap();
+        let next = g.context.rand.rand();
+
+        g.context.rand.srand(1);
+        g.context.stage = 0;
+        g.context.state = 0;
+        g.model.score = 0;
+        g.context.timers.cancel("t.save", true);
+        g.load_game(&gs).unwrap();
+        assert_eq!(g.context.rand.rand(), next);
+        assert_eq!((g.context.stage, g.context.state), (100, 3));
+        assert_eq!(g.model.score, 7);
+        assert!(g.context.timers.stage("t.save") > 0);
+    }
+
+    #[test]
+    fn it_keeps_the_timers_of_each_game_apart() {
+        let (mut a, mut b) = (game(), game());
+        a.context.timers.fire("t.save", 0u8);
+        assert!(a.context.timers.stage("t.save") > 0);
+        assert_eq!(b.context.timers.stage("t.save"), 0);
+        let gs = b.save_game().unwrap();
+        a.load_game(&gs).unwrap();
+        assert_eq!(a.context.timers.stage("t.save"), 0);
+    }
+
+    #[test]
+    fn it_rejects_other_versions() {
+        let mut gs = game().save_game().unwrap();
+        gs.version += 1;
+        assert!(GameSave::from_bytes(&gs.to_bytes()).is_err());
+    }
+}
@@ -0,0 +1,448 @@
+// RustPixel
+// copyright zhouxin@tuyoogame.com 2022~2024
+
+//! A scene stack hosts several screens (menu, play, pause, game over...)
+//! in one Game, instead of switching them with Context::state.
+//!
+//! Every scene owns its own model and render and gets enter/exit/pause/resume
+//! calls when it is pushed, popped or covered by another scene.
+//! Only the top scene is updated and drawn.
+//! SceneStack advances context.timers once per update, after the logo and
+//! before the top scene runs, so scenes do not drive timers themselves.
+//!
+//! Scenes are registered by name, and request transitions by pushing a
+//! SceneCmd to context.scene_cmds. They are applied before the next update.
+//! If fade_time is set, the screen fades out and in around each transition
+//! via context.fade, which Panel::draw applies to the rendered buffer.
+//!
+//! SceneStack implements Model and SceneRender implements Render,
+//! so they run in the usual Game main loop.
+//! ModelScene wraps an existing Model and Render pair as a scene,
+//! its init methods are called on the first enter. Only the first scene
+//! should call ctx.adapter.init, others just init their panels.
+//!
+//! Pushing or replacing with an unknown name and popping the root scene
+//! are refused: apply returns an error and the stack is left as it was,
+//! commands from context.scene_cmds are logged and dropped.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let mut ss = SceneStack::new("menu");
+//! ss.add("menu", ModelScene::new(MenuModel::new(), MenuRender::new()));
+//! ss.add("play", ModelScene::new(PlayModel::new(), PlayRender::new()));
+//! ss.set_fade_time(0.3);
+//! let mut g = Game::new(ss, SceneRender, "demo");
+//! g.init();
+//! g.run()?;
+//!
+//! // in MenuModel::handle_input
+//! ctx.scene_cmds.push(SceneCmd::Replace("play".to_string()));
+//! ```
+
+use crate::{
+    context::Context,
+    game::{Model, Render},
+};
+use log::{info, warn};
+use std::any::Any;
+use std::collections::HashMap;
+
+/// scene transitions, scenes are referenced by their registered names
+#[derive(Debug, Clone, PartialEq)]
+pub enum SceneCmd {
+    /// pauses the top scene and enters the new one above it
+    Push(String),
+    /// exits the top scene and resumes the one below
+    Pop,
+    /// exits the top scene and enters the new one in its place
+    Replace(String),
+}
+
+pub trait Scene {
+    /// called when the scene becomes the top scene by push or replace
+    fn enter(&mut self, _ctx: &mut Context) {}
+    /// called when the scene is removed by pop or replace
+    fn exit(&mut self, _ctx: &mut Context) {}
+    /// called when another scene is pushed above it
+    fn pause(&mut self, _ctx: &mut Context) {}
+    /// called when it becomes the top scene again by pop
+    fn resume(&mut self, _ctx: &mut Context) {}
+    fn update(&mut self, ctx: &mut Context, dt: f32);
+    fn draw(&mut self, ctx: &mut Context, dt: f32);
+}
+
+/// runs a Model and Render pair as a scene
+pub struct ModelScene<M, R>
+where
+    M: Model,
+    R: Render,
+{
+    pub model: M,
+    pub render: R,
+    pub inited: bool,
+    // context.state of this scene, saved while it is paused
+    pub state: u8,
+}
+
+impl<M, R> ModelScene<M, R>
+where
+    M: Model,
+    R: Render,
+{
+    pub fn new(m: M, r: R) -> Self {
+        Self {
+            model: m,
+            render: r,
+            inited: false,
+            state: 0,
+        }
+    }
+}
+
+impl<M, R> Scene for ModelScene<M, R>
+where
+    M: Model,
+    R: Render,
+{
+    fn enter(&mut self, ctx: &mut Context) {
+        if !self.inited {
+            self.model.init(ctx);
+            self.render.init(ctx, &mut self.model);
+            self.inited = true;
+        }
+    }
+
+    fn pause(&mut self, ctx: &mut Context) {
+        self.state = ctx.state;
+    }
+
+    fn resume(&mut self, ctx: &mut Context) {
+        ctx.state = self.state;
+    }
+
+    // steps of Model::update, the timers are advanced by SceneStack
+    fn update(&mut self, ctx: &mut Context, dt: f32) {
+        if ctx.stage <= ctx.logo_frames() {
+            return;
+        }
+        self.model.handle_event(ctx, dt);
+        self.model.handle_timer(ctx, dt);
+        self.model.handle_input(ctx, dt);
+        self.model.handle_auto(ctx, dt);
+    }
+
+    fn draw(&mut self, ctx: &mut Context, dt: f32) {
+        self.render.update(ctx, &mut self.model, dt);
+    }
+}
+
+pub struct SceneStack {
+    pub scenes: HashMap<String, Box<dyn Scene>>,
+    // names of the scenes, the last one is on top
+    pub stack: Vec<String>,
+    // seconds of fade out, and fade in, 0.0 switches immediately
+    pub fade_time: f32,
+    // transition waiting for the end of fade out
+    fading: Option<SceneCmd>,
+    // fade level, 1.0 is black
+    fade: f32,
+}
+
+impl SceneStack {
+    /// creates a stack, the first scene is entered in Model::init
+    pub fn new(first: &str) -> Self {
+        Self {
+            scenes: HashMap::new(),
+            stack: vec![first.to_string()],
+            fade_time: 0.0,
+            fading: None,
+            fade: 0.0,
+        }
+    }
+
+    pub fn add<S: Scene + 'static>(&mut self, name: &str, scene: S) {
+        self.scenes.insert(name.to_string(), Box::new(scene));
+    }
+
+    pub fn set_fade_time(&mut self, t: f32) {
+        self.fade_time = t.max(0.0);
+    }
+
+    /// name of the top scene
+    pub fn top(&self) -> Option<&str> {
+        self.stack.last().map(|s| s.as_str())
+    }
+
+    pub fn is_fading(&self) -> bool {
+        self.fading.is_some() || self.fade > 0.0
+    }
+
+    fn top_scene(&mut self) -> Option<&mut Box<dyn Scene>> {
+        let name = self.stack.last()?;
+        self.scenes.get_mut(name)
+    }
+
+    /// applies a transition immediately, without fading.
+    /// Unknown scenes and popping the root scene are refused
+    pub fn apply(&mut self, ctx: &mut Context, cmd: SceneCmd) -> Result<(), String> {
+        info!("scene {:?}", cmd);
+        match &cmd {
+            SceneCmd::Push(name) | SceneCmd::Replace(name) => {
+                if !self.scenes.contains_key(name) {
+                    return Err(format!("scene {} not found", name));
+                }
+            }
+            SceneCmd::Pop => {
+                if self.stack.len() <= 1 {
+                    return Err("can not pop the root scene".to_string());
+                }
+            }
+        }
+        match cmd {
+            SceneCmd::Push(name) => {
+                if let Some(s) = self.top_scene() {
+                    s.pause(ctx);
+                }
+                self.stack.push(name);
+                if let Some(s) = self.top_scene() {
+                    s.enter(ctx);
+                }
+            }
+            SceneCmd::Pop => {
+                if let Some(s) = self.top_scene() {
+                    s.exit(ctx);
+                }
+                self.stack.pop();
+                if let Some(s) = self.top_scene() {
+                    s.resume(ctx);
+                }
+            }
+            SceneCmd::Replace(name) => {
+                if let Some(s) = self.top_scene() {
+                    s.exit(ctx);
+                }
+                self.stack.pop();
+                self.stack.push(name);
+                if let Some(s) = self.top_scene() {
+                    s.enter(ctx);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn apply_or_warn(&mut self, ctx: &mut Context, cmd: SceneCmd) {
+        if let Err(e) = self.apply(ctx, cmd) {
+            warn!("scene: {}", e);
+        }
+    }
+
+    // starts the next requested transition, or advances the fade
+    fn process_cmds(&mut self, ctx: &mut Context, dt: f32) {
+        if self.fading.is_none() && !ctx.scene_cmds.is_empty() {
+            let cmd = ctx.scene_cmds.remove(0);
+            if self.fade_time > 0.0 {
+                self.fading = Some(cmd);
+            } else {
+                self.apply_or_warn(ctx, cmd);
+            }
+        }
+        let step = if self.fade_time > 0.0 {
+            dt / self.fade_time
+        } else {
+            1.0
+        };
+        if self.fading.is_some() {
+            self.fade = (self.fade + step).min(1.0);
+            if self.fade >= 1.0 {
+                let cmd = self.fading.take().unwrap();
+                self.apply_or_warn(ctx, cmd);
+            }
+        } else if self.fade > 0.0 {
+            self.fade = (self.fade - step).max(0.0);
+        }
+        ctx.fade = self.fade;
+    }
+}
+
+impl Model for SceneStack {
+    fn init(&mut self, ctx: &mut Context) {
+        if let Some(s) = self.top_scene() {
+            s.enter(ctx);
+        }
+    }
+
+    fn update(&mut self, ctx: &mut Context, dt: f32) {
+        self.process_cmds(ctx, dt);
+        // the scene is frozen while fading out
+        if self.fading.is_some() {
+            return;
+        }
+        if ctx.stage > ctx.logo_frames() {
+            ctx.update_timers(dt);
+        }
+        if let Some(s) = self.top_scene() {
+            s.update(ctx, dt);
+        }
+    }
+
+    fn handle_timer(&mut self, _ctx: &mut Context, _dt: f32) {}
+    fn handle_event(&mut self, _ctx: &mut Context, _dt: f32) {}
+    fn handle_input(&mut self, _ctx: &mut Context, _dt: f32) {}
+    fn handle_auto(&mut self, _ctx: &mut Context, _dt: f32) {}
+
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// draws the top scene of a SceneStack model
+pub struct SceneRender;
+
+impl Render for SceneRender {
+    fn init<G: Model>(&mut self, _ctx: &mut Context, _m: &mut G) {}
+
+    fn handle_event<G: Model>(&mut self, _ctx: &mut Context, _m: &mut G, _dt: f32) {}
+
+    fn handle_timer<G: Model>(&mut self, _ctx: &mut Context, _m: &mut G, _dt: f32) {}
+
+    fn draw<G: Model>(&mut self, ctx: &mut Context, m: &mut G, dt: f32) {
+        let ss = m.as_any().downcast_mut::<SceneStack>().unwrap();
+        if let Some(s) = ss.top_scene() {
+            s.draw(ctx, dt);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{event::TimerEvent, render::adapter::headless::HeadlessAdapter};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    struct TScene {
+        name: String,
+        log: Rc<RefCell<Vec<String>>>,
+    }
+
+    impl TScene {
+        fn push_log(&self, s: &str) {
+            self.log.borrow_mut().push(format!("{}.{}", self.name, s));
+        }
+    }
+
+    impl Scene for TScene {
+        fn enter(&mut self, _ctx: &mut Context) {
+            self.push_log("enter");
+        }
+        fn exit(&mut self, _ctx: &mut Context) {
+            self.push_log("exit");
+        }
+        fn pause(&mut self, _ctx: &mut Context) {
+            self.push_log("pause");
+        }
+        fn resume(&mut self, _ctx: &mut Context) {
+            self.push_log("resume");
+        }
+        fn update(&mut self, _ctx: &mut Context, _dt: f32) {
+            self.push_log("update");
+        }
+        fn draw(&mut self, _ctx: &mut Context, _dt: f32) {}
+    }
+
+    fn stack(log: &Rc<RefCell<Vec<String>>>) -> (SceneStack, Context) {
+        let mut ss = SceneStack::new("a");
+        for n in ["a", "b", "c"] {
+            ss.add(
+                n,
+                TScene {
+                    name: n.to_string(),
+                    log: log.clone(),
+                },
+            );
+        }
+        let mut ctx = Context::new_with_adapter("t", Box::new(HeadlessAdapter::new("t")));
+        ss.init(&mut ctx);
+        (ss, ctx)
+    }
+
+    #[test]
+    fn it_calls_hooks_on_push_replace_and_pop() {
+        let log = Rc::new(RefCell::new(vec![]));
+        let (mut ss, mut ctx) = stack(&log);
+        ss.update(&mut ctx, 0.1);
+        ctx.scene_cmds.push(SceneCmd::Push("b".to_string()));
+        ss.update(&mut ctx, 0.1);
+        ctx.scene_cmds.push(SceneCmd::Replace("c".to_string()));
+        ctx.scene_cmds.push(SceneCmd::Pop);
+        ss.update(&mut ctx, 0.1);
+        ss.update(&mut ctx, 0.1);
+        assert_eq!(ss.top(), Some("a"));
+        assert_eq!(
+            *log.borrow(),
+            vec![
+                "a.enter", "a.update", "a.pause", "b.enter", "b.update", "b.exit", "c.enter",
+                "c.update", "c.exit", "a.resume", "a.update"
+            ]
+        );
+    }
+
+    #[test]
+    fn it_fades_out_switches_and_fades_in() {
+        let log = Rc::new(RefCell::new(vec![]));
+        let (mut ss, mut ctx) = stack(&log);
+        log.borrow_mut().clear();
+        // fades out in 2 steps, switches, then fades in
+        ss.set_fade_time(0.2);
+        ctx.scene_cmds.push(SceneCmd::Push("b".to_string()));
+        ss.update(&mut ctx, 0.1);
+        assert_eq!(ctx.fade, 0.5);
+        ss.update(&mut ctx, 0.1);
+        assert_eq!(ctx.fade, 1.0);
+        assert_eq!(ss.top(), Some("b"));
+        ss.update(&mut ctx, 0.1);
+        ss.update(&mut ctx, 0.1);
+        assert_eq!(ctx.fade, 0.0);
+        assert!(!ss.is_fading());
+        assert_eq!(
+            *log.borrow(),
+            vec!["a.pause", "b.enter", "b.update", "b.update", "b.update"]
+        );
+    }
+
+    #[test]
+    fn it_advances_timers_for_any_scene() {
+        let log = Rc::new(RefCell::new(vec![]));
+        let (mut ss, mut ctx) = stack(&log);
+        ctx.events.subscribe::<TimerEvent>("t");
+        ctx.timers.register("t.scene", 0.15);
+        ctx.timers.fire("t.scene", 0u8);
+        // frozen during the logo
+        ss.update(&mut ctx, 0.1);
+        ss.update(&mut ctx, 0.1);
+        assert!(ctx.events.drain::<TimerEvent>("t").is_empty());
+        ctx.stage = ctx.logo_frames() + 1;
+        ss.update(&mut ctx, 0.1);
+        assert!(ctx.events.drain::<TimerEvent>("t").is_empty());
+        ss.update(&mut ctx, 0.1);
+        assert_eq!(
+            ctx.events.drain::<TimerEvent>("t"),
+            vec![TimerEvent("t.scene".to_string())]
+        );
+    }
+
+    #[test]
+    fn it_refuses_unknown_scenes_and_popping_the_root() {
+        let log = Rc::new(RefCell::new(vec![]));
+        let (mut ss, mut ctx) = stack(&log);
+        assert!(ss.apply(&mut ctx, SceneCmd::Push("x".to_string())).is_err());
+        assert!(ss.apply(&mut ctx, SceneCmd::Replace("x".to_string())).is_err());
+        assert!(ss.apply(&mut ctx, SceneCmd::Pop).is_err());
+        ctx.scene_cmds.push(SceneCmd::Pop);
+        ss.update(&mut ctx, 0.1);
+        assert_eq!(ss.stack, vec!["a"]);
+        assert_eq!(*log.borrow(), vec!["a.enter", "a.update"]);
+    }
+}
@@ -0,0 +1,113 @@
+// RustPixel
+// copyright zhouxin@tuyoogame.com 2022~2024
+
+//! A minimal game for the tests of Game, its adapters and its helpers.
+//! TModel logs the keys it handles, TRender draws them
+//! in the first line of a 10x2 panel.
+
+use crate::{
+    context::Context,
+    event::{Event, KeyCode, KeyEvent, KeyModifiers},
+    game::{save::SaveModel, Game, Model, Render},
+    render::{
+        adapter::headless::HeadlessAdapter,
+        panel::Panel,
+        sprite::{Sprite, Sprites},
+        style::Style,
+    },
+};
+use std::any::Any;
+
+pub fn key(c: char) -> Event {
+    Event::Key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE))
+}
+
+pub struct TModel {
+    // (stage, key, random number) of every key handled
+    pub log: Vec<(u32, char, u32)>,
+    // model data of the save tests
+    pub score: u32,
+}
+
+impl TModel {
+    pub fn keys(&self) -> Vec<char> {
+        self.log.iter().map(|l| l.1).collect()
+    }
+}
+
+impl Model for TModel {
+    fn init(&mut self, _ctx: &mut Context) {}
+    fn handle_timer(&mut self, _ctx: &mut Context, _dt: f32) {}
+    fn handle_event(&mut self, _ctx: &mut Context, _dt: f32) {}
+    fn handle_input(&mut self, ctx: &mut Context, _dt: f32) {
+        let es: Vec<Event> = ctx.input_events.drain(..).collect();
+        for e in es {
+            if let Event::Key(KeyEvent {
+                code: KeyCode::Char(c),
+                ..
+            }) = e
+            {
+                let r = ctx.rand.rand() % 1000;
+                self.log.push((ctx.stage, c, r));
+            }
+        }
+    }
+    fn handle_auto(&mut self, _ctx: &mut Context, _dt: f32) {}
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl SaveModel for TModel {
+    fn save_model(&self) -> Result<Vec<u8>, String> {
+        bincode::serialize(&self.score).map_err(|e| e.to_string())
+    }
+    fn load_model(&mut self, _ctx: &mut Context, data: &[u8]) -> Result<(), String> {
+        self.score = bincode::deserialize(data).map_err(|e| e.to_string())?;
+        Ok(())
+    }
+}
+
+pub struct TRender {
+    panel: Panel,
+    sprites: Sprites,
+}
+
+impl Render for TRender {
+    fn init<G: Model>(&mut self, ctx: &mut Context, _m: &mut G) {
+        ctx.adapter.init(10, 2, 1.0, 1.0, "t".to_string());
+        self.panel.init(ctx);
+    }
+    fn handle_event<G: Model>(&mut self, _ctx: &mut Context, _m: &mut G, _dt: f32) {}
+    fn handle_timer<G: Model>(&mut self, _ctx: &mut Context, _m: &mut G, _dt: f32) {}
+    fn draw<G: Model>(&mut self, ctx: &mut Context, m: &mut G, _dt: f32) {
+        let tm = m.as_any().downcast_mut::<TModel>().unwrap();
+        let s: String = tm.keys().into_iter().collect();
+        let l = self.sprites.get_by_tag("T");
+        l.content.set_str(0, 0, &s, Style::default());
+        self.panel
+            .draw(ctx, |a, f| {
+                self.sprites.render_all(a, f);
+            })
+            .unwrap();
+    }
+}
+
+/// an initialized test game running on ha
+pub fn game(ha: HeadlessAdapter) -> Game<TModel, TRender> {
+    let mut sprites = Sprites::new("main");
+    sprites.add_by_tag(Sprite::new(0, 0, 10, 1), "T");
+    let mut g = Game::new_with_context(
+        TModel {
+            log: vec![],
+            score: 0,
+        },
+        TRender {
+            panel: Panel::new(),
+            sprites,
+        },
+        Context::new_with_adapter("t", Box::new(ha)),
+    );
+    g.init();
+    g
+}
@@ -20,12 +20,14 @@
 //! Base mode requires fewer dependencies and therefore it is a good fit for compiling to ffi
 //! or wasm libs.
 
-/// framerate per second, set to moderate number to save CPUs
+/// default framerate per second, set to moderate number to save CPUs
+/// games can change it with Context::set_fps
 pub const GAME_FRAME: u32 = 60;
+/// seconds the logo is shown, see Context::logo_frames for the steps at the current fps
 #[cfg(not(any(feature = "sdl", target_arch = "wasm32")))]
-pub const LOGO_FRAME: u32 = GAME_FRAME / 4 * 2;
+pub const LOGO_SECS: f32 = 0.5;
 #[cfg(any(feature = "sdl", target_arch = "wasm32"))]
-pub const LOGO_FRAME: u32 = GAME_FRAME / 4 * 5;
+pub const LOGO_SECS: f32 = 1.25;
 
 /// disjoint-set data structure, astar
 pub mod algorithm;
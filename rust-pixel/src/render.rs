@@ -20,9 +20,33 @@ pub mod image;
 /// sprite, basic drawing unit
 pub mod sprite;
 
+/// glyph atlases from png tilesets, bdf and psf fonts, draw unicode text in graphical mode
+pub mod atlas;
+
 /// defines attributes like fore- or back-ground colors
 pub mod style;
 
+/// camera and viewport, shows a part of a larger world
+pub mod camera;
+
+/// tweens, animate sprite properties with easing curves
+pub mod tween;
+
+/// canvas with braille, quadrant and half-block pixels smaller than a cell
+pub mod canvas;
+
+/// particle emitters drawn with pixel sprites, or characters in text mode
+pub mod particle;
+
+/// palettes, loadable and swappable at runtime for color effects
+pub mod palette;
+
+/// tile layers with collision flags and Tiled map import
+pub mod tilemap;
+
 /// draw panel, compatible with both text mode (crossterm) and graphical mode (SDL&wasm)
 pub mod panel;
 
+/// golden-frame snapshot, serializes buffer to text and compares with golden files
+pub mod snapshot;
+
@@ -2,17 +2,23 @@
 // copyright zhouxin@tuyoogame.com 2022~2024
 
 #[cfg(any(feature = "sdl", target_arch = "wasm32"))]
-use crate::render::style::Color;
+use crate::render::{
+    atlas::AtlasGlyph,
+    style::{BlendMode, Color},
+};
 #[cfg(any(feature = "sdl", target_arch = "wasm32"))]
 use crate::util::{
     Rand, {APoint, ARect},
 };
-#[cfg(any(feature = "sdl", target_arch = "wasm32"))]
-use crate::LOGO_FRAME;
 use crate::{
+    asset::AssetManager,
     event::Event,
-    render::{buffer::Buffer, sprite::Sprites},
+    render::{
+        atlas::AtlasRegistry, buffer::Buffer, palette::Palette, sprite::Sprites,
+        style::ColorDepth,
+    },
     util::Rect,
+    GAME_FRAME, LOGO_SECS,
 };
 use std::any::Any;
 use std::time::Duration;
@@ -86,6 +92,15 @@ pub struct AdapterBase {
     pub pixel_h: u32,
     pub ratio_x: f32,
     pub ratio_y: f32,
+    // colors of the terminal, text mode only, detected at start.
+    // Games may set it, e.g. ctx.adapter.get_base().color_depth = ColorDepth::Ansi16
+    pub color_depth: ColorDepth,
+    // ordered dithering when colors are reduced for the terminal
+    pub dither: bool,
+    // copy of context.palette, set by Panel::draw
+    pub palette: Palette,
+    // copy of context.logo_frames(), set by Panel::draw
+    pub logo_frames: u32,
 }
 
 impl AdapterBase {
@@ -99,6 +114,10 @@ impl AdapterBase {
             pixel_h: 0,
             ratio_x: 1.0,
             ratio_y: 1.0,
+            color_depth: ColorDepth::detect(),
+            dither: false,
+            palette: Palette::xterm(),
+            logo_frames: (LOGO_SECS * GAME_FRAME as f32).round() as u32,
         }
     }
 }
@@ -107,12 +126,19 @@ pub trait Adapter {
     fn init(&mut self, w: u16, h: u16, rx: f32, ry: f32, s: String);
     fn reset(&mut self);
     fn get_base(&mut self) -> &mut AdapterBase;
+    /// reads the texture files through am, so they come from its mounted
+    /// packs too. Panel::draw calls it before every render_buffer,
+    /// adapters load them once
+    fn load_textures(&mut self, _am: &AssetManager) {}
     fn poll_event(&mut self, timeout: Duration, ev: &mut Vec<Event>) -> bool;
+    /// draws cb, pb is the previous frame. Symbols found in atlases
+    /// are drawn with their glyphs in graphical mode
     fn render_buffer(
         &mut self,
         cb: &Buffer,
         pb: &Buffer,
         ps: &mut Sprites,
+        atlases: &AtlasRegistry,
         stage: u32,
     ) -> Result<(), String>;
 
@@ -175,15 +201,19 @@ pub trait Adapter {
     fn set_cursor(&mut self, x: u16, y: u16) -> Result<(), String>;
     fn get_cursor(&mut self) -> Result<(u16, u16), String>;
     fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
 }
 
+/// ag is the atlas glyph of the cell, sh is used without one
 #[cfg(any(feature = "sdl", target_arch = "wasm32"))]
+#[allow(clippy::too_many_arguments)]
 fn render_helper(
     cell_w: u16,
     rx: f32,
     ry: f32,
     i: usize,
     sh: &(u8, u8, u8),
+    ag: Option<AtlasGlyph>,
     px: u16,
     py: u16,
     is_border: bool,
@@ -193,6 +223,30 @@ fn render_helper(
     let dstx = i as u16 % cell_w;
     let dsty = i as u16 / cell_w;
     let tex_count = PIXEL_TEXTURE_FILES.len() as u8 * 4;
+    if let Some((sym, tex, (gx, gy, gw, gh), span)) = ag {
+        // fit the glyph into span cells keeping its aspect
+        let (cw, ch) = ((w as f32 / rx) as i32, (h as f32 / ry) as i32);
+        let off = if is_border { 0 } else { 1 };
+        let (bw, bh) = (cw * span as i32, ch);
+        let k = (bw as f32 / gw.max(1) as f32).min(bh as f32 / gh.max(1) as f32);
+        let (dw, dh) = ((gw as f32 * k) as i32, (gh as f32 * k) as i32);
+        return (
+            ARect {
+                x: gx as i32,
+                y: gy as i32,
+                w: gw,
+                h: gh,
+            },
+            ARect {
+                x: (dstx + off) as i32 * cw + px as i32 + (bw - dw) / 2,
+                y: (dsty + off) as i32 * ch + py as i32 + (bh - dh) / 2,
+                w: dw as u32,
+                h: dh as u32,
+            },
+            tex as usize,
+            sym as usize,
+        );
+    }
     let tx = if sh.1 < tex_count { sh.1 as usize } else { 1 };
     let srcy = sh.0 as u32 / w as u32 + (tx as u32 / 2u32) * w as u32;
     let srcx = sh.0 as u32 % w as u32 + (tx as u32 % 2u32) * w as u32;
@@ -215,10 +269,30 @@ fn render_helper(
     )
 }
 
+/// index of texture texidx in the adapter's texture list, the 4 parts of
+/// c64.png share one texture, glyph atlas pages follow
 #[cfg(any(feature = "sdl", target_arch = "wasm32"))]
-pub fn render_pixel_sprites<F>(pixel_spt: &mut Sprites, rx: f32, ry: f32, mut f: F)
-where
-    F: FnMut(&(u8, u8, u8), ARect, ARect, usize, usize, f64, APoint),
+pub fn texture_slot(texidx: usize) -> usize {
+    let n = PIXEL_TEXTURE_FILES.len();
+    if texidx < n * 4 {
+        texidx / 4
+    } else {
+        n + texidx - n * 4
+    }
+}
+
+/// pixel sprites are drawn by the adapter directly, f also gets the
+/// opacity (0~255) and blend mode of the sprite
+#[cfg(any(feature = "sdl", target_arch = "wasm32"))]
+pub fn render_pixel_sprites<F>(
+    pixel_spt: &mut Sprites,
+    pal: &Palette,
+    atlases: &AtlasRegistry,
+    rx: f32,
+    ry: f32,
+    mut f: F,
+) where
+    F: FnMut(&(u8, u8, u8), ARect, ARect, usize, usize, f64, APoint, u8, BlendMode),
 {
     // sort by render_weight...
     pixel_spt.update_render_index();
@@ -234,7 +308,8 @@ where
 
         for (i, cell) in s.content.content.iter().enumerate() {
             let sh = &cell.get_cell_info();
-            let (s1, s2, texidx, symidx) = render_helper(pw, rx, ry, i, sh, px, py, false);
+            let ag = cell.glyph_char().and_then(|c| atlases.lookup(c));
+            let (s1, s2, texidx, symidx) = render_helper(pw, rx, ry, i, sh, ag, px, py, false);
             let x = i % pw as usize;
             let y = i / pw as usize;
             // center point ...
@@ -242,22 +317,30 @@ where
                 x: ((pw as f32 / 2.0 - x as f32) * PIXEL_SYM_WIDTH as f32 / rx) as i32,
                 y: ((ph as f32 / 2.0 - y as f32) * PIXEL_SYM_HEIGHT as f32 / ry) as i32,
             };
-            let fc = Color::Indexed(sh.2).get_rgb();
-            f(&fc, s1, s2, texidx, symidx, s.angle, ccp);
+            let fc = pal.index(sh.2);
+            let alpha = (s.opacity.clamp(0.0, 1.0) * 255.0) as u8;
+            f(&fc, s1, s2, texidx, symidx, s.angle, ccp, alpha, s.blend);
         }
     }
 }
 
 #[cfg(any(feature = "sdl", target_arch = "wasm32"))]
-pub fn render_main_buffer<F>(buf: &Buffer, width: u16, rx: f32, ry: f32, mut f: F)
-where
+pub fn render_main_buffer<F>(
+    buf: &Buffer,
+    pal: &Palette,
+    atlases: &AtlasRegistry,
+    width: u16,
+    rx: f32,
+    ry: f32,
+    mut f: F,
+) where
     F: FnMut(&(u8, u8, u8), ARect, ARect, usize, usize),
 {
     for (i, cell) in buf.content.iter().enumerate() {
-        for sh in &cell.draw_history {
-            let (s1, s2, texidx, symidx) = render_helper(width, rx, ry, i, sh, 0, 0, false);
-            let fc = Color::Indexed(sh.2).get_rgb();
-            f(&fc, s1, s2, texidx, symidx);
+        for (sh, fc, ch) in &cell.draw_history {
+            let ag = ch.and_then(|c| atlases.lookup(c));
+            let (s1, s2, texidx, symidx) = render_helper(width, rx, ry, i, sh, ag, 0, 0, false);
+            f(&pal.rgb(*fc), s1, s2, texidx, symidx);
         }
     }
 }
@@ -292,6 +375,7 @@ where
                 ry,
                 n * (cell_w as usize + 2) + m,
                 rsh,
+                None,
                 0,
                 0,
                 true,
@@ -303,8 +387,19 @@ where
 }
 
 #[cfg(any(feature = "sdl", target_arch = "wasm32"))]
-pub fn render_logo<F>(srx: f32, sry: f32, spw: u32, sph: u32, rd: &mut Rand, stage: u32, mut f: F)
-where
+/// draws the logo at stage of the logo_frames steps it is shown for:
+/// it fades in, stays and fades out in thirds of them
+#[allow(clippy::too_many_arguments)]
+pub fn render_logo<F>(
+    srx: f32,
+    sry: f32,
+    spw: u32,
+    sph: u32,
+    rd: &mut Rand,
+    stage: u32,
+    logo_frames: u32,
+    mut f: F,
+) where
     F: FnMut(&(u8, u8, u8), ARect, ARect, usize, usize),
 {
     let rx = srx * 1.0;
@@ -325,6 +420,7 @@ where
                     PIXEL_LOGO[sci * 3 + 2],
                     PIXEL_LOGO[sci * 3 + 1],
                 ),
+                None,
                 spw as u16 / 2 - (PIXEL_LOGO_WIDTH as f32 / 2.0 * symw) as u16,
                 sph as u16 / 2 - (PIXEL_LOGO_HEIGHT as f32 / 2.0 * symh) as u16,
                 false,
@@ -332,21 +428,23 @@ where
             let fc = Color::Indexed(PIXEL_LOGO[sci * 3 + 1]).get_rgb();
 
             let randadj = 12 - (rd.rand() % 24) as i32;
-            let sg = LOGO_FRAME as u8 / 3;
+            let sg = (logo_frames / 3).max(1);
+            // brightness of n steps into a third, 250 at its end
+            let lum = |n: u32| (n * 250 / sg).min(255) as u8;
             let r: u8;
             let g: u8;
             let b: u8;
-            if stage <= sg as u32 {
-                r = (stage as u8).saturating_mul(10);
-                g = (stage as u8).saturating_mul(10);
-                b = (stage as u8).saturating_mul(10);
+            if stage <= sg {
+                r = lum(stage);
+                g = lum(stage);
+                b = lum(stage);
                 s2.x = s2.x + randadj;
-            } else if stage <= sg as u32 * 2 {
+            } else if stage <= sg * 2 {
                 r = fc.0;
                 g = fc.1;
                 b = fc.2;
             } else {
-                let cc = (stage as u8 - sg as u8 * 2).saturating_mul(10);
+                let cc = lum(stage - sg * 2);
                 r = fc.0.saturating_sub(cc);
                 g = fc.1.saturating_sub(cc);
                 b = fc.2.saturating_sub(cc);
@@ -356,6 +454,9 @@ where
     }
 }
 
+/// headless driver, renders to memory, used for testing...
+pub mod headless;
+
 /// sdl driver...
 #[cfg(all(feature = "sdl", not(target_arch = "wasm32")))]
 pub mod sdl;
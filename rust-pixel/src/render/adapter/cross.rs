@@ -5,13 +5,13 @@ use crate::{
     event::{Event, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind},
     render::{
         adapter::{Adapter, AdapterBase},
+        atlas::AtlasRegistry,
         buffer::Buffer,
         image::to_error,
         sprite::Sprites,
         style::{Color, Modifier, ModifierDiff},
     },
     util::Rand,
-    LOGO_FRAME,
 };
 #[cfg(not(feature = "sdl"))]
 use crossterm::{
@@ -131,9 +131,10 @@ impl Adapter for CrosstermAdapter {
         current_buffer: &Buffer,
         previous_buffer: &Buffer,
         _pix: &mut Sprites,
+        _atlases: &AtlasRegistry,
         stage: u32,
     ) -> Result<(), String> {
-        if stage <= LOGO_FRAME {
+        if stage <= self.base.logo_frames {
             let w = current_buffer.area().width as u16;
             let x = w - self.rd.rand() as u16 % w;
             let y = current_buffer.area().height as u16 / 2;
@@ -147,7 +148,7 @@ impl Adapter for CrosstermAdapter {
             to_error(queue!(self.writer, MoveTo(x, y)))?;
             to_error(queue!(self.writer, SetForegroundColor(cc)))?;
             to_error(queue!(self.writer, Print("...RustPixel...")))?;
-            if stage == LOGO_FRAME {
+            if stage == self.base.logo_frames {
                 // clear screen
                 to_error(queue!(self.writer, MoveTo(x, y)))?;
                 to_error(queue!(
@@ -163,6 +164,14 @@ impl Adapter for CrosstermAdapter {
             return Ok(());
         }
         let updates = previous_buffer.diff(current_buffer);
+        let (depth, dither) = (self.base.color_depth, self.base.dither);
+        // a custom palette is sent as rgb, the terminal only knows its own
+        let pal = if self.base.palette.is_default() {
+            None
+        } else {
+            Some(&self.base.palette)
+        };
+        let resolve = |c: Color| pal.map_or(c, |p| p.resolve(c));
 
         let mut fg = Color::Reset;
         let mut bg = Color::Reset;
@@ -182,15 +191,18 @@ impl Adapter for CrosstermAdapter {
                 to_error(diff.queue(&mut self.writer))?;
                 modifier = cell.modifier;
             }
-            if cell.fg != fg {
-                let color = CColor::from(cell.fg);
+            // colors the terminal can not show are mapped to its palette
+            let cfg = resolve(cell.fg).quantize(depth, x, y, dither);
+            if cfg != fg {
+                let color = CColor::from(cfg);
                 to_error(queue!(self.writer, SetForegroundColor(color)))?;
-                fg = cell.fg;
+                fg = cfg;
             }
-            if cell.bg != bg {
-                let color = CColor::from(cell.bg);
+            let cbg = resolve(cell.bg).quantize(depth, x, y, dither);
+            if cbg != bg {
+                let color = CColor::from(cbg);
                 to_error(queue!(self.writer, SetBackgroundColor(color)))?;
-                bg = cell.bg;
+                bg = cbg;
             }
 
             to_error(queue!(self.writer, Print(&cell.symbol)))?;
@@ -206,6 +218,10 @@ impl Adapter for CrosstermAdapter {
     fn as_any(&self) -> &dyn Any {
         self
     }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
 }
 
 /// 转换crossterm的输入事件为pixel输入事件，方便游戏采用统一接口处理
@@ -0,0 +1,255 @@
+// RustPixel
+// copyright zhouxin@tuyoogame.com 2022~2024
+
+//! Implements a headless Adapter, it needs no terminal, SDL window or browser.
+//! Rendering goes to an in-memory buffer, input events are scripted in advance.
+//! It is used to run Game::run or Game::on_tick deterministically in CI.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let mut ha = HeadlessAdapter::new("snake");
+//! // the logo takes ctx.logo_frames() steps, 30 at the default fps
+//! ha.push_event_at(31, Event::Key(KeyEvent::new(KeyCode::Char('s'), KeyModifiers::NONE)));
+//! ha.set_max_frames(40);
+//! let ctx = Context::new_with_adapter("snake", Box::new(ha));
+//! let mut g = Game::new_with_context(SnakeModel::new(), SnakeRender::new(), ctx);
+//! g.init();
+//! g.run()?;
+//! let ha = g.context.adapter.as_any().downcast_ref::<HeadlessAdapter>().unwrap();
+//! assert_eq!(ha.last_frame().get(0, 0).symbol, "┌");
+//! ```
+
+use crate::{
+    event::Event,
+    render::{
+        adapter::{Adapter, AdapterBase},
+        atlas::AtlasRegistry,
+        buffer::Buffer,
+        sprite::Sprites,
+    },
+    util::Rect,
+};
+use std::any::Any;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+pub struct HeadlessAdapter {
+    pub base: AdapterBase,
+    // last rendered frame
+    pub frame: Buffer,
+    // stage of the last rendered frame
+    pub stage: u32,
+    // how many times render_buffer has been called
+    pub frame_count: u32,
+    // scripted events, (stage, event), sorted by stage
+    pub script: VecDeque<(u32, Event)>,
+    // poll_event returns true(quit) when frame_count reaches max_frames
+    pub max_frames: Option<u32>,
+    // sleep timeout in poll_event like a real adapter
+    pub realtime: bool,
+    pub cursor: (u16, u16),
+}
+
+impl HeadlessAdapter {
+    pub fn new(gn: &str) -> Self {
+        Self {
+            base: AdapterBase::new(gn),
+            frame: Buffer::empty(Rect::new(0, 0, 0, 0)),
+            stage: 0,
+            frame_count: 0,
+            script: VecDeque::new(),
+            max_frames: None,
+            realtime: true,
+            cursor: (0, 0),
+        }
+    }
+
+    /// queues an event, it is delivered by the next poll_event
+    pub fn push_event(&mut self, e: Event) {
+        self.push_event_at(0, e);
+    }
+
+    /// queues an event, it is delivered by the first poll_event
+    /// after the frame of the given stage is rendered
+    pub fn push_event_at(&mut self, stage: u32, e: Event) {
+        let pos = self
+            .script
+            .iter()
+            .position(|(s, _)| *s > stage)
+            .unwrap_or(self.script.len());
+        self.script.insert(pos, (stage, e));
+    }
+
+    pub fn set_max_frames(&mut self, n: u32) {
+        self.max_frames = Some(n);
+    }
+
+    pub fn set_realtime(&mut self, flag: bool) {
+        self.realtime = flag;
+    }
+
+    /// returns the last frame rendered by the panel
+    pub fn last_frame(&self) -> &Buffer {
+        &self.frame
+    }
+}
+
+impl Adapter for HeadlessAdapter {
+    fn init(&mut self, w: u16, h: u16, rx: f32, ry: f32, s: String) {
+        self.set_size(w, h)
+            .set_ratiox(rx)
+            .set_ratioy(ry)
+            .set_pixel_size()
+            .set_title(s);
+        self.frame.resize(Rect::new(0, 0, w, h));
+        self.frame.reset();
+    }
+
+    fn get_base(&mut self) -> &mut AdapterBase {
+        &mut self.base
+    }
+
+    fn reset(&mut self) {}
+
+    fn cell_width(&self) -> f32 {
+        0.0
+    }
+
+    fn cell_height(&self) -> f32 {
+        0.0
+    }
+
+    fn hide_cursor(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn show_cursor(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn set_cursor(&mut self, x: u16, y: u16) -> Result<(), String> {
+        self.cursor = (x, y);
+        Ok(())
+    }
+
+    fn get_cursor(&mut self) -> Result<(u16, u16), String> {
+        Ok(self.cursor)
+    }
+
+    fn poll_event(&mut self, timeout: Duration, es: &mut Vec<Event>) -> bool {
+        if let Some(mf) = self.max_frames {
+            if self.frame_count >= mf {
+                return true;
+            }
+        }
+        while let Some((s, _)) = self.script.front() {
+            if *s > self.stage {
+                break;
+            }
+            let (_, e) = self.script.pop_front().unwrap();
+            es.push(e);
+        }
+        if self.realtime {
+            std::thread::sleep(timeout);
+        }
+        false
+    }
+
+    fn render_buffer(
+        &mut self,
+        current_buffer: &Buffer,
+        _previous_buffer: &Buffer,
+        _pix: &mut Sprites,
+        _atlases: &AtlasRegistry,
+        stage: u32,
+    ) -> Result<(), String> {
+        self.frame = current_buffer.clone();
+        self.stage = stage;
+        self.frame_count += 1;
+        Ok(())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        game::{
+            test_util::{game, key, TModel, TRender},
+            Game,
+        },
+    };
+
+    // runs a game typing keys, one per frame from the last logo frame,
+    // stops frames after the logo
+    fn run_keys(keys: &str, frames: u32) -> Game<TModel, TRender> {
+        let mut g = game(HeadlessAdapter::new("t"));
+        let lf = g.context.logo_frames();
+        let ha = g
+            .context
+            .adapter
+            .as_any_mut()
+            .downcast_mut::<HeadlessAdapter>()
+            .unwrap();
+        ha.set_realtime(false);
+        ha.set_max_frames(lf + frames);
+        for (i, c) in keys.chars().enumerate() {
+            ha.push_event_at(lf + i as u32, key(c));
+        }
+        g.run().unwrap();
+        g
+    }
+
+    fn headless(g: &Game<TModel, TRender>) -> &HeadlessAdapter {
+        g.context
+            .adapter
+            .as_any()
+            .downcast_ref::<HeadlessAdapter>()
+            .unwrap()
+    }
+
+    #[test]
+    fn it_stops_after_max_frames() {
+        let g = run_keys("", 4);
+        assert_eq!(headless(&g).frame_count, g.context.logo_frames() + 4);
+        assert_eq!(headless(&g).last_frame().area, Rect::new(0, 0, 10, 2));
+    }
+
+    #[test]
+    fn it_renders_scripted_events() {
+        let g = run_keys("ok", 4);
+        assert_eq!(headless(&g).last_frame().get(0, 0).symbol, "o");
+        assert_eq!(headless(&g).last_frame().get(1, 0).symbol, "k");
+    }
+
+    #[test]
+    fn it_keeps_events_scripted_after_the_last_frame() {
+        let g = run_keys("abc", 2);
+        assert_eq!(g.model.keys(), vec!['a', 'b']);
+        assert_eq!(headless(&g).script.len(), 1);
+    }
+
+    #[test]
+    fn it_shows_the_logo_for_the_same_seconds_at_any_fps() {
+        let mut g = game(HeadlessAdapter::new("t"));
+        let lf = g.context.logo_frames();
+        g.context.set_fps(g.context.fps * 2);
+        assert_eq!(g.context.logo_frames(), lf * 2);
+        for _ in 0..lf * 2 {
+            g.context.input_events.push(key('a'));
+            g.step();
+        }
+        assert!(g.model.log.is_empty());
+        g.step();
+        assert_eq!(g.model.log[0].0, lf * 2 + 1);
+    }
+}
@@ -6,28 +6,32 @@
 //! Includes resizing of height and width, init settings,
 //! some code is also called in cell.rs
 use crate::event::{
-    Event, KeyCode, KeyEvent, KeyModifiers, MouseButton::*, MouseEvent, MouseEventKind::*,
+    Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseButton::*, MouseEvent,
+    MouseEventKind::*,
 };
 use crate::{
+    asset::AssetManager,
     render::{
         adapter::{
-            render_border, render_logo, render_main_buffer, render_pixel_sprites, ARect, Adapter,
-            AdapterBase, PIXEL_SYM_HEIGHT, PIXEL_SYM_WIDTH, PIXEL_TEXTURE_FILES,
+            render_border, render_logo, render_main_buffer, render_pixel_sprites, texture_slot,
+            ARect, Adapter, AdapterBase, PIXEL_SYM_HEIGHT, PIXEL_SYM_WIDTH, PIXEL_TEXTURE_FILES,
         },
+        atlas::AtlasRegistry,
         buffer::Buffer,
         sprite::Sprites,
+        style::BlendMode,
     },
     util::Rand,
-    LOGO_FRAME,
 };
 use sdl2::{
     event::Event as SEvent,
-    image::{InitFlag, LoadSurface, LoadTexture},
+    image::{ImageRWops, InitFlag, LoadTexture},
     keyboard::Keycode as SKeycode,
     mouse::*,
     pixels::PixelFormatEnum,
     rect::{Point as SPoint, Rect as SRect},
-    render::{Canvas, Texture},
+    render::{BlendMode as SBlendMode, Canvas, Texture},
+    rwops::RWops,
     surface::Surface,
     video::{Window, WindowPos::Positioned},
     EventPump, Sdl,
@@ -51,8 +55,10 @@ pub struct SdlAdapter {
     // custom cursor in rust-sdl2
     pub cursor: Option<Cursor>,
     pub canvas: Option<Canvas<Window>>,
-    // raw textures
+    // raw textures, the texture files then glyph atlas pages
     pub asset_textures: Option<Vec<Texture>>,
+    // version of the atlas registry the uploaded pages come from
+    pub atlas_version: usize,
     // rendering target textures
     pub render_texture: Option<Texture>,
     pub rd: Rand,
@@ -74,6 +80,7 @@ impl SdlAdapter {
             cursor: None,
             canvas: None,
             asset_textures: None,
+            atlas_version: 0,
             render_texture: None,
             drag_ing: false,
             drag_mouse_x: 0,
@@ -182,6 +189,33 @@ impl SdlAdapter {
             _ => {}
         }
     }
+
+    /// uploads glyph atlas pages after the texture files when atlases changed
+    fn sync_atlases(&mut self, atlases: &AtlasRegistry) {
+        let v = atlases.version();
+        if v == self.atlas_version {
+            return;
+        }
+        if let (Some(c), Some(texs)) = (&self.canvas, &mut self.asset_textures) {
+            let creator = c.texture_creator();
+            for t in texs.drain(PIXEL_TEXTURE_FILES.len()..) {
+                unsafe { t.destroy() };
+            }
+            for p in 0..atlases.page_count() {
+                let Some((w, h, img)) = atlases.page_rgba(p) else {
+                    break;
+                };
+                // rgba bytes
+                let mut t = creator
+                    .create_texture_static(PixelFormatEnum::ABGR8888, w, h)
+                    .unwrap();
+                t.update(None, &img, w as usize * 4).unwrap();
+                t.set_blend_mode(SBlendMode::Blend);
+                texs.push(t);
+            }
+            self.atlas_version = v;
+        }
+    }
 }
 
 impl Adapter for SdlAdapter {
@@ -211,22 +245,8 @@ impl Adapter for SdlAdapter {
             .map_err(|e| e.to_string())
             .unwrap();
 
-        let texture_creator = canvas.texture_creator();
-        let mut vt: Vec<Texture> = vec![];
-        for t in 0..PIXEL_TEXTURE_FILES.len() {
-            vt.push(
-                texture_creator
-                    .load_texture(format!(
-                        "games{}{}{}{}",
-                        std::path::MAIN_SEPARATOR,
-                        self.base.game_name,
-                        std::path::MAIN_SEPARATOR,
-                        PIXEL_TEXTURE_FILES[t]
-                    ))
-                    .unwrap(),
-            );
-        }
-        let rt = texture_creator
+        let rt = canvas
+            .texture_creator()
             .create_texture_target(
                 PixelFormatEnum::RGBA8888,
                 self.base.pixel_w,
@@ -234,22 +254,44 @@ impl Adapter for SdlAdapter {
             )
             .unwrap();
 
-        let surface = Surface::from_file(format!(
-                        "games{}{}{}{}",
-                        std::path::MAIN_SEPARATOR,
-                        self.base.game_name,
-                        std::path::MAIN_SEPARATOR,
-                        "assets/pix/cursor.png"))
-            .map_err(|err| format!("failed to load cursor image: {}", err))
-            .unwrap();
-        self.set_mouse_cursor(&surface);
-
         self.canvas = Some(canvas);
-        self.asset_textures = Some(vt);
         self.render_texture = Some(rt);
         self.event_pump = Some(self.context.event_pump().unwrap());
     }
 
+    fn load_textures(&mut self, am: &AssetManager) {
+        if self.asset_textures.is_some() {
+            return;
+        }
+        let game_file = |f: &str| {
+            let loc = format!(
+                "games{}{}{}{}",
+                std::path::MAIN_SEPARATOR,
+                self.base.game_name,
+                std::path::MAIN_SEPARATOR,
+                f
+            );
+            am.read_file(&loc).map_err(|e| e.to_string()).unwrap()
+        };
+        let cursor_png = game_file("assets/pix/cursor.png");
+        let files: Vec<Vec<u8>> = PIXEL_TEXTURE_FILES.iter().map(|f| game_file(f)).collect();
+        let Some(canvas) = &self.canvas else {
+            return;
+        };
+        let texture_creator = canvas.texture_creator();
+        let mut vt: Vec<Texture> = vec![];
+        for f in &files {
+            vt.push(texture_creator.load_texture_bytes(f).unwrap());
+        }
+        self.asset_textures = Some(vt);
+
+        let surface = RWops::from_bytes(&cursor_png)
+            .and_then(|rw| rw.load())
+            .map_err(|err| format!("failed to load cursor image: {}", err))
+            .unwrap();
+        self.set_mouse_cursor(&surface);
+    }
+
     fn get_base(&mut self) -> &mut AdapterBase {
         &mut self.base
     }
@@ -298,9 +340,12 @@ impl Adapter for SdlAdapter {
         current_buffer: &Buffer,
         _p: &Buffer,
         pixel_sprites: &mut Sprites,
+        atlases: &AtlasRegistry,
         stage: u32,
     ) -> Result<(), String> {
+        self.sync_atlases(atlases);
         let width = current_buffer.area.width;
+        let pal = self.base.palette.clone();
 
         if let (Some(c), Some(rt), Some(texs)) = (
             &mut self.canvas,
@@ -313,7 +358,7 @@ impl Adapter for SdlAdapter {
             c.with_texture_canvas(rt, |tc| {
                 tc.clear();
 
-                if stage <= LOGO_FRAME {
+                if stage <= self.base.logo_frames {
                     render_logo(
                         self.base.ratio_x,
                         self.base.ratio_y,
@@ -321,10 +366,11 @@ impl Adapter for SdlAdapter {
                         self.base.pixel_h,
                         &mut self.rd,
                         stage,
+                        self.base.logo_frames,
                         |fc, ss1, ss2, texidx, _symidx| {
                             let s1 = SRect::new(ss1.x, ss1.y, ss1.w, ss1.h);
                             let s2 = SRect::new(ss2.x, ss2.y, ss2.w, ss2.h);
-                            let tx = &mut texs[texidx / 4];
+                            let tx = &mut texs[texture_slot(texidx)];
                             tx.set_color_mod(fc.0, fc.1, fc.2);
                             tc.copy(tx, s1, s2).unwrap();
                         },
@@ -335,25 +381,36 @@ impl Adapter for SdlAdapter {
                 let ry = self.base.ratio_y;
                 let mut rfunc =
                     |fc: &(u8, u8, u8), s1: ARect, s2: ARect, texidx: usize, _symidx: usize| {
-                        let tx = &mut texs[texidx / 4];
+                        let tx = &mut texs[texture_slot(texidx)];
                         let ss1 = SRect::new(s1.x, s1.y, s1.w, s1.h);
                         let ss2 = SRect::new(s2.x, s2.y, s2.w, s2.h);
                         tx.set_color_mod(fc.0, fc.1, fc.2);
+                        // pixel sprites may change them
+                        tx.set_alpha_mod(255);
+                        tx.set_blend_mode(SBlendMode::Blend);
                         tc.copy(tx, ss1, ss2).unwrap();
                     };
-                if stage > LOGO_FRAME {
+                if stage > self.base.logo_frames {
                     render_border(self.base.cell_w, self.base.cell_h, rx, ry, &mut rfunc);
-                    render_main_buffer(current_buffer, width, rx, ry, &mut rfunc);
+                    render_main_buffer(current_buffer, &pal, atlases, width, rx, ry, &mut rfunc);
                     render_pixel_sprites(
                         pixel_sprites,
+                        &pal,
+                        atlases,
                         rx,
                         ry,
-                        |fc, s1, s2, texidx, _symidx, angle, ccp| {
-                            let tx = &mut texs[texidx / 4];
+                        |fc, s1, s2, texidx, _symidx, angle, ccp, alpha, blend| {
+                            let tx = &mut texs[texture_slot(texidx)];
                             let ss1 = SRect::new(s1.x, s1.y, s1.w, s1.h);
                             let ss2 = SRect::new(s2.x, s2.y, s2.w, s2.h);
                             let cccp = SPoint::new(ccp.x, ccp.y);
                             tx.set_color_mod(fc.0, fc.1, fc.2);
+                            tx.set_alpha_mod(alpha);
+                            tx.set_blend_mode(match blend {
+                                BlendMode::Normal => SBlendMode::Blend,
+                                BlendMode::Additive => SBlendMode::Add,
+                                BlendMode::Multiply => SBlendMode::Mod,
+                            });
                             tc.copy_ex(tx, ss1, ss2, angle, cccp, false, false).unwrap();
                         },
                     );
@@ -385,6 +442,10 @@ impl Adapter for SdlAdapter {
     fn as_any(&self) -> &dyn Any {
         self
     }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
 }
 
 pub fn sdl_move_win(drag_need: &mut bool, c: &mut Canvas<Window>, dx: i32, dy: i32) {
@@ -416,7 +477,20 @@ pub fn input_events_from_sdl(e: &SEvent, adjx: f32, adjy: f32) -> Option<Event>
     let sym_height = PIXEL_SYM_HEIGHT as f32;
     let mut mcte: Option<MouseEvent> = None;
     match e {
-        SEvent::KeyDown { keycode, .. } => {
+        SEvent::KeyDown {
+            keycode, repeat, ..
+        }
+        | SEvent::KeyUp {
+            keycode, repeat, ..
+        } => {
+            // ActionMap repeats held keys itself
+            if *repeat {
+                return None;
+            }
+            let kind = match e {
+                SEvent::KeyUp { .. } => KeyEventKind::Release,
+                _ => KeyEventKind::Press,
+            };
             let kc;
             match keycode {
                 Some(SKeycode::Space) => kc = ' ',
@@ -450,7 +524,7 @@ pub fn input_events_from_sdl(e: &SEvent, adjx: f32, adjy: f32) -> Option<Event>
                     return None;
                 }
             }
-            let cte = KeyEvent::new(KeyCode::Char(kc), KeyModifiers::NONE);
+            let cte = KeyEvent::new_with_kind(KeyCode::Char(kc), KeyModifiers::NONE, kind);
             return Some(Event::Key(cte));
         }
         SEvent::MouseButtonUp { x, y, .. } => {
@@ -7,7 +7,8 @@
 //! all web related processing is handled here.
 
 use crate::event::{
-    Event, KeyCode, KeyEvent, KeyModifiers, MouseButton::*, MouseEvent, MouseEventKind::*,
+    Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseButton::*, MouseEvent,
+    MouseEventKind::*,
 };
 use crate::{
     render::{
@@ -15,11 +16,12 @@ use crate::{
             render_border, render_logo, render_main_buffer, render_pixel_sprites,
             APoint, ARect, Adapter, AdapterBase, PIXEL_SYM_HEIGHT, PIXEL_SYM_WIDTH,
         },
+        atlas::{web::share, AtlasRegistry, ATLAS_TEXTURE_START},
         buffer::Buffer,
         sprite::Sprites,
+        style::BlendMode,
     },
     util::Rand,
-    LOGO_FRAME,
 };
 use log::info;
 use std::any::Any;
@@ -40,6 +42,10 @@ pub struct WebCell {
     pub angle: u32,
     pub cx: i32,
     pub cy: i32,
+    // opacity 0~255
+    pub alpha: u32,
+    // BlendMode as 0 normal, 1 additive, 2 multiply
+    pub blend: u32,
 }
 
 pub struct WebAdapter {
@@ -67,14 +73,21 @@ impl WebAdapter {
         s: ARect,
         angle: f64,
         ccp: &APoint,
+        alpha: u8,
+        blend: BlendMode,
     ) {
         let mut wc: WebCell = Default::default();
         wc.r = r as u32;
         wc.g = g as u32;
         wc.b = b as u32;
-        let y = symidx as u32 / 16u32 + (texidx as u32 / 2u32) * 16u32;
-        let x = symidx as u32 % 16u32 + (texidx as u32 % 2u32) * 16u32;
-        wc.texsym = y * 32u32 + x;
+        if texidx >= ATLAS_TEXTURE_START as usize {
+            // glyph atlas pages follow the 32 x 32 symbols of c64.png
+            wc.texsym = 1024 + (texidx as u32 - ATLAS_TEXTURE_START as u32) * 256 + symidx as u32;
+        } else {
+            let y = symidx as u32 / 16u32 + (texidx as u32 / 2u32) * 16u32;
+            let x = symidx as u32 % 16u32 + (texidx as u32 % 2u32) * 16u32;
+            wc.texsym = y * 32u32 + x;
+        }
         wc.x = s.x;
         wc.y = s.y;
         wc.w = s.w;
@@ -94,6 +107,12 @@ impl WebAdapter {
         }
         wc.cx = ccp.x as i32;
         wc.cy = ccp.y as i32;
+        wc.alpha = alpha as u32;
+        wc.blend = match blend {
+            BlendMode::Normal => 0,
+            BlendMode::Additive => 1,
+            BlendMode::Multiply => 2,
+        };
         self.web_buf.push(wc);
     }
 }
@@ -131,11 +150,15 @@ impl Adapter for WebAdapter {
         current_buffer: &Buffer,
         _p: &Buffer,
         pixel_sprites: &mut Sprites,
+        atlases: &AtlasRegistry,
         stage: u32,
     ) -> Result<(), String> {
         self.web_buf.clear();
+        // index.js reads the atlas pages through the exports of atlas::web
+        share(atlases);
         let width = current_buffer.area.width;
-        if stage <= LOGO_FRAME {
+        let pal = self.base.palette.clone();
+        if stage <= self.base.logo_frames {
             let mut tv = vec![];
             render_logo(
                 self.base.ratio_x,
@@ -144,6 +167,7 @@ impl Adapter for WebAdapter {
                 self.base.pixel_h,
                 &mut self.rd,
                 stage,
+                self.base.logo_frames,
                 |fc, _s1, s2, texidx, symidx| {
                     tv.push((fc.0, fc.1, fc.2, texidx, symidx, s2));
                 },
@@ -158,6 +182,8 @@ impl Adapter for WebAdapter {
                     tmp.5,
                     0.0,
                     &APoint { x: 0, y: 0 },
+                    255,
+                    BlendMode::Normal,
                 );
             }
             return Ok(());
@@ -167,6 +193,7 @@ impl Adapter for WebAdapter {
         let ch = self.base.cell_h;
         let rx = self.base.ratio_x;
         let ry = self.base.ratio_y;
+        let logo_frames = self.base.logo_frames;
         let mut rfunc = |fc: &(u8, u8, u8), _s1: ARect, s2: ARect, texidx: usize, symidx: usize| {
             self.push_web_buffer(
                 fc.0,
@@ -177,19 +204,25 @@ impl Adapter for WebAdapter {
                 s2,
                 0.0,
                 &APoint { x: 0, y: 0 },
+                255,
+                BlendMode::Normal,
             );
         };
         render_border(cw, ch, rx, ry, &mut rfunc);
-        if stage > LOGO_FRAME {
-            render_main_buffer(current_buffer, width, rx, ry, &mut rfunc);
+        if stage > logo_frames {
+            render_main_buffer(current_buffer, &pal, atlases, width, rx, ry, &mut rfunc);
         }
-        if stage > LOGO_FRAME {
+        if stage > logo_frames {
             render_pixel_sprites(
                 pixel_sprites,
+                &pal,
+                atlases,
                 rx,
                 ry,
-                |fc, _s1, s2, texidx, symidx, angle, ccp| {
-                    self.push_web_buffer(fc.0, fc.1, fc.2, texidx, symidx, s2, angle, &ccp);
+                |fc, _s1, s2, texidx, symidx, angle, ccp, alpha, blend| {
+                    self.push_web_buffer(
+                        fc.0, fc.1, fc.2, texidx, symidx, s2, angle, &ccp, alpha, blend,
+                    );
                 },
             );
         }
@@ -215,6 +248,10 @@ impl Adapter for WebAdapter {
     fn as_any(&self) -> &dyn Any {
         self
     }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
 }
 
 macro_rules! web_event {
@@ -238,20 +275,30 @@ pub fn input_events_from_web(t: u8, e: web_sys::Event, ratiox: f32, ratioy: f32)
     let mut mcte: Option<MouseEvent> = None;
 
     if let Some(key_e) = wasm_bindgen::JsCast::dyn_ref::<web_sys::KeyboardEvent>(&e) {
-        assert!(t == 0);
-        let kcc = (key_e.key_code(), key_e.char_code());
-        match kcc.0 {
-            32 | 48..=57 | 97..=122 => {
-                let cte = KeyEvent::new(
-                    KeyCode::Char(char::from_u32(kcc.0).unwrap()),
-                    KeyModifiers::NONE,
-                );
-                return Some(Event::Key(cte));
-            }
+        // ActionMap repeats held keys itself
+        if key_e.repeat() {
+            return None;
+        }
+        // keypress(0) reports the char, keyup(4) the key with letters in upper case
+        let kc = match (t, key_e.key_code()) {
+            (0, c @ (32 | 48..=57 | 97..=122)) => c,
+            (4, c @ (32 | 48..=57)) => c,
+            (4, c @ 65..=90) => c + 32,
             _ => {
                 return None;
             }
-        }
+        };
+        let kind = if t == 4 {
+            KeyEventKind::Release
+        } else {
+            KeyEventKind::Press
+        };
+        let cte = KeyEvent::new_with_kind(
+            KeyCode::Char(char::from_u32(kc).unwrap()),
+            KeyModifiers::NONE,
+            kind,
+        );
+        return Some(Event::Key(cte));
     }
 
     if let Some(mouse_e) = wasm_bindgen::JsCast::dyn_ref::<web_sys::MouseEvent>(&e) {
@@ -0,0 +1,558 @@
+// RustPixel
+// copyright zhouxin@tuyoogame.com 2022~2024
+
+//! Glyph atlases draw text with custom fonts in graphical mode.
+//!
+//! By default graphical mode only has the c64 texture (assets/pix/c64.png),
+//! set_str can draw the latin letters listed in CELL_SYM_MAP and nothing
+//! else. A GlyphAtlas holds the glyphs of a PNG tileset, a BDF font or a PSF
+//! console font and maps unicode chars to them.
+//!
+//! Atlases are registered in the AtlasRegistry of the game, ctx.atlases.
+//! They are split into pages of 256 glyphs, each page takes a texture index
+//! after the c64 textures (ATLAS_TEXTURE_START). Cells keep the char of
+//! their symbol in draw_history, the adapters look it up in the registered
+//! atlases first, in registration order, so a CJK font can back up a latin
+//! one. Symbols not found fall back to cellsym and CELL_SYM_MAP as before.
+//!
+//! Glyphs are scaled to fit the cell keeping their aspect, double width
+//! chars span two cells. The SDL adapter uploads pages when the registry
+//! changes, the web adapter sends glyphs as texsym 1024 + page * 256 + sym
+//! and web-templates/index.js makes textures of the pages with the exports
+//! of the web module below.
+//!
+//! Text mode ignores atlases, the terminal draws the chars itself.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let font = GlyphAtlas::load("assets/font/unifont.bdf", 0, 0, "")?;
+//! ctx.atlases.register(font)?;
+//! let sheet = GlyphAtlas::load("assets/font/ascii.png", 8, 8, &ascii_chars())?;
+//! ctx.atlases.register(sheet)?;
+//! buf.set_str(0, 0, "Hello 世界", Style::default().fg(Color::Yellow));
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use unicode_width::UnicodeWidthChar;
+
+/// texture index of the first atlas page, textures before it are
+/// the 4 parts of c64.png
+pub const ATLAS_TEXTURE_START: u8 = 4;
+
+/// glyphs per page, one texture index each
+pub const ATLAS_PAGE_SIZE: usize = 256;
+
+/// printable ascii chars, 32 ~ 126, the usual order of font sheets
+pub fn ascii_chars() -> String {
+    (32u8..127).map(|c| c as char).collect()
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct GlyphAtlas {
+    pub name: String,
+    // size of a glyph slot in pixels
+    pub glyph_w: u16,
+    pub glyph_h: u16,
+    // alpha of each glyph, glyph_w * glyph_h bytes row by row
+    pub glyphs: Vec<Vec<u8>>,
+    // advance width of each glyph in pixels, <= glyph_w
+    pub widths: Vec<u16>,
+    pub chars: HashMap<char, usize>,
+}
+
+impl GlyphAtlas {
+    pub fn new(name: &str, glyph_w: u16, glyph_h: u16) -> Self {
+        Self {
+            name: name.to_string(),
+            glyph_w,
+            glyph_h,
+            ..Default::default()
+        }
+    }
+
+    /// adds a glyph of advance width w and returns its index,
+    /// alpha is cut or padded to glyph_w * glyph_h
+    pub fn add_glyph(&mut self, alpha: Vec<u8>, w: u16) -> usize {
+        let mut alpha = alpha;
+        alpha.resize(self.glyph_w as usize * self.glyph_h as usize, 0);
+        self.glyphs.push(alpha);
+        self.widths.push(w.min(self.glyph_w));
+        self.glyphs.len() - 1
+    }
+
+    /// maps ch to glyph idx, the first mapping of a char is kept
+    pub fn map(&mut self, ch: char, idx: usize) {
+        if idx < self.glyphs.len() {
+            self.chars.entry(ch).or_insert(idx);
+        }
+    }
+
+    pub fn glyph(&self, ch: char) -> Option<&[u8]> {
+        self.chars.get(&ch).map(|i| self.glyphs[*i].as_slice())
+    }
+
+    pub fn pages(&self) -> usize {
+        self.glyphs.len().div_ceil(ATLAS_PAGE_SIZE)
+    }
+
+    /// loads .png, .bdf or .psf by the file extension,
+    /// glyph_w, glyph_h and chars are only used by png
+    pub fn load(path: &str, glyph_w: u16, glyph_h: u16, chars: &str) -> Result<Self, String> {
+        let p = Path::new(path);
+        let name = p.file_stem().and_then(|n| n.to_str()).unwrap_or("");
+        let data = fs::read(path).map_err(|e| format!("atlas: {} {}", path, e))?;
+        match p.extension().and_then(|e| e.to_str()) {
+            Some("png") => Self::from_png(name, &data, glyph_w, glyph_h, 0, chars),
+            Some("bdf") => Self::from_bdf(name, &String::from_utf8_lossy(&data)),
+            Some("psf") => Self::from_psf(name, &data),
+            _ => Err(format!("atlas: unknown file type {}", path)),
+        }
+    }
+
+    /// cuts a png tileset into glyph_w x glyph_h glyphs, row by row,
+    /// with gap pixels between them. chars names the glyphs in order.
+    /// Alpha comes from the alpha channel, or the brightness if there is none
+    pub fn from_png(
+        name: &str,
+        data: &[u8],
+        glyph_w: u16,
+        glyph_h: u16,
+        gap: u16,
+        chars: &str,
+    ) -> Result<Self, String> {
+        if glyph_w == 0 || glyph_h == 0 {
+            return Err("atlas: glyph size is 0".to_string());
+        }
+        let mut dec = png::Decoder::new(data);
+        dec.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = dec.read_info().map_err(|e| format!("atlas: {}", e))?;
+        let mut img = vec![0; reader.output_buffer_size()];
+        let info = reader
+            .next_frame(&mut img)
+            .map_err(|e| format!("atlas: {}", e))?;
+        let n = info.color_type.samples();
+        let (iw, ih) = (info.width as usize, info.height as usize);
+        let alpha_at = |x: usize, y: usize| {
+            let p = &img[y * info.line_size + x * n..][..n];
+            match n {
+                2 | 4 => p[n - 1],
+                _ => *p.iter().max().unwrap(),
+            }
+        };
+
+        let mut atlas = Self::new(name, glyph_w, glyph_h);
+        let (gw, gh, gap) = (glyph_w as usize, glyph_h as usize, gap as usize);
+        let cols = (iw + gap) / (gw + gap);
+        let rows = (ih + gap) / (gh + gap);
+        for r in 0..rows {
+            for c in 0..cols {
+                let (x0, y0) = (c * (gw + gap), r * (gh + gap));
+                let mut alpha = Vec::with_capacity(gw * gh);
+                for y in 0..gh {
+                    for x in 0..gw {
+                        alpha.push(alpha_at(x0 + x, y0 + y));
+                    }
+                }
+                atlas.add_glyph(alpha, glyph_w);
+            }
+        }
+        for (i, ch) in chars.chars().enumerate() {
+            atlas.map(ch, i);
+        }
+        Ok(atlas)
+    }
+
+    /// parses a BDF bitmap font, the glyph size is its FONTBOUNDINGBOX
+    pub fn from_bdf(name: &str, s: &str) -> Result<Self, String> {
+        let nums = |l: &str| -> Vec<i32> {
+            l.split_whitespace()
+                .skip(1)
+                .filter_map(|n| n.parse().ok())
+                .collect()
+        };
+        let mut lines = s.lines();
+        let fbb = lines
+            .by_ref()
+            .find(|l| l.starts_with("FONTBOUNDINGBOX"))
+            .map(nums)
+            .filter(|v| v.len() == 4 && v[0] > 0 && v[1] > 0)
+            .ok_or("atlas: bdf has no FONTBOUNDINGBOX".to_string())?;
+        let (fw, fh, fx, fy) = (fbb[0], fbb[1], fbb[2], fbb[3]);
+        // baseline from the top of a glyph slot
+        let ascent = fh + fy;
+
+        let mut atlas = Self::new(name, fw as u16, fh as u16);
+        let mut enc = -1;
+        let mut bbx = vec![fw, fh, fx, fy];
+        let mut dw = fw;
+        while let Some(l) = lines.next() {
+            let key = l.split_whitespace().next().unwrap_or("");
+            match key {
+                "ENCODING" => enc = nums(l).first().copied().unwrap_or(-1),
+                "DWIDTH" => dw = nums(l).first().copied().unwrap_or(fw),
+                "BBX" => bbx = nums(l),
+                "BITMAP" => {
+                    if bbx.len() != 4 {
+                        return Err(format!("atlas: bad bdf BBX near ENCODING {}", enc));
+                    }
+                    let (bw, bh, bx, by) = (bbx[0], bbx[1], bbx[2], bbx[3]);
+                    let mut alpha = vec![0u8; (fw * fh) as usize];
+                    let top = ascent - (by + bh);
+                    for row in 0..bh {
+                        let hex = lines.next().unwrap_or("").trim();
+                        let bits = u128::from_str_radix(hex, 16).unwrap_or(0);
+                        let nbits = hex.len() as i32 * 4;
+                        for col in 0..bw.min(nbits) {
+                            if (bits >> (nbits - 1 - col)) & 1 == 0 {
+                                continue;
+                            }
+                            let (x, y) = (bx - fx + col, top + row);
+                            if x >= 0 && y >= 0 && x < fw && y < fh {
+                                alpha[(y * fw + x) as usize] = 255;
+                            }
+                        }
+                    }
+                    let idx = atlas.add_glyph(alpha, dw.clamp(0, fw) as u16);
+                    if let Some(ch) = u32::try_from(enc).ok().and_then(char::from_u32) {
+                        atlas.map(ch, idx);
+                    }
+                    // defaults for the next glyph
+                    enc = -1;
+                    bbx = vec![fw, fh, fx, fy];
+                    dw = fw;
+                }
+                _ => {}
+            }
+        }
+        Ok(atlas)
+    }
+
+    /// parses a PSF1 or PSF2 console font, glyphs are mapped by its unicode
+    /// table, or to chars 0 ~ 255 by index if it has none
+    pub fn from_psf(name: &str, data: &[u8]) -> Result<Self, String> {
+        let u32_at = |i: usize| -> u32 {
+            data.get(i..i + 4)
+                .map_or(0, |b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        };
+        let (w, h, count, size, start, table) = if data.starts_with(&[0x36, 0x04]) {
+            let mode = *data.get(2).unwrap_or(&0);
+            let size = *data.get(3).unwrap_or(&0) as usize;
+            let count = if mode & 0x01 != 0 { 512 } else { 256 };
+            (8, size, count, size, 4, mode & 0x06 != 0)
+        } else if data.starts_with(&[0x72, 0xb5, 0x4a, 0x86]) {
+            let (start, flags, count) = (u32_at(8) as usize, u32_at(12), u32_at(16) as usize);
+            let (size, h, w) = (
+                u32_at(20) as usize,
+                u32_at(24) as usize,
+                u32_at(28) as usize,
+            );
+            (w, h, count, size, start, flags & 0x01 != 0)
+        } else {
+            return Err("atlas: not a psf font".to_string());
+        };
+        let row = w.div_ceil(8);
+        if w == 0 || h == 0 || size < row * h || data.len() < start + count * size {
+            return Err("atlas: bad psf header".to_string());
+        }
+
+        let mut atlas = Self::new(name, w as u16, h as u16);
+        for g in 0..count {
+            let bm = &data[start + g * size..][..size];
+            let mut alpha = vec![0u8; w * h];
+            for y in 0..h {
+                for x in 0..w {
+                    if bm[y * row + x / 8] & (0x80 >> (x % 8)) != 0 {
+                        alpha[y * w + x] = 255;
+                    }
+                }
+            }
+            atlas.add_glyph(alpha, w as u16);
+        }
+
+        let mut pos = start + count * size;
+        if !table {
+            for g in 0..count.min(256) {
+                atlas.map(g as u8 as char, g);
+            }
+        } else if data.starts_with(&[0x36, 0x04]) {
+            // u16 values per glyph, 0xfffe starts sequences, 0xffff ends
+            for g in 0..count {
+                let mut seq = false;
+                while let Some(b) = data.get(pos..pos + 2) {
+                    pos += 2;
+                    match u16::from_le_bytes([b[0], b[1]]) {
+                        0xffff => break,
+                        0xfffe => seq = true,
+                        u if !seq => {
+                            if let Some(ch) = char::from_u32(u as u32) {
+                                atlas.map(ch, g);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        } else {
+            // utf8 chars per glyph, 0xfe starts sequences, 0xff ends
+            for g in 0..count {
+                let end = data[pos..]
+                    .iter()
+                    .position(|b| *b == 0xff)
+                    .map_or(data.len(), |i| pos + i);
+                let single = data[pos..end].split(|b| *b == 0xfe).next().unwrap_or(&[]);
+                for ch in String::from_utf8_lossy(single).chars() {
+                    atlas.map(ch, g);
+                }
+                pos = (end + 1).min(data.len());
+            }
+        }
+        Ok(atlas)
+    }
+}
+
+/// sym, texture, source rect (x, y, w, h) in its page image
+/// and the cells the glyph spans
+pub type AtlasGlyph = (u8, u8, (u32, u32, u32, u32), u16);
+
+/// the atlases of a game, kept in Context::atlases
+#[derive(Debug, Clone, Default)]
+pub struct AtlasRegistry {
+    atlases: Vec<GlyphAtlas>,
+    // char to (sym, texture), the first atlas having a char wins
+    chars: HashMap<char, (u8, u8)>,
+    // (sym, texture) of double width chars
+    wide: HashSet<(u8, u8)>,
+    // bumped on every change, adapters upload pages again when it differs
+    version: usize,
+}
+
+impl AtlasRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // (atlas, first glyph) of texture tex
+    fn page(&self, tex: u8) -> Option<(&GlyphAtlas, usize)> {
+        let mut p = tex.checked_sub(ATLAS_TEXTURE_START)? as usize;
+        for a in &self.atlases {
+            if p < a.pages() {
+                return Some((a, p * ATLAS_PAGE_SIZE));
+            }
+            p -= a.pages();
+        }
+        None
+    }
+
+    fn rebuild(&mut self) {
+        self.chars.clear();
+        self.wide.clear();
+        let mut tex = ATLAS_TEXTURE_START as usize;
+        for a in &self.atlases {
+            for (ch, i) in &a.chars {
+                let t = tex + i / ATLAS_PAGE_SIZE;
+                if t > u8::MAX as usize || self.chars.contains_key(ch) {
+                    continue;
+                }
+                let g = ((i % ATLAS_PAGE_SIZE) as u8, t as u8);
+                self.chars.insert(*ch, g);
+                if ch.width() == Some(2) {
+                    self.wide.insert(g);
+                }
+            }
+            tex += a.pages();
+        }
+        self.version += 1;
+    }
+
+    /// registers an atlas for graphical mode, replacing the one with the same name.
+    /// Returns the texture index of its first page
+    pub fn register(&mut self, atlas: GlyphAtlas) -> Result<u8, String> {
+        self.atlases.retain(|a| a.name != atlas.name);
+        let used = self.page_count();
+        let first = ATLAS_TEXTURE_START as usize + used;
+        if first + atlas.pages() > u8::MAX as usize + 1 {
+            return Err(format!("atlas: no texture index left for {}", atlas.name));
+        }
+        self.atlases.push(atlas);
+        self.rebuild();
+        Ok(first as u8)
+    }
+
+    pub fn unregister(&mut self, name: &str) {
+        let n = self.atlases.len();
+        self.atlases.retain(|a| a.name != name);
+        if self.atlases.len() != n {
+            self.rebuild();
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.atlases.is_empty()
+    }
+
+    /// (sym, texture) of a one char symbol
+    pub fn glyph(&self, symbol: &str) -> Option<(u8, u8)> {
+        let mut cs = symbol.chars();
+        let ch = cs.next()?;
+        if cs.next().is_some() {
+            return None;
+        }
+        self.chars.get(&ch).copied()
+    }
+
+    /// the glyph ch is drawn with, None if no atlas has it
+    pub fn lookup(&self, ch: char) -> Option<AtlasGlyph> {
+        let (sym, tex) = *self.chars.get(&ch)?;
+        let (rect, span) = self.glyph_rect(tex, sym)?;
+        Some((sym, tex, rect, span))
+    }
+
+    /// source rect (x, y, w, h) of a glyph in its page image,
+    /// and the cells it spans, None if tex is not an atlas page
+    pub fn glyph_rect(&self, tex: u8, sym: u8) -> Option<((u32, u32, u32, u32), u16)> {
+        let (a, first) = self.page(tex)?;
+        let (gw, gh) = (a.glyph_w as u32, a.glyph_h as u32);
+        let w = a.widths.get(first + sym as usize).copied().unwrap_or(0) as u32;
+        let span = if self.wide.contains(&(sym, tex)) { 2 } else { 1 };
+        let (col, row) = (sym as u32 % 16, sym as u32 / 16);
+        Some(((col * gw, row * gh, w, gh), span))
+    }
+
+    /// changes whenever atlases are registered or removed
+    pub fn version(&self) -> usize {
+        self.version
+    }
+
+    /// number of atlas pages, texture indexes from ATLAS_TEXTURE_START
+    pub fn page_count(&self) -> usize {
+        self.atlases.iter().map(|a| a.pages()).sum()
+    }
+
+    /// image of page p as (width, height, rgba), 16 x 16 glyphs in white
+    /// with their alpha, drawn tinted with the fg color like c64.png
+    pub fn page_rgba(&self, p: usize) -> Option<(u32, u32, Vec<u8>)> {
+        let (a, first) = self.page((ATLAS_TEXTURE_START as usize + p).try_into().ok()?)?;
+        let (gw, gh) = (a.glyph_w as usize, a.glyph_h as usize);
+        let (w, h) = (gw * 16, gh * 16);
+        let mut img = vec![0u8; w * h * 4];
+        for (i, g) in a.glyphs[first..].iter().take(ATLAS_PAGE_SIZE).enumerate() {
+            let (x0, y0) = (i % 16 * gw, i / 16 * gh);
+            for (k, al) in g.iter().enumerate() {
+                let o = ((y0 + k / gw) * w + x0 + k % gw) * 4;
+                img[o..o + 4].copy_from_slice(&[255, 255, 255, *al]);
+            }
+        }
+        Some((w as u32, h as u32, img))
+    }
+}
+
+// exports for the js renderer in web-templates/index.js,
+// they read the copy of the game's registry shared by the web adapter
+#[cfg(target_arch = "wasm32")]
+pub mod web {
+    use super::{AtlasRegistry, ATLAS_TEXTURE_START};
+    use std::cell::RefCell;
+    use wasm_bindgen::prelude::*;
+
+    thread_local! {
+        static SHARED: RefCell<AtlasRegistry> = RefCell::new(AtlasRegistry::new());
+    }
+
+    /// copies reg for the exports when it changed, called by WebAdapter::render_buffer
+    pub fn share(reg: &AtlasRegistry) {
+        SHARED.with(|s| {
+            if s.borrow().version != reg.version {
+                *s.borrow_mut() = reg.clone();
+            }
+        });
+    }
+
+    #[wasm_bindgen]
+    pub fn atlas_version() -> usize {
+        SHARED.with(|s| s.borrow().version())
+    }
+
+    /// width of page p in pixels, 0 if there is no such page
+    #[wasm_bindgen]
+    pub fn atlas_page_width(p: usize) -> u32 {
+        SHARED.with(|s| s.borrow().page_rgba(p).map_or(0, |(w, _, _)| w))
+    }
+
+    #[wasm_bindgen]
+    pub fn atlas_page_rgba(p: usize) -> Vec<u8> {
+        SHARED.with(|s| s.borrow().page_rgba(p).map_or(vec![], |(_, _, img)| img))
+    }
+
+    /// [x, y, w, h] of glyph sym in page p, empty if there is none
+    #[wasm_bindgen]
+    pub fn atlas_glyph_frame(p: usize, sym: u8) -> Vec<u32> {
+        let Ok(tex) = u8::try_from(ATLAS_TEXTURE_START as usize + p) else {
+            return vec![];
+        };
+        SHARED.with(|s| {
+            s.borrow()
+                .glyph_rect(tex, sym)
+                .map_or(vec![], |((x, y, w, h), _)| vec![x, y, w, h])
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // psf2, 2 glyphs of 8x2, the unicode table maps '☺' and '世'
+    fn psf() -> Vec<u8> {
+        let mut psf = vec![0x72, 0xb5, 0x4a, 0x86];
+        for v in [0u32, 32, 1, 2, 2, 2, 8] {
+            psf.extend_from_slice(&v.to_le_bytes());
+        }
+        psf.extend_from_slice(&[0x80, 0x00, 0x00, 0x01, 0xe2, 0x98, 0xba, 0xff]);
+        psf.extend_from_slice("世".as_bytes());
+        psf.push(0xff);
+        psf
+    }
+
+    #[test]
+    fn it_loads_bdf_fonts() {
+        let bdf = "STARTFONT 2.1\nFONTBOUNDINGBOX 4 4 0 -1\nSTARTCHAR A\nENCODING 65\n\
+                   DWIDTH 3 0\nBBX 2 2 1 0\nBITMAP\nC0\n40\nENDCHAR\nENDFONT\n";
+        let a = GlyphAtlas::from_bdf("t_bdf", bdf).unwrap();
+        assert_eq!((a.glyph_w, a.glyph_h, a.widths[0]), (4, 4, 3));
+        // 2x2 bitmap at x 1, bottom on the baseline at row 2
+        assert_eq!(
+            a.glyph('A').unwrap(),
+            &[0, 0, 0, 0, 0, 255, 255, 0, 0, 0, 255, 0, 0, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn it_loads_psf_fonts() {
+        let p = GlyphAtlas::from_psf("t_psf", &psf()).unwrap();
+        assert_eq!(p.glyph('☺').unwrap()[0], 255);
+        assert_eq!(p.glyph('世').unwrap()[15], 255);
+        assert!(GlyphAtlas::from_psf("bad", &[1, 2, 3]).is_err());
+    }
+
+    #[test]
+    fn it_maps_chars_of_registered_atlases() {
+        let mut reg = AtlasRegistry::new();
+        let p = GlyphAtlas::from_psf("t_reg", &psf()).unwrap();
+        let tex = reg.register(p).unwrap();
+        assert_eq!(tex, ATLAS_TEXTURE_START);
+        let (sym, t) = reg.glyph("世").unwrap();
+        let ((_, _, w, h), span) = reg.glyph_rect(t, sym).unwrap();
+        assert_eq!((sym, w, h, span), (1, 8, 2, 2));
+        assert_eq!(reg.lookup('世'), Some((1, t, (8, 0, 8, 2), 2)));
+        assert_eq!(reg.glyph("☺"), Some((0, t)));
+        let (pw, _, img) = reg.page_rgba((t - ATLAS_TEXTURE_START) as usize).unwrap();
+        assert_eq!((pw, img[3]), (128, 255));
+        let v = reg.version();
+        reg.unregister("t_reg");
+        assert_eq!(reg.glyph("世"), None);
+        assert!(reg.version() != v);
+    }
+}
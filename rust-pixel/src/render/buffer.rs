@@ -56,7 +56,16 @@
 //! Please refer to the merge and built method of push_draw_history
 //! Please refer to the flush method of SDL mode in sdl.rs
 //!
-use crate::{render::cell::Cell, render::style::Style, util::Rect};
+//! merge_blend composites a sprite with opacity and a blend mode
+//! (normal, additive, multiply) instead of just skipping blank cells,
+//! in terminal mode it is approximated by dithering and the 256 color palette.
+//!
+use crate::{
+    render::cell::Cell,
+    render::palette::Palette,
+    render::style::{BlendMode, Color, Style},
+    util::Rect,
+};
 use log::info;
 use serde::{Deserialize, Serialize};
 use std::cmp::min;
@@ -287,6 +296,20 @@ impl Buffer {
     }
 
     pub fn merge(&mut self, other: &Buffer, fast: bool) {
+        // opaque normal merges copy cells, the palette is not used
+        self.merge_blend(other, fast, 1.0, BlendMode::Normal, &Palette::xterm());
+    }
+
+    /// merges other like merge, compositing its cells with opacity in [0, 1]
+    /// and the blend mode, indexed colors are taken from pal, see blend_cell
+    pub fn merge_blend(
+        &mut self,
+        other: &Buffer,
+        fast: bool,
+        opacity: f32,
+        mode: BlendMode,
+        pal: &Palette,
+    ) {
         let area = self.area.union(other.area);
         let cell: Cell = Default::default();
         self.content.resize(area.area() as usize, cell.clone());
@@ -309,13 +332,82 @@ impl Buffer {
             let (x, y) = other.pos_of(i);
             let k = ((y - area.y) * area.width + x - area.x) as usize;
             // 增加透明支持
-            if !other.content[i].is_blank() {
+            if other.content[i].is_blank() || opacity <= 0.0 {
+                continue;
+            }
+            if opacity >= 1.0 && mode == BlendMode::Normal {
                 self.copy_cell(k, other, i);
+            } else {
+                self.blend_cell(k, x, y, &other.content[i], opacity, mode, pal);
             }
         }
         self.area = area;
     }
 
+    /// draws src over the cell at pos with opacity and blend mode.
+    ///
+    /// In graphical mode src is drawn above the symbols of the cell,
+    /// with its fg color blended with the color of the top one.
+    ///
+    /// Terminal cells show one symbol only, so the symbols are dithered:
+    /// src replaces the cell where opacity passes a 4x4 ordered dither of
+    /// the position (x, y), a multiply keeps the symbol below if it has one.
+    /// The blended colors are rgb, the adapter maps them to the colors
+    /// the terminal can show.
+    #[allow(clippy::too_many_arguments)]
+    pub fn blend_cell(
+        &mut self,
+        pos: usize,
+        x: u16,
+        y: u16,
+        src: &Cell,
+        opacity: f32,
+        mode: BlendMode,
+        pal: &Palette,
+    ) {
+        let dst = &mut self.content[pos];
+        #[cfg(any(feature = "sdl", target_arch = "wasm32"))]
+        {
+            let _ = (x, y);
+            let below = dst.draw_history.last().map_or(Color::Black, |h| h.1);
+            let fc = src.draw_fg().blend_in(pal, below, mode, opacity);
+            let history = std::mem::take(&mut dst.draw_history);
+            *dst = src.clone();
+            dst.draw_history = history;
+            dst.draw_history.push((src.get_cell_info(), fc, src.glyph_char()));
+        }
+        #[cfg(not(any(feature = "sdl", target_arch = "wasm32")))]
+        {
+            use crate::render::style::BAYER4;
+            let dst_blank = dst.symbol == " ";
+            // color seen below the symbol, the default bg is taken as black
+            let dst_fg = if dst_blank { dst.bg } else { dst.fg };
+            let dst_fg = if dst_fg == Color::Reset { Color::Black } else { dst_fg };
+            let dst_bg = if dst.bg == Color::Reset { Color::Black } else { dst.bg };
+            let show = match mode {
+                BlendMode::Multiply => dst_blank,
+                _ => (BAYER4[(y % 4 * 4 + x % 4) as usize] + 0.5) / 16.0 < opacity,
+            };
+            if src.fg != Color::Reset {
+                let fg = src.fg.blend_in(pal, dst_fg, mode, opacity);
+                if show {
+                    dst.set_symbol(&src.symbol);
+                    dst.modifier = src.modifier;
+                    dst.fg = fg;
+                } else if !dst_blank {
+                    // the symbol below is tinted
+                    dst.fg = src.fg.blend_in(pal, dst_fg, mode, opacity * 0.5);
+                }
+            } else if show {
+                dst.set_symbol(&src.symbol);
+                dst.modifier = src.modifier;
+            }
+            if src.bg != Color::Reset {
+                dst.bg = src.bg.blend_in(pal, dst_bg, mode, opacity);
+            }
+        }
+    }
+
     /// Builds a minimal sequence of coordinates and Cells necessary to update the UI from
     /// self to other.
     pub fn diff<'a>(&self, other: &'a Buffer) -> Vec<(u16, u16, &'a Cell)> {
@@ -368,4 +460,47 @@ mod tests {
         assert_eq!(buf.pos_of(buf.content.len() - 1), (249, 179));
         assert_eq!(buf.index_of(249, 179), buf.content.len() - 1);
     }
+
+    // 4x4 red #s over blue "ab"
+    #[cfg(not(any(feature = "sdl", target_arch = "wasm32")))]
+    fn blend_layers() -> (Buffer, Buffer) {
+        let mut top = Buffer::empty(Rect::new(0, 0, 4, 4));
+        for y in 0..4 {
+            top.set_str(0, y, "####", Style::default().fg(Color::Rgb(255, 0, 0)));
+        }
+        let mut base = Buffer::empty(Rect::new(0, 0, 4, 4));
+        base.set_str(0, 0, "ab", Style::default().fg(Color::Rgb(0, 0, 255)));
+        (top, base)
+    }
+
+    #[test]
+    #[cfg(not(any(feature = "sdl", target_arch = "wasm32")))]
+    fn it_dithers_half_opacity_on_terminals() {
+        let (top, mut b) = blend_layers();
+        b.merge_blend(&top, true, 0.5, BlendMode::Normal, &Palette::xterm());
+        let shown = b.content.iter().filter(|c| c.symbol == "#").count();
+        assert_eq!(shown, 8);
+        assert!(b.content.iter().all(|c| matches!(c.fg, Color::Rgb(..) | Color::Reset)));
+    }
+
+    #[test]
+    #[cfg(not(any(feature = "sdl", target_arch = "wasm32")))]
+    fn it_keeps_symbols_below_on_multiply() {
+        let (top, mut b) = blend_layers();
+        b.merge_blend(&top, true, 1.0, BlendMode::Multiply, &Palette::xterm());
+        assert_eq!(b.get(0, 0).symbol, "a");
+        assert_eq!(b.get(2, 0).symbol, "#");
+    }
+
+    #[test]
+    #[cfg(not(any(feature = "sdl", target_arch = "wasm32")))]
+    fn it_merges_opaque_normal_as_before() {
+        let (top, base) = blend_layers();
+        let mut b = base.clone();
+        b.merge_blend(&top, true, 1.0, BlendMode::Normal, &Palette::xterm());
+        let mut m = base.clone();
+        m.merge(&top, true);
+        assert_eq!(b, m);
+        assert_eq!(b.get(0, 0).fg, Color::Rgb(255, 0, 0));
+    }
 }
@@ -0,0 +1,316 @@
+// RustPixel
+// copyright zhouxin@tuyoogame.com 2022~2024
+
+//! Camera shows a part of a world larger than the screen in a viewport.
+//!
+//! World coordinates are signed cells, the camera position is the world
+//! point shown at the top-left of its viewport (a screen Rect).
+//! A camera can follow a target, smoothly if follow_speed is set,
+//! stay inside world bounds, and shake for hits and explosions.
+//! Call update(dt) once per frame.
+//!
+//! Sprites placed in the world (Sprite::set_world_pos) are drawn through a
+//! camera with Frame::render_camera or Sprites::render_all_camera, which
+//! translates them at render time and clips them to the viewport.
+//! Screen sprites like a HUD are drawn as usual.
+//! Several cameras with different viewports give split-screen,
+//! e.g. one per player board.
+//!
+//! Pixel sprites are positioned by the game, use world_to_screen for them.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let mut cams = Camera::split(ctx.adapter.size(), 2, true);
+//! cams[0].follow(p1.x as f32, p1.y as f32);
+//! cams[0].set_bounds(0, 0, MAP_W, MAP_H);
+//! cams[0].update(dt);
+//! self.panel.draw(ctx, |a, f| {
+//!     for c in &cams {
+//!         self.world.render_all_camera(a, f, c);
+//!     }
+//!     self.hud.render_all(a, f);
+//! })?;
+//!
+//! // mouse click to world
+//! if let Some((wx, wy)) = cams[0].mouse_to_world(&e) { ... }
+//! ```
+
+use crate::{
+    asset::AssetManager,
+    event::Event,
+    render::{buffer::Buffer, palette::Palette, sprite::Sprite},
+    util::{Rand, Rect},
+};
+
+pub struct Camera {
+    // screen area the camera draws to
+    pub viewport: Rect,
+    // world position of the viewport's top-left
+    pub x: f32,
+    pub y: f32,
+    // world point kept at the viewport center
+    pub target: Option<(f32, f32)>,
+    // how fast the camera catches up with target,
+    // about the part of the distance covered per second, 0.0 snaps at once
+    pub follow_speed: f32,
+    // world area (x, y, w, h) the viewport stays in
+    pub bounds: Option<(i32, i32, i32, i32)>,
+    shake_amp: f32,
+    shake_time: f32,
+    shake_left: f32,
+    shake: (i32, i32),
+    rand: Rand,
+}
+
+impl Camera {
+    pub fn new(viewport: Rect) -> Self {
+        Self {
+            viewport,
+            x: 0.0,
+            y: 0.0,
+            target: None,
+            follow_speed: 0.0,
+            bounds: None,
+            shake_amp: 0.0,
+            shake_time: 0.0,
+            shake_left: 0.0,
+            shake: (0, 0),
+            rand: Rand::new(),
+        }
+    }
+
+    /// splits area into n viewports side by side, or stacked if not horizontal
+    pub fn split(area: Rect, n: u16, horizontal: bool) -> Vec<Camera> {
+        let n = n.max(1);
+        (0..n)
+            .map(|i| {
+                let vp = if horizontal {
+                    let w = area.width / n;
+                    Rect::new(area.x + i * w, area.y, w, area.height)
+                } else {
+                    let h = area.height / n;
+                    Rect::new(area.x, area.y + i * h, area.width, h)
+                };
+                Camera::new(vp)
+            })
+            .collect()
+    }
+
+    /// moves the camera at once so (wx, wy) is at the viewport center
+    pub fn look_at(&mut self, wx: f32, wy: f32) {
+        self.x = wx - self.viewport.width as f32 / 2.0;
+        self.y = wy - self.viewport.height as f32 / 2.0;
+        self.clamp();
+    }
+
+    pub fn follow(&mut self, wx: f32, wy: f32) {
+        self.target = Some((wx, wy));
+    }
+
+    pub fn set_follow_speed(&mut self, s: f32) {
+        self.follow_speed = s.max(0.0);
+    }
+
+    pub fn set_bounds(&mut self, x: i32, y: i32, w: i32, h: i32) {
+        self.bounds = Some((x, y, w, h));
+        self.clamp();
+    }
+
+    pub fn clear_bounds(&mut self) {
+        self.bounds = None;
+    }
+
+    /// shakes the camera by up to amp cells, fading out in secs seconds
+    pub fn shake(&mut self, amp: f32, secs: f32) {
+        self.shake_amp = amp.max(0.0);
+        self.shake_time = secs.max(0.0);
+        self.shake_left = self.shake_time;
+    }
+
+    // keeps the viewport inside bounds, a world smaller than it is centered
+    fn clamp(&mut self) {
+        if let Some((bx, by, bw, bh)) = self.bounds {
+            let clamp_axis = |v: f32, b: i32, bs: i32, vs: u16| {
+                let (b, bs, vs) = (b as f32, bs as f32, vs as f32);
+                if bs <= vs {
+                    b + (bs - vs) / 2.0
+                } else {
+                    v.clamp(b, b + bs - vs)
+                }
+            };
+            self.x = clamp_axis(self.x, bx, bw, self.viewport.width);
+            self.y = clamp_axis(self.y, by, bh, self.viewport.height);
+        }
+    }
+
+    /// follows the target and advances the shake
+    pub fn update(&mut self, dt: f32) {
+        if let Some((tx, ty)) = self.target {
+            let dx = tx - self.viewport.width as f32 / 2.0 - self.x;
+            let dy = ty - self.viewport.height as f32 / 2.0 - self.y;
+            let k = if self.follow_speed > 0.0 {
+                1.0 - (-self.follow_speed * dt).exp()
+            } else {
+                1.0
+            };
+            self.x += dx * k;
+            self.y += dy * k;
+        }
+        self.clamp();
+        if self.shake_left > 0.0 {
+            let a = (self.shake_amp * self.shake_left / self.shake_time).round() as i32;
+            let mut r = || (self.rand.rand() % (2 * a as u32 + 1)) as i32 - a;
+            self.shake = (r(), r());
+            self.shake_left -= dt;
+        } else {
+            self.shake = (0, 0);
+        }
+    }
+
+    /// world position of the viewport's top-left, including the shake
+    pub fn origin(&self) -> (i32, i32) {
+        (
+            self.x.round() as i32 + self.shake.0,
+            self.y.round() as i32 + self.shake.1,
+        )
+    }
+
+    /// screen cell of a world point, may be outside the viewport
+    pub fn world_to_screen(&self, wx: i32, wy: i32) -> (i32, i32) {
+        let (ox, oy) = self.origin();
+        (
+            wx - ox + self.viewport.x as i32,
+            wy - oy + self.viewport.y as i32,
+        )
+    }
+
+    /// world point of a screen cell, None if it is outside the viewport
+    pub fn screen_to_world(&self, sx: u16, sy: u16) -> Option<(i32, i32)> {
+        let vp = self.viewport;
+        if sx < vp.left() || sx >= vp.right() || sy < vp.top() || sy >= vp.bottom() {
+            return None;
+        }
+        let (ox, oy) = self.origin();
+        Some((sx as i32 - vp.x as i32 + ox, sy as i32 - vp.y as i32 + oy))
+    }
+
+    /// world point of a mouse event inside the viewport
+    pub fn mouse_to_world(&self, e: &Event) -> Option<(i32, i32)> {
+        match e {
+            Event::Mouse(m) => self.screen_to_world(m.column, m.row),
+            _ => None,
+        }
+    }
+
+    pub fn is_visible(&self, wx: i32, wy: i32) -> bool {
+        let (sx, sy) = self.world_to_screen(wx, wy);
+        sx >= 0 && sy >= 0 && self.screen_to_world(sx as u16, sy as u16).is_some()
+    }
+
+    /// draws a world sprite into buf, clipped to the viewport.
+    /// The sprite is at its world_pos, or at its area position if it has none
+    pub fn render(&self, am: &mut AssetManager, pal: &Palette, buf: &mut Buffer, sp: &mut Sprite) {
+        if sp.is_hidden() {
+            return;
+        }
+        sp.check_asset_request(am);
+        let a = sp.content.area;
+        let (wx, wy) = sp.world_pos.unwrap_or((a.x as i32, a.y as i32));
+        let (sx, sy) = self.world_to_screen(wx, wy);
+        // visible part in screen cells
+        let vp = self.viewport;
+        let x0 = sx.max(vp.left() as i32);
+        let y0 = sy.max(vp.top() as i32);
+        let x1 = (sx + a.width as i32).min(vp.right() as i32);
+        let y1 = (sy + a.height as i32).min(vp.bottom() as i32);
+        if x0 >= x1 || y0 >= y1 {
+            return;
+        }
+        let mut clip = Buffer::empty(Rect::new(
+            x0 as u16,
+            y0 as u16,
+            (x1 - x0) as u16,
+            (y1 - y0) as u16,
+        ));
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let src = (y - sy) as usize * a.width as usize + (x - sx) as usize;
+                let i = clip.index_of(x as u16, y as u16);
+                clip.content[i] = sp.content.content[src].clone();
+            }
+        }
+        buf.merge_blend(&clip, true, sp.opacity, sp.blend, pal);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
+    use crate::render::style::Style;
+
+    // a 20x10 viewport at screen x 10, looking at world (0, 20)
+    fn cam() -> Camera {
+        let mut c = Camera::new(Rect::new(10, 0, 20, 10));
+        c.set_bounds(-50, 0, 100, 40);
+        c.look_at(0.0, 20.0);
+        c
+    }
+
+    #[test]
+    fn it_clamps_to_bounds_and_maps_coordinates() {
+        let mut c = cam();
+        c.follow(-100.0, 20.0);
+        c.update(0.1);
+        // clamped to the left and centered vertically on the target
+        assert_eq!(c.origin(), (-50, 15));
+        assert_eq!(c.world_to_screen(-50, 15), (10, 0));
+        assert_eq!(c.screen_to_world(12, 3), Some((-48, 18)));
+        assert_eq!(c.screen_to_world(5, 3), None);
+        let e = Event::Mouse(MouseEvent {
+            kind: MouseEventKind::Down(MouseButton::Left),
+            column: 29,
+            row: 9,
+            modifiers: KeyModifiers::NONE,
+        });
+        assert_eq!(c.mouse_to_world(&e), Some((-31, 24)));
+    }
+
+    #[test]
+    fn it_follows_part_of_the_way_when_smooth() {
+        let mut c = cam();
+        c.set_follow_speed(5.0);
+        c.follow(-100.0, 20.0);
+        c.update(0.1);
+        assert!(c.x > -50.0 && c.x < -10.0);
+    }
+
+    #[test]
+    fn it_shakes_within_the_amplitude_and_stops() {
+        let mut c = cam();
+        assert_eq!(c.origin(), (-10, 15));
+        c.shake(2.0, 0.2);
+        for _ in 0..4 {
+            c.update(0.05);
+            assert!(c.origin().0 >= -12 && c.origin().0 <= -8);
+        }
+        c.update(0.05);
+        assert_eq!(c.origin(), (-10, 15));
+    }
+
+    #[test]
+    fn it_clips_world_sprites_to_the_viewport() {
+        let c = cam();
+        let mut sp = Sprite::new(0, 0, 4, 1);
+        sp.content.set_str(0, 0, "abcd", Style::default());
+        sp.set_world_pos(-12, 15);
+        let mut buf = Buffer::empty(Rect::new(0, 0, 40, 10));
+        let mut am = AssetManager::new();
+        c.render(&mut am, &Palette::xterm(), &mut buf, &mut sp);
+        assert_eq!(buf.area, Rect::new(0, 0, 40, 10));
+        assert_eq!(buf.get(10, 0).symbol, "c");
+        assert_eq!(buf.get(11, 0).symbol, "d");
+        assert_eq!(buf.get(9, 0).symbol, " ");
+    }
+}
@@ -0,0 +1,314 @@
+// RustPixel
+// copyright zhouxin@tuyoogame.com 2022~2024
+
+//! Canvas plots pixels smaller than a cell, for charts, smooth lines and
+//! small images in text mode.
+//!
+//! Braille mode has 2x4 dots per cell, quadrant mode 2x2 blocks (▘▝▖▗...),
+//! half-block mode 1x2 blocks (▀▄). Braille and quadrant cells take the color
+//! drawn last in them, half-block cells show two colors, the upper pixel as fg
+//! and the lower one as bg, so they suit images best.
+//!
+//! Pixels are addressed with signed coordinates, those outside the canvas
+//! are clipped. render draws the cells with lit pixels into a Buffer,
+//! empty cells are left as they are.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let mut cv = Canvas::new(40, 10, CanvasMode::Braille);
+//! cv.line(0, 0, 79, 39, Color::Green);
+//! cv.circle(40, 20, 15, Color::Yellow);
+//! cv.chart(&prices, Color::Cyan);
+//! cv.render(&mut sprite.content, 0, 0);
+//! ```
+
+use crate::render::{
+    buffer::Buffer,
+    style::{Color, Style},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CanvasMode {
+    Braille,
+    Quadrant,
+    HalfBlock,
+}
+
+impl CanvasMode {
+    /// pixels per cell (x, y)
+    pub fn dots(self) -> (u16, u16) {
+        match self {
+            CanvasMode::Braille => (2, 4),
+            CanvasMode::Quadrant => (2, 2),
+            CanvasMode::HalfBlock => (1, 2),
+        }
+    }
+}
+
+// bits of braille dots, [y][x] in a cell
+const BRAILLE_BITS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+// indexed by bits top-left 1, top-right 2, bottom-left 4, bottom-right 8
+const QUADRANTS: [&str; 16] = [
+    " ", "▘", "▝", "▀", "▖", "▌", "▞", "▛", "▗", "▚", "▐", "▜", "▄", "▙", "▟", "█",
+];
+
+pub struct Canvas {
+    pub mode: CanvasMode,
+    // size in cells
+    pub width: u16,
+    pub height: u16,
+    // color of each lit pixel, row by row
+    pixels: Vec<Option<Color>>,
+    // color drawn last in each cell
+    cell_colors: Vec<Color>,
+}
+
+impl Canvas {
+    pub fn new(width: u16, height: u16, mode: CanvasMode) -> Self {
+        let (dx, dy) = mode.dots();
+        let n = width as usize * dx as usize * height as usize * dy as usize;
+        Self {
+            mode,
+            width,
+            height,
+            pixels: vec![None; n],
+            cell_colors: vec![Color::Reset; width as usize * height as usize],
+        }
+    }
+
+    /// size in pixels
+    pub fn size(&self) -> (i32, i32) {
+        let (dx, dy) = self.mode.dots();
+        ((self.width * dx) as i32, (self.height * dy) as i32)
+    }
+
+    pub fn clear(&mut self) {
+        self.pixels.iter_mut().for_each(|p| *p = None);
+    }
+
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        let (w, h) = self.size();
+        if x < 0 || y < 0 || x >= w || y >= h {
+            None
+        } else {
+            Some((y * w + x) as usize)
+        }
+    }
+
+    pub fn set(&mut self, x: i32, y: i32, c: Color) {
+        if let Some(i) = self.index(x, y) {
+            self.pixels[i] = Some(c);
+            let (dx, dy) = self.mode.dots();
+            let ci = (y / dy as i32) as usize * self.width as usize + (x / dx as i32) as usize;
+            self.cell_colors[ci] = c;
+        }
+    }
+
+    pub fn unset(&mut self, x: i32, y: i32) {
+        if let Some(i) = self.index(x, y) {
+            self.pixels[i] = None;
+        }
+    }
+
+    pub fn get(&self, x: i32, y: i32) -> Option<Color> {
+        self.index(x, y).and_then(|i| self.pixels[i])
+    }
+
+    /// bresenham line
+    pub fn line(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, c: Color) {
+        let (dx, dy) = ((x1 - x0).abs(), -(y1 - y0).abs());
+        let (sx, sy) = (if x0 < x1 { 1 } else { -1 }, if y0 < y1 { 1 } else { -1 });
+        let (mut x, mut y, mut err) = (x0, y0, dx + dy);
+        loop {
+            self.set(x, y, c);
+            if x == x1 && y == y1 {
+                break;
+            }
+            let e2 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                y += sy;
+            }
+        }
+    }
+
+    /// midpoint circle outline
+    pub fn circle(&mut self, cx: i32, cy: i32, r: i32, c: Color) {
+        let (mut x, mut y, mut d) = (0, r, 1 - r);
+        while x <= y {
+            for (px, py) in [
+                (x, y),
+                (y, x),
+                (-x, y),
+                (-y, x),
+                (x, -y),
+                (y, -x),
+                (-x, -y),
+                (-y, -x),
+            ] {
+                self.set(cx + px, cy + py, c);
+            }
+            x += 1;
+            if d < 0 {
+                d += 2 * x + 1;
+            } else {
+                y -= 1;
+                d += 2 * (x - y) + 1;
+            }
+        }
+    }
+
+    pub fn rect(&mut self, x: i32, y: i32, w: i32, h: i32, c: Color, fill: bool) {
+        for py in y..y + h {
+            for px in x..x + w {
+                if fill || px == x || py == y || px == x + w - 1 || py == y + h - 1 {
+                    self.set(px, py, c);
+                }
+            }
+        }
+    }
+
+    /// line chart of values over the full canvas, the lowest value at the bottom
+    pub fn chart(&mut self, values: &[f32], c: Color) {
+        if values.is_empty() {
+            return;
+        }
+        let (w, h) = self.size();
+        let lo = values.iter().cloned().fold(f32::MAX, f32::min);
+        let hi = values.iter().cloned().fold(f32::MIN, f32::max);
+        let range = if hi > lo { hi - lo } else { 1.0 };
+        let n = values.len();
+        let pt = |i: usize| {
+            let x = if n > 1 {
+                i as i32 * (w - 1) / (n as i32 - 1)
+            } else {
+                0
+            };
+            let y = h - 1 - ((values[i] - lo) / range * (h - 1) as f32).round() as i32;
+            (x, y)
+        };
+        let mut last = pt(0);
+        self.set(last.0, last.1, c);
+        for i in 1..n {
+            let p = pt(i);
+            self.line(last.0, last.1, p.0, p.1, c);
+            last = p;
+        }
+    }
+
+    /// draws an rgb image of width w at (x, y), black pixels are left empty
+    pub fn image(&mut self, x: i32, y: i32, w: usize, rgb: &[(u8, u8, u8)]) {
+        if w == 0 {
+            return;
+        }
+        for (i, (r, g, b)) in rgb.iter().enumerate() {
+            if (*r, *g, *b) != (0, 0, 0) {
+                self.set(
+                    x + (i % w) as i32,
+                    y + (i / w) as i32,
+                    Color::Rgb(*r, *g, *b),
+                );
+            }
+        }
+    }
+
+    // symbol and style of cell (cx, cy), None if it has no lit pixel
+    fn cell(&self, cx: u16, cy: u16) -> Option<(String, Style)> {
+        let (dx, dy) = self.mode.dots();
+        let (x0, y0) = ((cx * dx) as i32, (cy * dy) as i32);
+        let color = self.cell_colors[cy as usize * self.width as usize + cx as usize];
+        match self.mode {
+            CanvasMode::Braille => {
+                let mut bits = 0u32;
+                for (y, row) in BRAILLE_BITS.iter().enumerate() {
+                    for (x, b) in row.iter().enumerate() {
+                        if self.get(x0 + x as i32, y0 + y as i32).is_some() {
+                            bits |= b;
+                        }
+                    }
+                }
+                let ch = char::from_u32(0x2800 + bits)?;
+                (bits != 0).then(|| (ch.to_string(), Style::default().fg(color)))
+            }
+            CanvasMode::Quadrant => {
+                let mut bits = 0usize;
+                for (k, (x, y)) in [(0, 0), (1, 0), (0, 1), (1, 1)].iter().enumerate() {
+                    if self.get(x0 + x, y0 + y).is_some() {
+                        bits |= 1 << k;
+                    }
+                }
+                (bits != 0).then(|| (QUADRANTS[bits].to_string(), Style::default().fg(color)))
+            }
+            CanvasMode::HalfBlock => match (self.get(x0, y0), self.get(x0, y0 + 1)) {
+                (None, None) => None,
+                (Some(t), None) => Some(("▀".to_string(), Style::default().fg(t))),
+                (None, Some(b)) => Some(("▄".to_string(), Style::default().fg(b))),
+                (Some(t), Some(b)) if t == b => Some(("█".to_string(), Style::default().fg(t))),
+                (Some(t), Some(b)) => Some(("▀".to_string(), Style::default().fg(t).bg(b))),
+            },
+        }
+    }
+
+    /// draws cells with lit pixels into buf at (x, y), relative to buf's area
+    pub fn render(&self, buf: &mut Buffer, x: u16, y: u16) {
+        let a = buf.area;
+        for cy in 0..self.height {
+            for cx in 0..self.width {
+                if x + cx >= a.width || y + cy >= a.height {
+                    continue;
+                }
+                if let Some((s, style)) = self.cell(cx, cy) {
+                    buf.set_str(x + cx, y + cy, s, style);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::Rect;
+
+    #[test]
+    fn it_plots_braille_dots_and_clips() {
+        let mut cv = Canvas::new(2, 1, CanvasMode::Braille);
+        assert_eq!(cv.size(), (4, 4));
+        cv.line(0, 0, 0, 3, Color::Red);
+        cv.set(3, 3, Color::Green);
+        cv.set(9, 9, Color::Green);
+        let mut buf = Buffer::empty(Rect::new(5, 5, 4, 1));
+        cv.render(&mut buf, 1, 0);
+        assert_eq!(buf.get(6, 5).symbol, "⡇");
+        assert_eq!(buf.get(6, 5).fg, Color::Red);
+        assert_eq!(buf.get(7, 5).symbol, "⢀");
+        assert_eq!(buf.get(5, 5).symbol, " ");
+    }
+
+    #[test]
+    fn it_plots_quadrant_blocks() {
+        let mut cv = Canvas::new(2, 1, CanvasMode::Quadrant);
+        cv.rect(0, 0, 3, 2, Color::Blue, true);
+        let mut buf = Buffer::empty(Rect::new(0, 0, 2, 1));
+        cv.render(&mut buf, 0, 0);
+        assert_eq!(buf.get(0, 0).symbol, "█");
+        assert_eq!(buf.get(1, 0).symbol, "▌");
+    }
+
+    #[test]
+    fn it_plots_half_blocks_in_two_colors() {
+        let mut cv = Canvas::new(1, 1, CanvasMode::HalfBlock);
+        cv.image(0, 0, 1, &[(255, 0, 0), (0, 0, 255)]);
+        let mut buf = Buffer::empty(Rect::new(0, 0, 1, 1));
+        cv.render(&mut buf, 0, 0);
+        assert_eq!(buf.get(0, 0).symbol, "▀");
+        assert_eq!(buf.get(0, 0).fg, Color::Rgb(255, 0, 0));
+        assert_eq!(buf.get(0, 0).bg, Color::Rgb(0, 0, 255));
+    }
+}
@@ -16,6 +16,8 @@
 //! During rendering, cell is rendered according to its opacity order first to render_texture,
 //! and later render_text displays on the canvas
 //! Please refer to the merge and blit and push_history method
+//! Each entry also keeps the rgb color the symbol is drawn with,
+//! so sprites with opacity or blend modes can tint it, see Buffer::merge_blend
 
 use crate::render::style::{Color, Modifier, Style};
 use serde::{Deserialize, Serialize};
@@ -55,6 +57,12 @@ lazy_static! {
 /// sym_index, texture_index, fg_color_index
 pub type CellInfo = (u8, u8, u8);
 
+/// a symbol drawn on a cell in graphical mode and its final fg color,
+/// an index of the active palette, or rgb if blended with the symbols below it.
+/// The char of a one char symbol is kept as well, adapters draw it
+/// from a glyph atlas if one has it, see atlas.rs
+pub type DrawInfo = (CellInfo, Color, Option<char>);
+
 /// returns a cellsym char by index
 ///
 /// 256 unicode chars mark the index of a symbol in a SDL texture
@@ -108,7 +116,7 @@ pub struct Cell {
     pub fg: Color,
     pub bg: Color,
     pub modifier: Modifier,
-    pub draw_history: Vec<CellInfo>,
+    pub draw_history: Vec<DrawInfo>,
 }
 
 impl Cell {
@@ -131,6 +139,15 @@ impl Cell {
         (cellinfo(&self.symbol), u8::from(self.bg), u8::from(self.fg))
     }
 
+    /// the symbol if it is one char, glyph atlases are looked up by it
+    pub fn glyph_char(&self) -> Option<char> {
+        let mut cs = self.symbol.chars();
+        match (cs.next(), cs.next()) {
+            (Some(ch), None) => Some(ch),
+            _ => None,
+        }
+    }
+
     pub fn set_char(&mut self, ch: char) -> &mut Cell {
         self.symbol.clear();
         self.symbol.push(ch);
@@ -168,7 +185,21 @@ impl Cell {
 
     pub fn push_history(&mut self) {
         #[cfg(any(target_arch = "wasm32", feature = "sdl"))]
-        self.draw_history.push(self.get_cell_info());
+        self.draw_history
+            .push((self.get_cell_info(), self.draw_fg(), self.glyph_char()));
+    }
+
+    /// fg as drawn in graphical mode, named colors are palette indexes there
+    pub fn draw_fg(&self) -> Color {
+        match self.fg {
+            Color::Rgb(..) => self.fg,
+            c => Color::Indexed(u8::from(c)),
+        }
+    }
+
+    /// rgb of fg as drawn in graphical mode, named colors are palette indexes there
+    pub fn fg_rgb(&self) -> (u8, u8, u8) {
+        self.draw_fg().get_rgb()
     }
 
     pub fn reset(&mut self) {
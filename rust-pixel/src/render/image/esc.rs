@@ -14,13 +14,13 @@
 //! $ cat assets/tetris/back.txt
 
 use crate::{
-    asset::{Asset, AssetBase},
+    asset::{Asset, AssetBase, AssetError},
     render::image::find_vaild_area,
     render::buffer::Buffer,
+    render::cell::Cell,
     render::style::{Color, Style},
     util::Rect,
 };
-use log::info;
 use regex::Regex;
 use std::io::{BufRead, BufReader, Write};
 use unicode_width::UnicodeWidthStr;
@@ -39,7 +39,7 @@ impl Asset for EscAsset {
         &mut self.base
     }
 
-    fn parse(&mut self) {
+    fn parse(&mut self) -> Result<(), AssetError> {
         self.base.parsed_buffers.clear();
         let size = Rect::new(0, 0, 500, 300);
         let mut sp = Buffer::empty(size);
@@ -47,17 +47,21 @@ impl Asset for EscAsset {
         let mut row = 0;
         let mut max_width: u16 = 0;
         for line in reader.lines() {
-            let l = line.unwrap();
-            let lw = escstr_to_buffer(&l, &mut sp, row, 0, 0);
+            let l = line.map_err(|e| AssetError::BadData(e.to_string()))?;
+            if row >= size.height {
+                return Err(AssetError::BadData(format!("more than {} rows", size.height)));
+            }
+            let lw = escstr_to_buffer(&l, &mut sp, row, 0, 0)?;
             if lw > max_width {
                 max_width = lw;
             }
             row += 1;
         }
-        let nsize = Rect::new(0, 0, max_width, row);
+        let nsize = Rect::new(0, 0, max_width.min(size.width), row);
         let mut nsp = Buffer::empty(nsize);
         let _ = nsp.blit(0, 0, &sp, nsize);
         self.base.parsed_buffers.push(nsp);
+        Ok(())
     }
 
     fn save(&mut self, content: &Buffer) {
@@ -69,71 +73,77 @@ impl Asset for EscAsset {
         for row in y1..y2 + 1 {
             let line =
                 &content.content[(row * width + x1) as usize..(row * width + x2 + 1) as usize];
-            let mut fg = Color::Reset;
-            let mut bg = Color::Reset;
-            let mut span = String::new();
-            let mut skip = 0i8;
-            for (_i, cell) in line.iter().enumerate() {
-                info!(
-                    "save_esc symbol={} symwidth={}",
-                    cell.symbol,
-                    cell.symbol.width()
-                );
-                //对于中文等宽字符后面跟的空白，要略过处理
-                //Skip processing the space after monospace chinese font
-                if skip > 0 {
-                    info!("skip, skip={}", skip);
-                    skip -= 1;
-                    continue;
-                }
-                let sw = cell.symbol.width();
-                if sw > 1 {
-                    skip = sw as i8;
-                    skip -= 1;
-                    info!("set skip, skip={}", skip);
-                }
-                if cell.fg != fg || cell.bg != bg {
-                    if span.len() != 0 {
-                        if fg == Color::Reset && bg == Color::Reset {
-                            let _ = ptr.write_all(span.as_bytes());
-                        } else {
-                            let ss = format!(
-                                "\x1b[38;5;{}m\x1b[48;5;{}m{}\x1b[0m",
-                                u8::from(fg),
-                                u8::from(bg),
-                                span
-                            );
-                            let _ = ptr.write_all(ss.as_bytes());
-                        }
-                        span.clear();
-                    }
-                    fg = cell.fg;
-                    bg = cell.bg;
-                    span.push_str(&cell.symbol);
-                } else {
-                    span.push_str(&cell.symbol);
-                }
-            }
-            if span.len() != 0 {
-                if fg == Color::Reset && bg == Color::Reset {
-                    let _ = ptr.write_all(span.as_bytes());
-                } else {
-                    let ss = format!(
-                        "\x1b[38;5;{}m\x1b[48;5;{}m{}\x1b[0m",
-                        u8::from(fg),
-                        u8::from(bg),
-                        span
-                    );
-                    let _ = ptr.write_all(ss.as_bytes());
-                }
-                span.clear();
-            }
+            let _ = ptr.write_all(cells_to_escstr(line).as_bytes());
             let _ = ptr.write_all("\n".as_bytes());
         }
     }
 }
 
-pub fn escstr_to_buffer(l: &String, content: &mut Buffer, row: u16, off_x: u16, off_y: u16) -> u16 {
+/// encodes a row of cells as esc sequences and utf8 text, without newline
+pub fn cells_to_escstr(line: &[Cell]) -> String {
+    let mut out = String::new();
+    let mut fg = Color::Reset;
+    let mut bg = Color::Reset;
+    let mut span = String::new();
+    let mut skip = 0i8;
+    let flush = |out: &mut String, span: &mut String, fg: Color, bg: Color| {
+        if !span.is_empty() {
+            if fg == Color::Reset && bg == Color::Reset {
+                out.push_str(span);
+            } else {
+                out.push_str(&format!(
+                    "\x1b[38;5;{}m\x1b[48;5;{}m{}\x1b[0m",
+                    u8::from(fg),
+                    u8::from(bg),
+                    span
+                ));
+            }
+            span.clear();
+        }
+    };
+    for cell in line.iter() {
+        //对于中文等宽字符后面跟的空白，要略过处理
+        //Skip processing the space after monospace chinese font
+        if skip > 0 {
+            skip -= 1;
+            continue;
+        }
+        let sw = cell.symbol.width();
+        if sw > 1 {
+            skip = sw as i8;
+            skip -= 1;
+        }
+        if cell.fg != fg || cell.bg != bg {
+            flush(&mut out, &mut span, fg, bg);
+            fg = cell.fg;
+            bg = cell.bg;
+        }
+        span.push_str(&cell.symbol);
+    }
+    flush(&mut out, &mut span, fg, bg);
+    out
+}
+
+/// draws a line of esc sequences at row, cells outside content are clipped.
+/// Returns the width of the line
+pub fn escstr_to_buffer(
+    l: &str,
+    content: &mut Buffer,
+    row: u16,
+    off_x: u16,
+    off_y: u16,
+) -> Result<u16, AssetError> {
+    let area = content.area;
+    let mut put = |x: u16, s: &str, style: Style| {
+        if x < area.width && row + off_y < area.height {
+            content.set_str(x, row + off_y, s, style);
+        }
+    };
+    let color = |s: &str| {
+        s.parse::<u8>()
+            .map(Color::Indexed)
+            .map_err(|_| AssetError::BadData(format!("color {}", s)))
+    };
     let mut pos = 0;
     let mut cell_pos = 0;
     let mut lpos = 0;
@@ -142,12 +152,7 @@ pub fn escstr_to_buffer(l: &String, content: &mut Buffer, row: u16, off_x: u16,
     for cap in re.captures_iter(l) {
         let cr = cap.get(0).unwrap();
         //info!("load_esc set1 x={} str={}", cell_pos + off_x, &l[pos..cr.start()]);
-        content.set_str(
-            cell_pos + off_x,
-            row + off_y,
-            &l[pos..cr.start()],
-            Style::default(),
-        );
+        put(cell_pos + off_x, &l[pos..cr.start()], Style::default());
         //注意要使用unicode的长度，不能直接使用byte长度
         //例如♥的正确长度是1，而byte长度是3
         //let graphemes = UnicodeSegmentation::graphemes(&l[pos..cr.start()], true)
@@ -165,13 +170,10 @@ pub fn escstr_to_buffer(l: &String, content: &mut Buffer, row: u16, off_x: u16,
         //use width to return the true char width
         cell_pos += l[pos..cr.start()].width() as u16;
         //info!("load_esc set2 x={} str={}", cell_pos + off_x, &cap[3]);
-        content.set_str(
+        put(
             cell_pos + off_x,
-            row + off_y,
             &cap[3],
-            Style::default()
-                .fg(Color::Indexed(cap[1].parse::<u8>().unwrap()))
-                .bg(Color::Indexed(cap[2].parse::<u8>().unwrap())),
+            Style::default().fg(color(&cap[1])?).bg(color(&cap[2])?),
         );
         //let graphemes = UnicodeSegmentation::graphemes(&cap[3], true)
         //    .collect::<Vec<&str>>();
@@ -182,13 +184,8 @@ pub fn escstr_to_buffer(l: &String, content: &mut Buffer, row: u16, off_x: u16,
         lcell_pos = cell_pos;
     }
     //info!("load_esc set3 x={} str={}", lcell_pos + off_x, &l[lpos..l.len()]);
-    content.set_str(
-        lcell_pos + off_x,
-        row + off_y,
-        &l[lpos..l.len()],
-        Style::default(),
-    );
+    put(lcell_pos + off_x, &l[lpos..l.len()], Style::default());
     //info!("load_esc line width = {}", lcell_pos + l[lpos..l.len()].width() as u16);
-    lcell_pos + l[lpos..l.len()].width() as u16
+    Ok(lcell_pos + l[lpos..l.len()].width() as u16)
 }
 
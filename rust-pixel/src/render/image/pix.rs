@@ -11,116 +11,426 @@
 //! pix file stores the ascii art images in graphical mode, saving the cell sequence row by row
 //! cell: char sym index, fore- and background colors (background color is used to mark texture in graphical mode)
 //! $ cat assets/snake/back.pix
+//!
+//! Binary pix stores the same cells compactly and is detected by its magic,
+//! so PixAsset loads both. Files named .pixb are saved as binary.
+//!
+//! "PIXB" | version u8 | flags u8 | width u16 | height u16 | texture u8 | body
+//!
+//! Numbers are little endian. The body has the cells row by row, each one
+//! idx,fg,bg bytes if texture is 255, else idx,fg. With PIX_RLE the body is
+//! runs of (count u8, cell), with PIX_GZIP it is gzip compressed after that.
+//! pix_to_binary and pix_to_text convert between the two losslessly.
 
 use crate::{
-    asset::{Asset, AssetBase},
+    asset::{Asset, AssetBase, AssetError},
     render::buffer::Buffer,
-    render::cell::cellsym,
+    render::cell::{cellinfo, cellsym},
     render::image::find_vaild_area,
     render::style::{Color, Style},
     util::Rect,
 };
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
 use log::info;
 use regex::Regex;
-use std::io::{BufRead, BufReader, Write};
+use std::io::{BufRead, BufReader, Read, Write};
 
-pub struct PixAsset {
-    base: AssetBase,
+pub const PIX_BINARY_MAGIC: &[u8; 4] = b"PIXB";
+const PIX_BINARY_VERSION: u8 = 1;
+// header size of binary pix
+const PIX_BINARY_HEAD: usize = 11;
+/// body flag, run length encoded cells
+pub const PIX_RLE: u8 = 1;
+/// body flag, gzip compressed
+pub const PIX_GZIP: u8 = 2;
+
+pub fn is_binary_pix(data: &[u8]) -> bool {
+    data.starts_with(PIX_BINARY_MAGIC)
 }
 
-impl Asset for PixAsset {
-    fn new(ab: AssetBase) -> Self {
-        Self { base: ab }
-    }
+/// converts a text pix to binary, flags are PIX_RLE | PIX_GZIP
+pub fn pix_to_binary(text: &[u8], flags: u8) -> Result<Vec<u8>, AssetError> {
+    Ok(PixData::from_bytes(text)?.to_binary(flags))
+}
 
-    fn get_base(&mut self) -> &mut AssetBase {
-        &mut self.base
-    }
+/// converts a binary pix to text
+pub fn pix_to_text(bin: &[u8]) -> Result<Vec<u8>, AssetError> {
+    Ok(PixData::from_bytes(bin)?.to_text())
+}
 
-    fn parse(&mut self) {
-        self.base.parsed_buffers.clear();
-        let size = Rect::new(0, 0, 0, 0);
-        let mut sp = Buffer::empty(size);
+/// cells of a pix file, the same in both formats
+#[derive(Debug, PartialEq, Clone)]
+pub struct PixData {
+    pub width: u16,
+    pub height: u16,
+    // 255: each cell has its own texture in bg
+    pub texture: u8,
+    // (idx, fg, bg) row by row, bg is texture if texture < 255
+    pub cells: Vec<(u8, u8, u8)>,
+}
+
+impl PixData {
+    /// parses text or binary pix
+    pub fn from_bytes(data: &[u8]) -> Result<Self, AssetError> {
+        if is_binary_pix(data) {
+            Self::from_binary(data)
+        } else {
+            Self::from_text(data)
+        }
+    }
 
-        let reader = BufReader::new(&self.base.raw_data[..]);
+    /// short rows are padded with blank cells, missing rows are BadData.
+    /// Cells are added row by row, so the header can not allocate more than the data
+    pub fn from_text(data: &[u8]) -> Result<Self, AssetError> {
+        let reader = BufReader::new(data);
         let re = Regex::new(r"width=(\d+),height=(\d+),texture=(\d+)").unwrap();
         let rel0 = Regex::new(r"(\d+),(\d+)(.*?)").unwrap();
         let rel1 = Regex::new(r"(\d+),(\d+),(\d+)(.*?)").unwrap();
-        let mut width: u16;
-        let mut height: u16;
-        let mut texid: u8 = 0;
+        let mut pd = PixData {
+            width: 0,
+            height: 0,
+            texture: 0,
+            cells: vec![],
+        };
         let mut lineidx = 0;
-        //info!("begin load_pix....");
+        let bad = |l: &str| AssetError::BadData(format!("line {}", l));
         for line in reader.lines() {
-            let l = line.unwrap();
-            //info!("load_pix line={}", l);
+            let l = line.map_err(|e| AssetError::BadData(e.to_string()))?;
             if lineidx == 0 {
-                for cap in re.captures_iter(&l) {
-                    width = cap[1].parse::<u16>().unwrap();
-                    height = cap[2].parse::<u16>().unwrap();
-                    texid = cap[3].parse::<u8>().unwrap();
-                    info!("w..{} h..{} l..{}", width, height, texid);
-                    let size = Rect::new(0, 0, width, height);
-                    sp.resize(size);
-                }
+                let cap = re
+                    .captures(&l)
+                    .ok_or(AssetError::MalformedHeader(l.clone()))?;
+                let (width, height) = match (cap[1].parse::<u16>(), cap[2].parse::<u16>()) {
+                    (Ok(w), Ok(h)) => (w, h),
+                    _ => return Err(AssetError::MalformedHeader(l.clone())),
+                };
+                let texid = cap[3]
+                    .parse::<u8>()
+                    .map_err(|_| AssetError::MalformedHeader(l.clone()))?;
+                info!("w..{} h..{} l..{}", width, height, texid);
+                pd.width = width;
+                pd.height = height;
+                pd.texture = texid;
             } else {
-                let mut col = 0;
-                if texid < 255 {
-                    for cap in rel0.captures_iter(&l) {
-                        let idx = cap[1].parse::<u8>().unwrap();
-                        let fgc = cap[2].parse::<u8>().unwrap();
-                        sp.set_str(
-                            col,
-                            lineidx - 1,
-                            cellsym(idx),
-                            Style::default()
-                                .fg(Color::Indexed(fgc))
-                                .bg(Color::Indexed(texid)),
-                        );
-                        col += 1;
+                if lineidx > pd.height {
+                    if !l.trim().is_empty() {
+                        return Err(AssetError::BadData(format!("more than {} rows", pd.height)));
                     }
-                } else {
-                    for cap in rel1.captures_iter(&l) {
-                        let idx = cap[1].parse::<u8>().unwrap();
-                        let fgc = cap[2].parse::<u8>().unwrap();
-                        let bgc = cap[3].parse::<u8>().unwrap();
-                        sp.set_str(
-                            col,
-                            lineidx - 1,
-                            cellsym(idx),
-                            Style::default()
-                                .fg(Color::Indexed(fgc))
-                                .bg(Color::Indexed(bgc)),
-                        );
-                        col += 1;
+                    lineidx += 1;
+                    continue;
+                }
+                let row = pd.cells.len();
+                pd.cells.resize(row + pd.width as usize, (32, 0, pd.texture));
+                let re = if pd.texture < 255 { &rel0 } else { &rel1 };
+                for (col, cap) in re.captures_iter(&l).enumerate() {
+                    let idx = cap[1].parse::<u8>().map_err(|_| bad(&l))?;
+                    let fgc = cap[2].parse::<u8>().map_err(|_| bad(&l))?;
+                    let bgc = if pd.texture < 255 {
+                        pd.texture
+                    } else {
+                        cap[3].parse::<u8>().map_err(|_| bad(&l))?
+                    };
+                    if col >= pd.width as usize {
+                        return Err(bad(&l));
                     }
+                    pd.cells[row + col] = (idx, fgc, bgc);
                 }
             }
             lineidx += 1;
         }
-        self.base.parsed_buffers.push(sp);
+        if lineidx == 0 {
+            return Err(AssetError::MalformedHeader("empty file".to_string()));
+        }
+        if pd.cells.len() != pd.width as usize * pd.height as usize {
+            return Err(AssetError::BadData(format!(
+                "{} rows, want {}",
+                pd.cells.len() / (pd.width as usize).max(1),
+                pd.height
+            )));
+        }
+        Ok(pd)
     }
 
-    fn save(&mut self, content: &Buffer) {
-        self.base.raw_data.clear();
-        let mut ptr = std::io::Cursor::new(&mut self.base.raw_data);
+    pub fn from_binary(data: &[u8]) -> Result<Self, AssetError> {
+        if data.len() < PIX_BINARY_HEAD || !is_binary_pix(data) {
+            return Err(AssetError::MalformedHeader("binary pix".to_string()));
+        }
+        if data[4] != PIX_BINARY_VERSION {
+            return Err(AssetError::MalformedHeader(format!(
+                "pix version {}",
+                data[4]
+            )));
+        }
+        let flags = data[5];
+        let width = u16::from_le_bytes([data[6], data[7]]);
+        let height = u16::from_le_bytes([data[8], data[9]]);
+        let texture = data[10];
+        let csize = if texture < 255 { 2 } else { 3 };
+        let count = width as usize * height as usize;
+        let mut body = data[PIX_BINARY_HEAD..].to_vec();
+        if flags & PIX_GZIP != 0 {
+            // a valid body is never longer than one run per cell
+            let limit = (count * (csize + 1)) as u64 + 1;
+            let mut out = vec![];
+            GzDecoder::new(&body[..])
+                .take(limit)
+                .read_to_end(&mut out)
+                .map_err(|e| AssetError::Decompress(e.to_string()))?;
+            body = out;
+        }
+        let cell = |b: &[u8]| (b[0], b[1], if texture < 255 { texture } else { b[2] });
+        // check the cell count before allocating the cells
+        let bad_count = |n: usize| AssetError::BadData(format!("{} cells, want {}", n, count));
+        let cells: Vec<(u8, u8, u8)> = if flags & PIX_RLE != 0 {
+            let runs = body.chunks_exact(csize + 1);
+            if !runs.remainder().is_empty() {
+                return Err(AssetError::BadData("pix runs".to_string()));
+            }
+            if runs.clone().any(|run| run[0] == 0) {
+                return Err(AssetError::BadData("pix run".to_string()));
+            }
+            let n: usize = runs.clone().map(|run| run[0] as usize).sum();
+            if n != count {
+                return Err(bad_count(n));
+            }
+            runs.flat_map(|run| std::iter::repeat_n(cell(&run[1..]), run[0] as usize))
+                .collect()
+        } else {
+            let cs = body.chunks_exact(csize);
+            if !cs.remainder().is_empty() {
+                return Err(AssetError::BadData("pix cells".to_string()));
+            }
+            if cs.len() != count {
+                return Err(bad_count(cs.len()));
+            }
+            cs.map(cell).collect()
+        };
+        Ok(Self {
+            width,
+            height,
+            texture,
+            cells,
+        })
+    }
+
+    /// non blank area of content, each cell with its texture
+    pub fn from_buffer(content: &Buffer) -> Self {
         let (x1, x2, y1, y2) = find_vaild_area(content);
         let width = content.area.width;
-        let _ = writeln!(
-            ptr,
-            "width={},height={},texture={}",
-            x2 - x1 + 1,
-            y2 - y1 + 1,
-            255
-        );
+        let mut cells = vec![];
         for row in y1..y2 + 1 {
             let line =
                 &content.content[(row * width + x1) as usize..(row * width + x2 + 1) as usize];
-            for (_i, cell) in line.iter().enumerate() {
-                let (idx, _, _) = cell.get_cell_info();
-                let _ = write!(ptr, "{},{},{} ", idx, u8::from(cell.fg), u8::from(cell.bg));
+            for cell in line.iter() {
+                // c64 index, glyph atlases are not saved
+                let idx = cellinfo(&cell.symbol);
+                cells.push((idx, u8::from(cell.fg), u8::from(cell.bg)));
+            }
+        }
+        Self {
+            width: x2 - x1 + 1,
+            height: y2 - y1 + 1,
+            texture: 255,
+            cells,
+        }
+    }
+
+    pub fn to_buffer(&self) -> Buffer {
+        let mut sp = Buffer::empty(Rect::new(0, 0, self.width, self.height));
+        for (i, (idx, fg, bg)) in self.cells.iter().enumerate() {
+            sp.set_str(
+                (i % self.width as usize) as u16,
+                (i / self.width as usize) as u16,
+                cellsym(*idx),
+                Style::default()
+                    .fg(Color::Indexed(*fg))
+                    .bg(Color::Indexed(*bg)),
+            );
+        }
+        sp
+    }
+
+    pub fn to_text(&self) -> Vec<u8> {
+        let mut out = vec![];
+        let _ = writeln!(
+            out,
+            "width={},height={},texture={}",
+            self.width, self.height, self.texture
+        );
+        if self.width == 0 {
+            return out;
+        }
+        for row in self.cells.chunks(self.width as usize) {
+            for (idx, fg, bg) in row {
+                if self.texture < 255 {
+                    let _ = write!(out, "{},{} ", idx, fg);
+                } else {
+                    let _ = write!(out, "{},{},{} ", idx, fg, bg);
+                }
             }
-            let _ = write!(ptr, "\n");
+            let _ = writeln!(out);
         }
+        out
+    }
+
+    /// flags are PIX_RLE | PIX_GZIP
+    pub fn to_binary(&self, flags: u8) -> Vec<u8> {
+        let mut out = PIX_BINARY_MAGIC.to_vec();
+        out.extend([PIX_BINARY_VERSION, flags]);
+        out.extend(self.width.to_le_bytes());
+        out.extend(self.height.to_le_bytes());
+        out.push(self.texture);
+        let put = |b: &mut Vec<u8>, c: &(u8, u8, u8)| {
+            b.extend([c.0, c.1]);
+            if self.texture == 255 {
+                b.push(c.2);
+            }
+        };
+        let mut body = vec![];
+        if flags & PIX_RLE != 0 {
+            let mut i = 0;
+            while i < self.cells.len() {
+                let c = &self.cells[i];
+                let mut n = 1;
+                while n < 255 && i + n < self.cells.len() && self.cells[i + n] == *c {
+                    n += 1;
+                }
+                body.push(n as u8);
+                put(&mut body, c);
+                i += n;
+            }
+        } else {
+            for c in &self.cells {
+                put(&mut body, c);
+            }
+        }
+        if flags & PIX_GZIP != 0 {
+            let mut enc = GzEncoder::new(vec![], Compression::best());
+            let _ = enc.write_all(&body);
+            body = enc.finish().unwrap_or_default();
+        }
+        out.extend(body);
+        out
+    }
+}
+
+pub struct PixAsset {
+    base: AssetBase,
+    // flags of binary pix to save with, None saves text
+    pub binary: Option<u8>,
+}
+
+impl Asset for PixAsset {
+    fn new(ab: AssetBase) -> Self {
+        let binary = if ab.location.ends_with(".pixb") {
+            Some(PIX_RLE | PIX_GZIP)
+        } else {
+            None
+        };
+        Self { base: ab, binary }
+    }
+
+    fn get_base(&mut self) -> &mut AssetBase {
+        &mut self.base
+    }
+
+    fn parse(&mut self) -> Result<(), AssetError> {
+        self.base.parsed_buffers.clear();
+        let data = &self.base.raw_data;
+        if is_binary_pix(data) && data.len() > 5 {
+            // save in the format it was loaded
+            self.binary = Some(data[5]);
+        }
+        let pd = PixData::from_bytes(data)?;
+        self.base.parsed_buffers.push(pd.to_buffer());
+        Ok(())
+    }
+
+    fn save(&mut self, content: &Buffer) {
+        let pd = PixData::from_buffer(content);
+        self.base.raw_data = match self.binary {
+            Some(flags) => pd.to_binary(flags),
+            None => pd.to_text(),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asset::AssetType;
+
+    const TEXT: &[u8] = b"width=3,height=2,texture=255\n1,2,3 1,2,3 1,2,3 \n4,5,6 7,8,9 0,0,1 \n";
+
+    #[test]
+    fn it_round_trips_binary_pix() {
+        let pd = PixData::from_bytes(TEXT).unwrap();
+        for flags in [0, PIX_RLE, PIX_GZIP, PIX_RLE | PIX_GZIP] {
+            let bin = pix_to_binary(TEXT, flags).unwrap();
+            assert!(is_binary_pix(&bin));
+            assert_eq!(PixData::from_bytes(&bin).unwrap(), pd);
+            assert_eq!(pix_to_text(&bin).unwrap(), TEXT.to_vec());
+        }
+    }
+
+    #[test]
+    fn it_leaves_bg_out_of_single_texture_pix() {
+        let tex = b"width=2,height=1,texture=3\n10,1 11,2 \n";
+        let bin = pix_to_binary(tex, PIX_RLE).unwrap();
+        assert_eq!(bin.len(), PIX_BINARY_HEAD + 6);
+        assert_eq!(pix_to_text(&bin).unwrap(), tex.to_vec());
+    }
+
+    #[test]
+    fn it_rejects_bad_binary_pix() {
+        let bin = pix_to_binary(TEXT, 0).unwrap();
+        assert!(PixData::from_bytes(&bin[..bin.len() - 3]).is_err());
+        assert!(PixData::from_bytes(b"PIXB\x09").is_err());
+    }
+
+    #[test]
+    fn it_checks_cell_counts_against_the_header() {
+        for flags in [0, PIX_RLE, PIX_RLE | PIX_GZIP] {
+            let mut bin = pix_to_binary(TEXT, flags).unwrap();
+            bin[6..10].copy_from_slice(&[0xff; 4]);
+            assert!(matches!(PixData::from_bytes(&bin), Err(AssetError::BadData(_))));
+        }
+        // runs longer than the header says
+        let mut bin = pix_to_binary(TEXT, PIX_RLE).unwrap();
+        bin[PIX_BINARY_HEAD] = 255;
+        assert!(matches!(PixData::from_bytes(&bin), Err(AssetError::BadData(_))));
+    }
+
+    #[test]
+    fn it_checks_text_rows_against_the_header() {
+        let short = b"width=3,height=2,texture=255
+1,2,3 
+";
+        assert_eq!(
+            PixData::from_bytes(short),
+            Err(AssetError::BadData("1 rows, want 2".to_string()))
+        );
+        let huge = b"width=65535,height=65535,texture=255
+1,2,3 
+";
+        assert!(matches!(PixData::from_bytes(huge), Err(AssetError::BadData(_))));
+        // a short row is padded
+        let pd = PixData::from_bytes(b"width=2,height=1,texture=3
+10,1 
+").unwrap();
+        assert_eq!(pd.cells, vec![(10, 1, 3), (32, 0, 3)]);
+    }
+
+    #[test]
+    fn it_saves_pixb_assets_as_binary() {
+        let pd = PixData::from_bytes(TEXT).unwrap();
+        let mut ast = PixAsset::new(AssetBase::new(AssetType::ImgPix, "a.pixb"));
+        ast.set_data(TEXT);
+        ast.parse().unwrap();
+        let buf = ast.get_base().parsed_buffers[0].clone();
+        ast.save(&buf);
+        assert!(is_binary_pix(&ast.get_base().raw_data));
+        let raw = ast.get_base().raw_data.clone();
+        assert_eq!(PixData::from_bytes(&raw).unwrap(), pd);
     }
 }
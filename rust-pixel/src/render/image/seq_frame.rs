@@ -12,19 +12,22 @@
 //!
 //! head of the file describes the width, height, framerate, texture and offset of each frame
 //! file content is compressed frame data
+//!
+//! encode_ssf writes frames in any of the texture modes, so tools can make
+//! their own animations.
 
 use crate::{
-    asset::{Asset, AssetBase, AssetState},
+    asset::{Asset, AssetBase, AssetError, AssetState},
     render::buffer::Buffer,
-    render::cell::cellsym,
-    render::image::esc::escstr_to_buffer,
+    render::cell::{cellinfo, cellsym},
+    render::image::esc::{cells_to_escstr, escstr_to_buffer},
     render::style::{Color, Style},
     util::Rect,
 };
-use flate2::read::GzDecoder;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
 // use log::info;
 use regex::Regex;
-use std::io::{BufRead, BufReader, Read};
+use std::io::{BufRead, BufReader, Read, Write};
 
 pub struct SeqFrameAsset {
     pub base: AssetBase,
@@ -78,9 +81,9 @@ impl Asset for SeqFrameAsset {
     /// cell的数据长度为2字节: fg cellsym
     /// texture_id < 255 means SDL frame data，each cell's texture is texture_id,
     /// the length of each cell data is 2 bytes : fg cellsym
-    fn parse(&mut self) {
+    fn parse(&mut self) -> Result<(), AssetError> {
         if self.get_state() != AssetState::Parsing {
-            return;
+            return Ok(());
         }
         self.frame_len = vec![];
         self.frame_offset = vec![];
@@ -91,106 +94,278 @@ impl Asset for SeqFrameAsset {
         let rel = Regex::new(r"(\d+),(.*?)").unwrap();
         let mut file_header = String::new();
         let _ = reader.read_line(&mut file_header);
-        for cap in re.captures_iter(&file_header) {
-            self.width = cap[1].parse::<u16>().unwrap();
-            self.height = cap[2].parse::<u16>().unwrap();
-            self.texture_id = cap[3].parse::<u16>().unwrap();
-            self.base.frame_count = cap[4].parse::<u16>().unwrap() as usize;
+        let header_err = || AssetError::MalformedHeader(file_header.trim_end().to_string());
+        let cap = re.captures(&file_header).ok_or_else(header_err)?;
+        let num = |i: usize| cap[i].parse::<u16>().map_err(|_| header_err());
+        self.width = num(1)?;
+        self.height = num(2)?;
+        self.texture_id = num(3)?;
+        self.base.frame_count = num(4)? as usize;
+        if self.width == 0 || self.height == 0 || self.base.frame_count == 0 {
+            return Err(header_err());
         }
         let mut len_header = String::new();
         let _ = reader.read_line(&mut len_header);
         let mut offset = 0u32;
         for cap in rel.captures_iter(&len_header) {
-            let flen = cap[1].parse::<u32>().unwrap();
+            let flen = cap[1]
+                .parse::<u32>()
+                .map_err(|_| AssetError::BadFrameOffsets(len_header.trim_end().to_string()))?;
             self.frame_len.push(flen);
             self.frame_offset.push(offset);
-            offset += flen;
+            offset = offset.saturating_add(flen);
         }
         let _ = reader.read_to_end(&mut self.frame_data);
+        if self.frame_len.len() < self.base.frame_count || offset as usize > self.frame_data.len() {
+            return Err(AssetError::BadFrameOffsets(format!(
+                "{} frames of {} bytes, {} lengths of {} bytes",
+                self.base.frame_count,
+                self.frame_data.len(),
+                self.frame_len.len(),
+                offset
+            )));
+        }
         self.base.parsed_buffers.clear();
+        let cells = self.width as usize * self.height as usize;
+        // max bytes of a cell, a valid frame is never longer than cells of it
+        let cell_len: usize = match self.texture_id {
+            256 => 6,
+            255 => 3,
+            _ => 2,
+        };
+        let bad = |f: usize| AssetError::BadData(format!("frame {}", f));
+        let unzip =
+            |f: usize, e: std::io::Error| AssetError::Decompress(format!("frame {} {}", f, e));
         for frame_idx in 0..self.base.frame_count {
             let size = Rect::new(0, 0, self.width, self.height);
             let mut sp = Buffer::empty(size);
             let start = self.frame_offset[frame_idx] as usize;
             let flen = self.frame_len[frame_idx] as usize;
-            let mut decoder = GzDecoder::new(&self.frame_data[start..start + flen]);
+            let decoder = GzDecoder::new(&self.frame_data[start..start + flen]);
+            let limit = (cells * cell_len) as u64 + 1;
 
             if self.texture_id == 257 {
                 let reader = BufReader::new(decoder);
                 let mut row = 0;
                 for line in reader.lines() {
-                    let l = line.unwrap();
-                    escstr_to_buffer(&l, &mut sp, row, 0, 0);
+                    let l = line.map_err(|e| unzip(frame_idx, e))?;
+                    escstr_to_buffer(&l, &mut sp, row, 0, 0)?;
                     row += 1;
                 }
             } else if self.texture_id == 256 {
                 let mut decompressed_data = Vec::new();
-                decoder.read_to_end(&mut decompressed_data).unwrap();
+                decoder
+                    .take(limit)
+                    .read_to_end(&mut decompressed_data)
+                    .map_err(|e| unzip(frame_idx, e))?;
                 let mut bpos = 0usize;
-                let mut i = 0u16;
-                loop {
+                let mut i = 0usize;
+                while bpos < decompressed_data.len() {
+                    if i >= cells || bpos + 3 > decompressed_data.len() {
+                        return Err(bad(frame_idx));
+                    }
                     let fgc = decompressed_data[bpos];
-                    bpos += 1;
-                    let bgc = decompressed_data[bpos];
-                    bpos += 1;
-                    let mut utf8_bytes: Vec<u8> = vec![];
-                    let first = decompressed_data[bpos];
-                    bpos += 1;
-                    utf8_bytes.push(first);
-                    let mut blen = 0;
-                    if first >> 7 == 0 {
-                        blen = 0;
+                    let bgc = decompressed_data[bpos + 1];
+                    let first = decompressed_data[bpos + 2];
+                    let blen = if first >> 7 == 0 {
+                        1
+                    } else if first >> 5 == 0b0000_0110 {
+                        2
+                    } else if first >> 4 == 0b0000_1110 {
+                        3
                     } else {
-                        if first >> 5 == 0b0000_0110 {
-                            blen = 1;
-                        }
-                        if first >> 4 == 0b0000_1110 {
-                            blen = 2;
-                        }
-                        if first >> 3 == 0b0001_1110 {
-                            blen = 3;
-                        }
-                    }
-                    for _i in 0..blen {
-                        utf8_bytes.push(decompressed_data[bpos]);
-                        bpos += 1;
-                    }
+                        4
+                    };
+                    let sym = decompressed_data
+                        .get(bpos + 2..bpos + 2 + blen)
+                        .and_then(|b| std::str::from_utf8(b).ok())
+                        .ok_or_else(|| bad(frame_idx))?;
                     sp.set_str(
-                        i % self.width,
-                        i / self.width,
-                        std::str::from_utf8(&utf8_bytes).unwrap(),
+                        (i % self.width as usize) as u16,
+                        (i / self.width as usize) as u16,
+                        sym,
                         Style::default()
                             .fg(Color::Indexed(fgc))
                             .bg(Color::Indexed(bgc)),
                     );
+                    bpos += 2 + blen;
                     i += 1;
-                    if bpos == decompressed_data.len() {
-                        break;
-                    }
                 }
             } else {
                 let mut decompressed_data = Vec::new();
-                decoder.read_to_end(&mut decompressed_data).unwrap();
-                let cell_len: usize = if self.texture_id == 255 { 3 } else { 2 };
-                for i in 0..decompressed_data.len() as u16 / cell_len as u16 {
+                decoder
+                    .take(limit)
+                    .read_to_end(&mut decompressed_data)
+                    .map_err(|e| unzip(frame_idx, e))?;
+                if decompressed_data.len() > cells * cell_len {
+                    return Err(bad(frame_idx));
+                }
+                for i in 0..decompressed_data.len() / cell_len {
                     let bgc: u8 = if self.texture_id == 255 {
-                        decompressed_data[i as usize * cell_len + 2]
+                        decompressed_data[i * cell_len + 2]
                     } else {
                         self.texture_id as u8
                     };
                     sp.set_str(
-                        i % self.width,
-                        i / self.width,
-                        cellsym(decompressed_data[i as usize * cell_len]),
+                        (i % self.width as usize) as u16,
+                        (i / self.width as usize) as u16,
+                        cellsym(decompressed_data[i * cell_len]),
                         Style::default()
-                            .fg(Color::Indexed(decompressed_data[i as usize * cell_len + 1]))
+                            .fg(Color::Indexed(decompressed_data[i * cell_len + 1]))
                             .bg(Color::Indexed(bgc)),
                     );
                 }
             }
             self.base.parsed_buffers.push(sp);
         }
+        Ok(())
+    }
+
+    /// saves content as a one frame ssf in texture_id mode,
+    /// use encode_ssf for animations
+    fn save(&mut self, content: &Buffer) {
+        if let Ok(data) = encode_ssf(std::slice::from_ref(content), self.texture_id) {
+            self.base.raw_data = data;
+        }
+    }
+}
+
+/// encodes frames of the same size as a .ssf file, texture_id is the mode
+/// described in SeqFrameAsset::parse. Each frame is gzip compressed
+pub fn encode_ssf(frames: &[Buffer], texture_id: u16) -> Result<Vec<u8>, AssetError> {
+    let area = frames
+        .first()
+        .ok_or(AssetError::BadData("no frames".to_string()))?
+        .area;
+    if area.width == 0 || area.height == 0 || texture_id > 257 {
+        return Err(AssetError::MalformedHeader(format!(
+            "width={},height={},texture={}",
+            area.width, area.height, texture_id
+        )));
+    }
+    let mut lens = String::new();
+    let mut data = vec![];
+    for (i, frame) in frames.iter().enumerate() {
+        if frame.area.width != area.width || frame.area.height != area.height {
+            return Err(AssetError::BadData(format!("frame {} size", i)));
+        }
+        let mut raw = vec![];
+        if texture_id == 257 {
+            for row in frame.content.chunks(area.width as usize) {
+                raw.extend(cells_to_escstr(row).as_bytes());
+                raw.push(b'\n');
+            }
+        } else {
+            for cell in &frame.content {
+                let (fg, bg) = (u8::from(cell.fg), u8::from(cell.bg));
+                if texture_id == 256 {
+                    let c = cell.symbol.chars().next().unwrap_or(' ');
+                    raw.extend([fg, bg]);
+                    raw.extend(c.to_string().as_bytes());
+                } else {
+                    raw.extend([cellinfo(&cell.symbol), fg]);
+                    if texture_id == 255 {
+                        raw.push(bg);
+                    }
+                }
+            }
+        }
+        let mut enc = GzEncoder::new(vec![], Compression::best());
+        let gz = enc
+            .write_all(&raw)
+            .and_then(|_| enc.finish())
+            .map_err(|e| AssetError::Decompress(format!("frame {} {}", i, e)))?;
+        lens.push_str(&format!("{},", gz.len()));
+        data.extend(gz);
+    }
+    let mut out = format!(
+        "width={},height={},texture={},frame_count={}\n{}\n",
+        area.width,
+        area.height,
+        texture_id,
+        frames.len(),
+        lens
+    )
+    .into_bytes();
+    out.extend(data);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asset::AssetType;
+
+    // three frames with a moving glyph, bg colors change per frame
+    // unless the texture is shared
+    fn frames(tex: u16) -> Vec<Buffer> {
+        let mut frames = vec![];
+        for f in 0..3u8 {
+            let mut b = Buffer::empty(Rect::new(0, 0, 3, 2));
+            let bg = if tex < 255 { tex as u8 } else { f };
+            let (blank, sym) = if tex >= 256 {
+                (" ", "é")
+            } else {
+                (cellsym(32), cellsym(f))
+            };
+            let style = Style::default()
+                .fg(Color::Indexed(0))
+                .bg(Color::Indexed(bg));
+            for y in 0..2 {
+                b.set_str(0, y, blank.repeat(3), style);
+            }
+            b.set_str(f as u16, 1, sym, style.fg(Color::Indexed(f + 9)));
+            frames.push(b);
+        }
+        frames
+    }
+
+    fn assert_round_trips(tex: u16) {
+        let frames = frames(tex);
+        let data = encode_ssf(&frames, tex).unwrap();
+        let mut ast = SeqFrameAsset::new(AssetBase::new(AssetType::ImgSsf, "a.ssf"));
+        ast.set_data(&data);
+        ast.parse().unwrap();
+        assert_eq!(ast.texture_id, tex);
+        assert_eq!(ast.base.parsed_buffers, frames);
+    }
+
+    #[test]
+    fn it_encodes_per_cell_textures() {
+        assert_round_trips(255);
     }
 
-    fn save(&mut self, _content: &Buffer) {}
+    #[test]
+    fn it_encodes_a_shared_texture() {
+        assert_round_trips(3);
+    }
+
+    #[test]
+    fn it_encodes_utf8_frames() {
+        assert_round_trips(256);
+    }
+
+    #[test]
+    fn it_encodes_esc_frames() {
+        assert_round_trips(257);
+    }
+
+    #[test]
+    fn it_rejects_frames_longer_than_the_header() {
+        for tex in [255, 3, 256] {
+            let big = vec![Buffer::empty(Rect::new(0, 0, 30, 20))];
+            let mut data = encode_ssf(&big, tex).unwrap();
+            let head = format!("width=30,height=20,texture={}", tex);
+            let small = format!("width=3,height=2,texture={}", tex);
+            data.splice(..head.len(), small.bytes());
+            let mut ast = SeqFrameAsset::new(AssetBase::new(AssetType::ImgSsf, "a.ssf"));
+            ast.set_data(&data);
+            assert!(matches!(ast.parse(), Err(AssetError::BadData(_))));
+        }
+    }
+
+    #[test]
+    fn it_rejects_empty_sequences() {
+        assert!(encode_ssf(&[], 255).is_err());
+    }
 }
@@ -0,0 +1,310 @@
+// RustPixel
+// copyright zhouxin@tuyoogame.com 2022~2024
+
+//! Palettes map indexed colors to rgb.
+//!
+//! The active palette is context.palette, Panel::draw hands it to the adapter
+//! every frame. Graphical modes draw all indexed colors with it, text mode
+//! sends indexed colors covered by a custom palette as rgb, so they are
+//! quantized for the terminal like other rgb colors.
+//!
+//! A palette sets the first entries of the 256 color table, the rest keep
+//! the xterm colors. Built-in palettes are xterm, c64, nes, pico8 and
+//! gameboy, custom ones are loaded from .hex (one RRGGBB per line) or
+//! GIMP .gpl files.
+//!
+//! Effects build new palettes instead of rewriting cells: cycle rotates a
+//! range of entries (water, fire), lerp and PaletteFade blend two palettes
+//! (day and night), tint mixes all entries with a color (damage flash).
+//!
+//! # Example
+//!
+//! ```ignore
+//! ctx.palette = Palette::by_name("pico8").unwrap();
+//! self.night = PaletteFade::new(ctx.palette.clone(), day.tint((0, 0, 64), 0.6), 3.0);
+//! // update
+//! ctx.palette = self.night.update(dt);
+//! ctx.palette.cycle(8, 11, 1);
+//! ```
+
+use crate::render::style::{Color, COLOR_RGB};
+use std::fs;
+use std::path::Path;
+
+const C64: &str = "000000 FFFFFF 68372B 70A4B2 6F3D86 588D43 352879 B8C76F
+    6F4F25 433900 9A6759 444444 6C6C6C 9AD284 6C5EB5 959595";
+
+const NES: &str = "7C7C7C 0000FC 0000BC 4428BC 940084 A80020 A81000 881400
+    503000 007800 006800 005800 004058 000000 000000 000000
+    BCBCBC 0078F8 0058F8 6844FC D800CC E40058 F83800 E45C10
+    AC7C00 00B800 00A800 00A844 008888 000000 000000 000000
+    F8F8F8 3CBCFC 6888FC 9878F8 F878F8 F85898 F87858 FCA044
+    F8B800 B8F818 58D854 58F898 00E8D8 787878 000000 000000
+    FCFCFC A4E4FC B8B8F8 D8B8F8 F8B8F8 F8A4C0 F0D0B0 FCE0A8
+    F8D878 D8F878 B8F8B8 B8F8D8 00FCFC F8D8F8 000000 000000";
+
+const PICO8: &str = "000000 1D2B53 7E2553 008751 AB5236 5F574F C2C3C7 FFF1E8
+    FF004D FFA300 FFEC27 00E436 29ADFF 83769C FF77A8 FFCCAA";
+
+// darkest to lightest
+const GAMEBOY: &str = "0F380F 306230 8BAC0F 9BBC0F";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette {
+    pub name: String,
+    // rgb of the first entries, the others are xterm colors
+    pub colors: Vec<(u8, u8, u8)>,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::xterm()
+    }
+}
+
+impl Palette {
+    pub fn new(name: &str, colors: Vec<(u8, u8, u8)>) -> Self {
+        let mut colors = colors;
+        colors.truncate(256);
+        Self {
+            name: name.to_string(),
+            colors,
+        }
+    }
+
+    /// the default 256 colors, same as COLOR_RGB
+    pub fn xterm() -> Self {
+        Self::new(
+            "xterm",
+            COLOR_RGB.iter().map(|c| (c[0], c[1], c[2])).collect(),
+        )
+    }
+
+    /// built-in palettes: xterm, c64, nes, pico8, gameboy
+    pub fn by_name(name: &str) -> Option<Self> {
+        let hex = match name {
+            "xterm" => return Some(Self::xterm()),
+            "c64" => C64,
+            "nes" => NES,
+            "pico8" => PICO8,
+            "gameboy" => GAMEBOY,
+            _ => return None,
+        };
+        Self::from_hex(name, hex).ok()
+    }
+
+    /// parses RRGGBB values separated by white space, a leading # is allowed
+    pub fn from_hex(name: &str, s: &str) -> Result<Self, String> {
+        let mut colors = vec![];
+        for t in s.split_whitespace() {
+            let t = t.trim_start_matches('#');
+            let v = u32::from_str_radix(t, 16)
+                .ok()
+                .filter(|_| t.len() == 6)
+                .ok_or(format!("palette: bad color {}", t))?;
+            colors.push(((v >> 16) as u8, (v >> 8) as u8, v as u8));
+        }
+        Ok(Self::new(name, colors))
+    }
+
+    /// parses a GIMP palette, lines of "r g b name" after the header
+    pub fn from_gpl(s: &str) -> Result<Self, String> {
+        let mut lines = s.lines();
+        if lines.next().map(|l| l.trim()) != Some("GIMP Palette") {
+            return Err("palette: not a GIMP palette".to_string());
+        }
+        let mut name = String::new();
+        let mut colors = vec![];
+        for l in lines {
+            let l = l.trim();
+            if let Some(n) = l.strip_prefix("Name:") {
+                name = n.trim().to_string();
+                continue;
+            }
+            if l.is_empty() || l.starts_with('#') || l.starts_with("Columns:") {
+                continue;
+            }
+            let v: Vec<u8> = l
+                .split_whitespace()
+                .take(3)
+                .map(|n| n.parse::<u8>())
+                .collect::<Result<_, _>>()
+                .map_err(|_| format!("palette: bad line {}", l))?;
+            if v.len() < 3 {
+                return Err(format!("palette: bad line {}", l));
+            }
+            colors.push((v[0], v[1], v[2]));
+        }
+        Ok(Self::new(&name, colors))
+    }
+
+    /// loads a .hex or .gpl file
+    pub fn load(path: &str) -> Result<Self, String> {
+        let s = fs::read_to_string(path).map_err(|e| format!("palette: {} {}", path, e))?;
+        let p = Path::new(path);
+        match p.extension().and_then(|e| e.to_str()) {
+            Some("gpl") => Self::from_gpl(&s),
+            Some("hex") => {
+                let name = p.file_stem().and_then(|n| n.to_str()).unwrap_or("");
+                Self::from_hex(name, &s)
+            }
+            _ => Err(format!("palette: unknown file type {}", path)),
+        }
+    }
+
+    /// rgb of entry i
+    pub fn index(&self, i: u8) -> (u8, u8, u8) {
+        match self.colors.get(i as usize) {
+            Some(c) => *c,
+            None => {
+                let c = COLOR_RGB[i as usize];
+                (c[0], c[1], c[2])
+            }
+        }
+    }
+
+    /// rgb of a color as drawn in graphical mode, named colors are indexes there
+    pub fn rgb(&self, c: Color) -> (u8, u8, u8) {
+        match c {
+            Color::Rgb(r, g, b) => (r, g, b),
+            c => self.index(u8::from(c)),
+        }
+    }
+
+    /// indexed colors covered by this palette as rgb, others unchanged
+    pub fn resolve(&self, c: Color) -> Color {
+        match c {
+            Color::Indexed(i) if (i as usize) < self.colors.len() => {
+                let (r, g, b) = self.index(i);
+                Color::Rgb(r, g, b)
+            }
+            c => c,
+        }
+    }
+
+    /// true if it is the same as the xterm colors
+    pub fn is_default(&self) -> bool {
+        (0..=255u8).all(|i| {
+            let c = COLOR_RGB[i as usize];
+            self.index(i) == (c[0], c[1], c[2])
+        })
+    }
+
+    /// rotates entries from..=to by n steps, color cycling
+    pub fn cycle(&mut self, from: usize, to: usize, n: isize) {
+        let to = to.min(255);
+        if from >= to {
+            return;
+        }
+        while self.colors.len() <= to {
+            let c = COLOR_RGB[self.colors.len()];
+            self.colors.push((c[0], c[1], c[2]));
+        }
+        let len = (to - from + 1) as isize;
+        self.colors[from..=to].rotate_right(n.rem_euclid(len) as usize);
+    }
+
+    /// palette between a and b at k in [0, 1]
+    pub fn lerp(a: &Palette, b: &Palette, k: f32) -> Palette {
+        let k = k.clamp(0.0, 1.0);
+        let n = a.colors.len().max(b.colors.len());
+        let mix = |x: u8, y: u8| (x as f32 + (y as f32 - x as f32) * k).round() as u8;
+        let colors = (0..n)
+            .map(|i| {
+                let (c0, c1) = (a.index(i as u8), b.index(i as u8));
+                (mix(c0.0, c1.0), mix(c0.1, c1.1), mix(c0.2, c1.2))
+            })
+            .collect();
+        Palette::new(if k < 1.0 { &a.name } else { &b.name }, colors)
+    }
+
+    /// every entry mixed with rgb by k, e.g. red for damage or blue for night
+    pub fn tint(&self, rgb: (u8, u8, u8), k: f32) -> Palette {
+        let all = Palette::new(&self.name, vec![rgb; self.colors.len()]);
+        let mut p = Palette::lerp(self, &all, k);
+        p.name = self.name.clone();
+        p
+    }
+}
+
+/// fades from one palette to another in secs seconds
+pub struct PaletteFade {
+    pub from: Palette,
+    pub to: Palette,
+    pub secs: f32,
+    pub time: f32,
+}
+
+impl PaletteFade {
+    pub fn new(from: Palette, to: Palette, secs: f32) -> Self {
+        Self {
+            from,
+            to,
+            secs,
+            time: 0.0,
+        }
+    }
+
+    /// advances dt seconds and returns the palette to use
+    pub fn update(&mut self, dt: f32) -> Palette {
+        self.time = (self.time + dt).min(self.secs);
+        let k = if self.secs > 0.0 {
+            self.time / self.secs
+        } else {
+            1.0
+        };
+        Palette::lerp(&self.from, &self.to, k)
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.time >= self.secs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_resolves_builtin_palettes() {
+        assert_eq!(Palette::by_name("pico8").unwrap().colors.len(), 16);
+        assert_eq!(Palette::by_name("nes").unwrap().colors.len(), 64);
+        let gb = Palette::by_name("gameboy").unwrap();
+        assert_eq!(gb.index(3), (0x9b, 0xbc, 0x0f));
+        // entries past the palette keep the xterm colors
+        assert_eq!(gb.index(196), (255, 0, 0));
+        assert_eq!(gb.resolve(Color::Indexed(0)), Color::Rgb(0x0f, 0x38, 0x0f));
+        assert_eq!(gb.resolve(Color::Indexed(200)), Color::Indexed(200));
+        assert!(Palette::xterm().is_default() && !gb.is_default());
+    }
+
+    #[test]
+    fn it_parses_gpl_and_hex_files() {
+        let gpl = "GIMP Palette\nName: duo\nColumns: 2\n# comment\n255 0 0\tred\n  0 0 255 blue\n";
+        let duo = Palette::from_gpl(gpl).unwrap();
+        assert_eq!(duo.name, "duo");
+        assert_eq!(duo.colors, vec![(255, 0, 0), (0, 0, 255)]);
+        let p = Palette::from_hex("p", "#000000 111111").unwrap();
+        assert_eq!(p.index(1), (0x11, 0x11, 0x11));
+        assert!(Palette::from_hex("x", "#12345").is_err());
+    }
+
+    #[test]
+    fn it_cycles_a_range_of_entries() {
+        let mut p = Palette::from_hex("p", "#000000 111111 222222 333333").unwrap();
+        p.cycle(1, 3, 1);
+        assert_eq!(p.index(0), (0, 0, 0));
+        assert_eq!(p.index(1), (0x33, 0x33, 0x33));
+        assert_eq!(p.index(2), (0x11, 0x11, 0x11));
+    }
+
+    #[test]
+    fn it_fades_between_palettes() {
+        let duo = Palette::from_hex("duo", "FF0000 0000FF").unwrap();
+        let mut fade = PaletteFade::new(duo.clone(), duo.tint((0, 0, 0), 1.0), 2.0);
+        assert_eq!(fade.update(1.0).index(0), (128, 0, 0));
+        assert!(!fade.is_done());
+        assert_eq!(fade.update(5.0).index(1), (0, 0, 0));
+        assert!(fade.is_done());
+    }
+}
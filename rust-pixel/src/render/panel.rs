@@ -10,6 +10,8 @@
 //! of each cell in every framework in SDL mode.
 //! During rendering, cell is rendered according to its opacity order first to render_texture,
 //! and later render_text displays on the canvas.
+//! Sprites with opacity or a blend mode are composited by Buffer::merge_blend,
+//! pixel_sprites by the adapter (texture alpha and blend modes in SDL).
 //! To further enhance our functionality, a set of special sprites：pixel_sprites are provided in SDL mode.
 //! They can be set per pixel, and are managed in the same way as cell.
 //! During rendering, they can be rendered by its pixel position or can be rotated about its centre.
@@ -24,10 +26,13 @@
 use crate::{
     asset::AssetManager,
     context::Context,
-    LOGO_FRAME,
     render::{
         buffer::Buffer,
+        camera::Camera,
+        palette::Palette,
+        particle::ParticleEmitter,
         sprite::{Sprite, Sprites, Widget},
+        style::Color,
     },
     util::{Rect, objpool::{GObj, GameObjPool, GameObject}},
 };
@@ -43,12 +48,19 @@ pub struct Panel {
 /// Represents a consistent panel interface for rendering.
 pub struct Frame<'a> {
     panel: &'a mut Panel,
+    // active palette, used to blend indexed colors
+    palette: &'a Palette,
     cursor_position: Option<(u16, u16)>,
 }
 
 impl<'a> Frame<'a> {
     pub fn render_widget<D: Widget>(&mut self, am: &mut AssetManager, w: &mut D) {
-        w.render(am, self.panel.current_buffer_mut());
+        w.render(am, self.palette, self.panel.current_buffer_mut());
+    }
+
+    /// draws a world sprite through a camera, see render::camera
+    pub fn render_camera(&mut self, am: &mut AssetManager, cam: &Camera, sp: &mut Sprite) {
+        cam.render(am, self.palette, self.panel.current_buffer_mut(), sp);
     }
 
     pub fn set_cursor(&mut self, x: u16, y: u16) {
@@ -78,9 +90,10 @@ impl Panel {
         info!("panel init size...{:?}", size);
     }
 
-    pub fn get_frame(&mut self) -> Frame {
+    pub fn get_frame<'a>(&'a mut self, palette: &'a Palette) -> Frame<'a> {
         Frame {
             panel: self,
+            palette,
             cursor_position: None,
         }
     }
@@ -105,8 +118,16 @@ impl Panel {
     where
         F: FnOnce(&mut AssetManager, &mut Frame),
     {
-        let mut frame = self.get_frame();
+        let mut frame = self.get_frame(&ctx.palette);
         f(&mut ctx.asset_manager, &mut frame);
+        if ctx.fade > 0.0 {
+            self.fade_buffer(&ctx.palette, ctx.fade);
+        }
+
+        // adapters draw indexed colors with the palette of the context
+        ctx.adapter.get_base().palette.clone_from(&ctx.palette);
+        ctx.adapter.get_base().logo_frames = ctx.logo_frames();
+        ctx.adapter.load_textures(&ctx.asset_manager);
 
         let cb = &self.buffers[self.current];
         let pb = &self.buffers[1 - self.current];
@@ -120,12 +141,12 @@ impl Panel {
         }
 
         ctx.adapter
-            .render_buffer(cb, pb, &mut self.pixel_sprites, ctx.stage)
+            .render_buffer(cb, pb, &mut self.pixel_sprites, &ctx.atlases, ctx.stage)
             .unwrap();
         ctx.adapter.hide_cursor().unwrap();
 
         // Swap buffers
-        if ctx.stage > LOGO_FRAME {
+        if ctx.stage > ctx.logo_frames() {
             self.buffers[1 - self.current].reset();
             self.current = 1 - self.current;
         }
@@ -133,6 +154,61 @@ impl Panel {
         Ok(())
     }
 
+    /// darkens the current buffer by level, used by scene transitions.
+    /// Indexed colors are taken from pal.
+    /// Default colors can not be scaled in text mode, so cells are cleared at level 1.0
+    fn fade_buffer(&mut self, pal: &Palette, level: f32) {
+        let buf = &mut self.buffers[self.current];
+        for c in buf.content.iter_mut() {
+            if level >= 1.0 {
+                c.reset();
+                continue;
+            }
+            if c.fg != Color::Reset {
+                c.fg = c.fg.darken_in(pal, level);
+            }
+            // graphical mode draws the symbols in draw_history
+            for h in c.draw_history.iter_mut() {
+                h.1 = h.1.darken_in(pal, level);
+            }
+            // bg indicates the texture in graphical mode
+            #[cfg(not(any(feature = "sdl", target_arch = "wasm32")))]
+            if c.bg != Color::Reset {
+                c.bg = c.bg.darken_in(pal, level);
+            }
+        }
+    }
+
+    /// draws the particles of em, call it before draw.
+    /// Graphical mode uses pixel sprites, created at the first call,
+    /// text mode draws characters into the current buffer
+    pub fn draw_particles(&mut self, ctx: &mut Context, em: &mut ParticleEmitter) {
+        #[cfg(any(feature = "sdl", target_arch = "wasm32"))]
+        {
+            if !self
+                .pixel_sprites
+                .tag_index
+                .contains_key(&format!("{}0", em.pool.prefix))
+            {
+                self.create_sprites(&em.pool, 1, 1, |_| {});
+            }
+            let (cw, ch) = (ctx.adapter.cell_width(), ctx.adapter.cell_height());
+            let tex = em.config.texture;
+            let syms: Vec<(u8, u8)> = em.pool.pool.iter().map(|o| em.sym_of(&o.obj)).collect();
+            self.draw_objs(&mut em.pool, |pl, o| {
+                let (c, s) = syms[o.id];
+                let (x, y) = ((o.obj.x * cw).max(0.0), (o.obj.y * ch).max(0.0));
+                pl.set_pos(x as u16, y as u16);
+                pl.set_sdl_content(0, 0, s, c, tex);
+            });
+        }
+        #[cfg(not(any(feature = "sdl", target_arch = "wasm32")))]
+        {
+            let _ = ctx;
+            em.render(self.current_buffer_mut());
+        }
+    }
+
     /// create a max number of sprites
     /// and calls f closure to init
     pub fn create_sprites<T, F>(
@@ -198,3 +274,23 @@ impl Panel {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::style::Style;
+
+    #[test]
+    fn it_fades_indexed_colors_with_the_palette() {
+        let pal = Palette::by_name("pico8").unwrap();
+        let mut p = Panel::new();
+        p.current_buffer_mut()
+            .set_str(0, 0, "a", Style::default().fg(Color::Indexed(8)));
+        p.fade_buffer(&pal, 0.5);
+        let (r, g, b) = pal.index(8);
+        assert_eq!(
+            p.current_buffer_mut().get(0, 0).fg,
+            Color::Rgb(r / 2, g / 2, b / 2)
+        );
+    }
+}
@@ -0,0 +1,325 @@
+// RustPixel
+// copyright zhouxin@tuyoogame.com 2022~2024
+
+//! Particle emitter for sparks, smoke, explosions and similar effects.
+//!
+//! Particles live in a GameObjPool. An emitter spawns them at a rate per
+//! second or in bursts, with random lifetime, speed and direction, and moves
+//! them with gravity. Over its life a particle steps through a color ramp of
+//! indexed palette colors and a sequence of symbols.
+//!
+//! Positions, speeds and gravity are in cells, Panel::draw_particles draws
+//! the particles with pixel_sprites in graphical mode (cellsym symbols),
+//! and as unicode characters into the main buffer in text mode.
+//!
+//! The randomness of an emitter is seeded by the caller, seed it from
+//! context.rand so recorded sessions replay the same particles.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let mut cfg = ParticleConfig::default();
+//! cfg.rate = 0.0;
+//! cfg.gravity = (0.0, 20.0);
+//! self.boom = ParticleEmitter::new("boom", cfg, ctx.rand.rand() as u64);
+//!
+//! // model
+//! self.boom.set_pos(x, y);
+//! self.boom.burst(30);
+//! self.boom.update(dt);
+//! // render, before panel.draw
+//! self.panel.draw_particles(ctx, &mut self.boom);
+//! ```
+
+use crate::{
+    render::buffer::Buffer,
+    render::style::{Color, Style},
+    util::{
+        objpool::{GObj, GameObjPool},
+        Point, Rand,
+    },
+};
+
+#[derive(Default, Debug, Clone)]
+pub struct Particle {
+    pub x: f32,
+    pub y: f32,
+    pub vx: f32,
+    pub vy: f32,
+    pub age: f32,
+    pub life: f32,
+}
+
+impl GObj for Particle {
+    fn new(t: u8, ps: &Vec<Point>) -> Self {
+        let mut p = Particle::default();
+        p.reset(t, ps);
+        p
+    }
+
+    fn reset(&mut self, _t: u8, ps: &Vec<Point>) {
+        *self = Particle::default();
+        if let Some(p) = ps.first() {
+            self.x = p.x as f32;
+            self.y = p.y as f32;
+        }
+    }
+}
+
+impl Particle {
+    /// 0.0 at birth ~ 1.0 at death
+    pub fn progress(&self) -> f32 {
+        if self.life <= 0.0 {
+            1.0
+        } else {
+            (self.age / self.life).min(1.0)
+        }
+    }
+}
+
+// item of a ramp at life progress t
+fn ramp<T>(v: &[T], t: f32) -> Option<&T> {
+    if v.is_empty() {
+        return None;
+    }
+    v.get(((t * v.len() as f32) as usize).min(v.len() - 1))
+}
+
+#[derive(Debug, Clone)]
+pub struct ParticleConfig {
+    // particles spawned per second while emitting
+    pub rate: f32,
+    // most particles alive at once, also the number of pixel sprites
+    pub max: usize,
+    // (min, max) seconds
+    pub life: (f32, f32),
+    // (min, max) cells per second
+    pub speed: (f32, f32),
+    // (min, max) direction in degrees, 0 is right, 90 is down
+    pub angle: (f32, f32),
+    // cells per second squared
+    pub gravity: (f32, f32),
+    // indexed colors over life
+    pub colors: Vec<u8>,
+    // cellsym symbols over life, graphical mode
+    pub symbols: Vec<u8>,
+    // characters over life, text mode
+    pub chars: Vec<String>,
+    // texture of the symbols, graphical mode
+    pub texture: u8,
+}
+
+impl Default for ParticleConfig {
+    fn default() -> Self {
+        Self {
+            rate: 20.0,
+            max: 64,
+            life: (0.5, 1.0),
+            speed: (5.0, 10.0),
+            angle: (0.0, 360.0),
+            gravity: (0.0, 0.0),
+            colors: vec![231, 226, 214, 196, 88],
+            symbols: vec![27, 26, 25, 24],
+            chars: ["*", "+", "·", "."].iter().map(|s| s.to_string()).collect(),
+            texture: 2,
+        }
+    }
+}
+
+pub struct ParticleEmitter {
+    pub config: ParticleConfig,
+    pub x: f32,
+    pub y: f32,
+    // spawns rate particles per second if true
+    pub emitting: bool,
+    pub pool: GameObjPool<Particle>,
+    // fraction of a particle carried to the next update
+    acc: f32,
+    rand: Rand,
+}
+
+impl ParticleEmitter {
+    /// prefix names the pixel sprites of the particles, must be unique in a panel,
+    /// seed starts the random lifetimes, speeds and angles
+    pub fn new(prefix: &str, config: ParticleConfig, seed: u64) -> Self {
+        let mut rand = Rand::new();
+        rand.srand(seed);
+        Self {
+            pool: GameObjPool::new(prefix, config.max),
+            config,
+            x: 0.0,
+            y: 0.0,
+            emitting: true,
+            acc: 0.0,
+            rand,
+        }
+    }
+
+    pub fn set_pos(&mut self, x: f32, y: f32) {
+        self.x = x;
+        self.y = y;
+    }
+
+    pub fn start(&mut self) {
+        self.emitting = true;
+    }
+
+    pub fn stop(&mut self) {
+        self.emitting = false;
+        self.acc = 0.0;
+    }
+
+    pub fn alive(&self) -> usize {
+        self.pool.pool.iter().filter(|o| o.active).count()
+    }
+
+    // random value in range r
+    fn rnd(&mut self, r: (f32, f32)) -> f32 {
+        let k = self.rand.rand() as f32 / u32::MAX as f32;
+        r.0 + (r.1 - r.0) * k
+    }
+
+    /// spawns n particles at once, as many as max allows
+    pub fn burst(&mut self, n: usize) {
+        let n = n.min(self.config.max.saturating_sub(self.alive()));
+        for _ in 0..n {
+            let life = self.rnd(self.config.life);
+            let speed = self.rnd(self.config.speed);
+            let a = self.rnd(self.config.angle).to_radians();
+            let id = self.pool.create(0, &vec![]);
+            self.pool.pool[id].obj = Particle {
+                x: self.x,
+                y: self.y,
+                vx: a.cos() * speed,
+                vy: a.sin() * speed,
+                age: 0.0,
+                life,
+            };
+        }
+    }
+
+    /// emits and moves particles, kills the old ones
+    pub fn update(&mut self, dt: f32) {
+        let (gx, gy) = self.config.gravity;
+        self.pool.update_active(|o| {
+            let p = &mut o.obj;
+            p.age += dt;
+            if p.age >= p.life {
+                o.active = false;
+                return;
+            }
+            p.vx += gx * dt;
+            p.vy += gy * dt;
+            p.x += p.vx * dt;
+            p.y += p.vy * dt;
+        });
+        if self.emitting {
+            self.acc += self.config.rate * dt;
+            let n = self.acc as usize;
+            self.acc -= n as f32;
+            self.burst(n);
+        }
+    }
+
+    /// (color, symbol) of a particle at its age, graphical mode
+    pub fn sym_of(&self, p: &Particle) -> (u8, u8) {
+        let t = p.progress();
+        (
+            *ramp(&self.config.colors, t).unwrap_or(&15),
+            *ramp(&self.config.symbols, t).unwrap_or(&0),
+        )
+    }
+
+    /// draws particles as characters into buf, the text mode fallback
+    pub fn render(&self, buf: &mut Buffer) {
+        let a = buf.area;
+        for o in self.pool.pool.iter().filter(|o| o.active) {
+            let p = &o.obj;
+            let (x, y) = (p.x.floor(), p.y.floor());
+            if x < a.left() as f32 || y < a.top() as f32 {
+                continue;
+            }
+            let (x, y) = (x as u16, y as u16);
+            if x >= a.right() || y >= a.bottom() {
+                continue;
+            }
+            let t = p.progress();
+            let c = *ramp(&self.config.colors, t).unwrap_or(&15);
+            let s = ramp(&self.config.chars, t).map_or("*", |s| s.as_str());
+            buf.set_string(x, y, s, Style::default().fg(Color::Indexed(c)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::Rect;
+
+    // 1s life, moving right at 2 cells/s with gravity down
+    fn emitter() -> ParticleEmitter {
+        let cfg = ParticleConfig {
+            rate: 10.0,
+            max: 8,
+            life: (1.0, 1.0),
+            speed: (2.0, 2.0),
+            angle: (0.0, 0.0),
+            gravity: (0.0, 4.0),
+            ..Default::default()
+        };
+        let mut em = ParticleEmitter::new("p", cfg, 1);
+        em.set_pos(1.0, 1.0);
+        em
+    }
+
+    #[test]
+    fn it_emits_at_rate_and_bursts_up_to_max() {
+        let mut em = emitter();
+        em.update(0.25);
+        assert_eq!(em.alive(), 2);
+        em.stop();
+        em.burst(100);
+        assert_eq!(em.alive(), 8);
+    }
+
+    #[test]
+    fn it_moves_and_renders_particles() {
+        let mut em = emitter();
+        em.stop();
+        em.burst(1);
+        em.update(0.5);
+        let p = &em.pool.pool[0].obj;
+        assert_eq!((p.x, p.y), (2.0, 2.0));
+        assert_eq!(em.sym_of(p), (214, 25));
+        let mut buf = Buffer::empty(Rect::new(0, 0, 4, 4));
+        em.render(&mut buf);
+        assert_eq!(buf.get(2, 2).symbol, "·");
+        assert_eq!(buf.get(2, 2).fg, Color::Indexed(214));
+    }
+
+    #[test]
+    fn it_kills_particles_after_their_life() {
+        let mut em = emitter();
+        em.stop();
+        em.burst(8);
+        em.update(0.5);
+        assert_eq!(em.alive(), 8);
+        em.update(0.6);
+        assert_eq!(em.alive(), 0);
+    }
+
+    #[test]
+    fn it_spawns_the_same_particles_from_the_same_seed() {
+        let spawn = |seed| {
+            let mut em = ParticleEmitter::new("p", ParticleConfig::default(), seed);
+            em.burst(4);
+            em.pool
+                .pool
+                .iter()
+                .map(|o| (o.obj.vx, o.obj.vy, o.obj.life))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(spawn(7), spawn(7));
+        assert_ne!(spawn(7), spawn(8));
+    }
+}
@@ -0,0 +1,391 @@
+// RustPixel
+// copyright zhouxin@tuyoogame.com 2022~2024
+
+//! Golden-frame snapshot for rendered buffers.
+//!
+//! A buffer is serialized to a stable text format, one row of symbols per line,
+//! followed by the style of every cell that differs from the default style:
+//!
+//! width=5,height=2
+//! |snake|
+//! |     |
+//! styles:
+//! 0,0 fg=Indexed(222) bg=Reset mod=BOLD
+//!
+//! A symbol that is not one grapheme, has control chars or would join its
+//! neighbour cells to one grapheme (e.g. a lone combining mark) is escaped
+//! in its row as \{61,62}, the hex code points of its chars, e.g. the empty
+//! symbol is \{}.
+//! A backslash symbol is written as \\.
+//!
+//! check_snapshot compares a buffer with a checked-in golden file and
+//! returns a cell-by-cell diff on mismatch. A missing golden file is an
+//! error too, set env PIXEL_UPDATE_SNAPSHOTS=1 to (re)write golden files.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let ha = g.context.adapter.as_any().downcast_ref::<HeadlessAdapter>().unwrap();
+//! assert_snapshot(ha.last_frame(), "tests/snapshots/snake_start.snap");
+//! ```
+
+use crate::{
+    render::buffer::Buffer,
+    render::cell::Cell,
+    render::style::{Color, Modifier},
+    util::Rect,
+};
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::fmt::Write;
+use std::path::Path;
+use unicode_segmentation::UnicodeSegmentation;
+
+pub const SNAPSHOT_UPDATE_ENV: &str = "PIXEL_UPDATE_SNAPSHOTS";
+
+lazy_static! {
+    static ref COLOR_RE: Regex = Regex::new(r"^(\w+)(?:\((\d+)(?:,(\d+),(\d+))?\))?$").unwrap();
+    static ref HEAD_RE: Regex = Regex::new(r"^width=(\d+),height=(\d+)$").unwrap();
+    static ref STYLE_RE: Regex =
+        Regex::new(r"^(\d+),(\d+) fg=(\S+) bg=(\S+) mod=(\S+)$").unwrap();
+}
+
+fn color_to_string(c: Color) -> String {
+    match c {
+        Color::Rgb(r, g, b) => format!("Rgb({},{},{})", r, g, b),
+        _ => format!("{:?}", c),
+    }
+}
+
+fn color_from_string(s: &str) -> Result<Color, String> {
+    let cap = COLOR_RE
+        .captures(s)
+        .ok_or_else(|| format!("snapshot: bad color {}", s))?;
+    let num = |i: usize| -> Result<u8, String> {
+        cap.get(i)
+            .ok_or_else(|| format!("snapshot: bad color {}", s))?
+            .as_str()
+            .parse::<u8>()
+            .map_err(|e| e.to_string())
+    };
+    let c = match &cap[1] {
+        "Reset" => Color::Reset,
+        "Black" => Color::Black,
+        "Red" => Color::Red,
+        "Green" => Color::Green,
+        "Yellow" => Color::Yellow,
+        "Blue" => Color::Blue,
+        "Magenta" => Color::Magenta,
+        "Cyan" => Color::Cyan,
+        "Gray" => Color::Gray,
+        "DarkGray" => Color::DarkGray,
+        "LightRed" => Color::LightRed,
+        "LightGreen" => Color::LightGreen,
+        "LightYellow" => Color::LightYellow,
+        "LightBlue" => Color::LightBlue,
+        "LightMagenta" => Color::LightMagenta,
+        "LightCyan" => Color::LightCyan,
+        "White" => Color::White,
+        "Indexed" => Color::Indexed(num(2)?),
+        "Rgb" => Color::Rgb(num(2)?, num(3)?, num(4)?),
+        _ => return Err(format!("snapshot: bad color {}", s)),
+    };
+    Ok(c)
+}
+
+fn modifier_to_string(m: Modifier) -> String {
+    if m.is_empty() {
+        return "NONE".to_string();
+    }
+    let mut s = String::new();
+    let _ = bitflags::parser::to_writer(&m, &mut s);
+    s.replace(' ', "")
+}
+
+fn modifier_from_string(s: &str) -> Result<Modifier, String> {
+    if s == "NONE" {
+        return Ok(Modifier::empty());
+    }
+    bitflags::parser::from_str::<Modifier>(&s.replace('|', " | ")).map_err(|e| e.to_string())
+}
+
+fn style_to_string(cell: &Cell) -> String {
+    format!(
+        "fg={} bg={} mod={}",
+        color_to_string(cell.fg),
+        color_to_string(cell.bg),
+        modifier_to_string(cell.modifier)
+    )
+}
+
+// writes a symbol to a row, escaped unless it stays one grapheme of its own
+// in the row: control chars and symbols joining the text before or after
+// them (combining marks, zwj, regional indicator pairs...) are escaped
+fn push_symbol(s: &mut String, sym: &str) {
+    let last = s.graphemes(true).next_back().unwrap_or("");
+    let plain = sym.graphemes(true).count() == 1
+        && !sym.chars().any(char::is_control)
+        && format!("{}{}", last, sym).graphemes(true).count() == 2
+        && format!("{}x", sym).graphemes(true).count() == 2;
+    if sym == "\\" {
+        s.push_str("\\\\");
+    } else if plain {
+        s.push_str(sym);
+    } else {
+        let cps: Vec<String> = sym.chars().map(|c| format!("{:x}", c as u32)).collect();
+        let _ = write!(s, "\\{{{}}}", cps.join(","));
+    }
+}
+
+// splits a row to the symbols of its cells, see push_symbol
+fn row_symbols(row: &str) -> Result<Vec<String>, String> {
+    let bad = || format!("snapshot: bad escape in {}", row);
+    let mut syms = vec![];
+    let mut gs = row.graphemes(true);
+    while let Some(g) = gs.next() {
+        if g != "\\" {
+            syms.push(g.to_string());
+            continue;
+        }
+        match gs.next() {
+            Some("\\") => syms.push(g.to_string()),
+            Some("{") => {
+                let mut body = String::new();
+                loop {
+                    match gs.next().ok_or_else(bad)? {
+                        "}" => break,
+                        h => body.push_str(h),
+                    }
+                }
+                let mut sym = String::new();
+                for h in body.split(',').filter(|h| !h.is_empty()) {
+                    let cp = u32::from_str_radix(h, 16).map_err(|_| bad())?;
+                    sym.push(char::from_u32(cp).ok_or_else(bad)?);
+                }
+                syms.push(sym);
+            }
+            _ => return Err(bad()),
+        }
+    }
+    Ok(syms)
+}
+
+/// serializes a buffer to snapshot text, draw_history is not included
+pub fn buffer_to_snapshot(buf: &Buffer) -> String {
+    let mut s = String::new();
+    let width = buf.area.width as usize;
+    let _ = writeln!(s, "width={},height={}", buf.area.width, buf.area.height);
+    for row in buf.content.chunks(width.max(1)) {
+        s.push('|');
+        for cell in row {
+            push_symbol(&mut s, &cell.symbol);
+        }
+        s.push_str("|\n");
+    }
+    s.push_str("styles:\n");
+    let dc = Cell::default();
+    for (i, cell) in buf.content.iter().enumerate() {
+        if cell.fg != dc.fg || cell.bg != dc.bg || cell.modifier != dc.modifier {
+            let _ = writeln!(s, "{},{} {}", i % width, i / width, style_to_string(cell));
+        }
+    }
+    s
+}
+
+/// parses snapshot text to a buffer located at (0, 0)
+pub fn snapshot_to_buffer(s: &str) -> Result<Buffer, String> {
+    let mut lines = s.lines();
+    let head = lines.next().unwrap_or("");
+    let cap = HEAD_RE
+        .captures(head)
+        .ok_or_else(|| format!("snapshot: bad header {}", head))?;
+    let width = cap[1].parse::<u16>().map_err(|e| e.to_string())?;
+    let height = cap[2].parse::<u16>().map_err(|e| e.to_string())?;
+    let mut buf = Buffer::empty(Rect::new(0, 0, width, height));
+    for y in 0..height {
+        let l = lines
+            .next()
+            .ok_or_else(|| format!("snapshot: missing row {}", y))?;
+        let row = l
+            .strip_prefix('|')
+            .and_then(|r| r.strip_suffix('|'))
+            .ok_or_else(|| format!("snapshot: bad row {}", y))?;
+        let syms = row_symbols(row)?;
+        if syms.len() != width as usize {
+            return Err(format!(
+                "snapshot: row {} has {} cells, expect {}",
+                y,
+                syms.len(),
+                width
+            ));
+        }
+        for (x, sym) in syms.iter().enumerate() {
+            buf.content[y as usize * width as usize + x].set_symbol(sym);
+        }
+    }
+    if lines.next() != Some("styles:") {
+        return Err("snapshot: missing styles".to_string());
+    }
+    for l in lines {
+        if l.is_empty() {
+            continue;
+        }
+        let cap = STYLE_RE
+            .captures(l)
+            .ok_or_else(|| format!("snapshot: bad style {}", l))?;
+        let x = cap[1].parse::<u16>().map_err(|e| e.to_string())?;
+        let y = cap[2].parse::<u16>().map_err(|e| e.to_string())?;
+        if x >= width || y >= height {
+            return Err(format!("snapshot: style out of range {}", l));
+        }
+        let cell = buf.get_mut(x, y);
+        cell.fg = color_from_string(&cap[3])?;
+        cell.bg = color_from_string(&cap[4])?;
+        cell.modifier = modifier_from_string(&cap[5])?;
+    }
+    Ok(buf)
+}
+
+/// compares two buffers cell by cell, ignoring position and draw_history
+/// returns a readable line for each different cell
+pub fn snapshot_diff(expected: &Buffer, actual: &Buffer) -> Vec<String> {
+    let mut ds = vec![];
+    let (ew, eh) = (expected.area.width, expected.area.height);
+    let (aw, ah) = (actual.area.width, actual.area.height);
+    if ew != aw || eh != ah {
+        ds.push(format!(
+            "size: expected {}x{}, actual {}x{}",
+            ew, eh, aw, ah
+        ));
+        return ds;
+    }
+    for (i, (e, a)) in expected.content.iter().zip(actual.content.iter()).enumerate() {
+        if e.symbol != a.symbol || e.fg != a.fg || e.bg != a.bg || e.modifier != a.modifier {
+            ds.push(format!(
+                "({},{}): expected {:?} {}, actual {:?} {}",
+                i % ew as usize,
+                i / ew as usize,
+                e.symbol,
+                style_to_string(e),
+                a.symbol,
+                style_to_string(a)
+            ));
+        }
+    }
+    ds
+}
+
+/// compares buf with the golden file at path, a missing golden file fails,
+/// writes the golden file instead if PIXEL_UPDATE_SNAPSHOTS is set
+pub fn check_snapshot(buf: &Buffer, path: &str) -> Result<(), String> {
+    let p = Path::new(path);
+    let update = std::env::var(SNAPSHOT_UPDATE_ENV).is_ok_and(|v| v != "0");
+    if !update && !p.exists() {
+        return Err(format!(
+            "snapshot {} missing, set {}=1 to write it",
+            path, SNAPSHOT_UPDATE_ENV
+        ));
+    }
+    if update {
+        if let Some(dir) = p.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        return std::fs::write(p, buffer_to_snapshot(buf)).map_err(|e| e.to_string());
+    }
+    let golden = std::fs::read_to_string(p).map_err(|e| e.to_string())?;
+    let expected = snapshot_to_buffer(&golden)?;
+    let ds = snapshot_diff(&expected, buf);
+    if ds.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "snapshot {} mismatch, {} cell(s) differ:\n{}",
+            path,
+            ds.len(),
+            ds.join("\n")
+        ))
+    }
+}
+
+/// panics with a cell-by-cell diff if buf differs from the golden file
+pub fn assert_snapshot(buf: &Buffer, path: &str) {
+    if let Err(e) = check_snapshot(buf, path) {
+        panic!("{}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::style::Style;
+
+    fn sample() -> Buffer {
+        let mut buf = Buffer::empty(Rect::new(0, 0, 6, 2));
+        buf.set_str(
+            0,
+            0,
+            "a|中∀",
+            Style::default()
+                .fg(Color::Indexed(222))
+                .bg(Color::Rgb(1, 2, 3))
+                .add_modifier(Modifier::BOLD | Modifier::ITALIC),
+        );
+        buf.set_str(1, 1, "x", Style::default().fg(Color::LightRed));
+        buf
+    }
+
+    #[test]
+    fn it_round_trips_snapshot_text() {
+        let buf = sample();
+        let s = buffer_to_snapshot(&buf);
+        let nbuf = snapshot_to_buffer(&s).unwrap();
+        assert!(snapshot_diff(&buf, &nbuf).is_empty());
+        assert_eq!(buffer_to_snapshot(&nbuf), s);
+    }
+
+    #[test]
+    fn it_escapes_symbols_that_are_not_one_grapheme() {
+        let mut buf = Buffer::empty(Rect::new(0, 0, 4, 1));
+        for (i, sym) in ["", "ab", "\\", "{"].iter().enumerate() {
+            buf.content[i].set_symbol(sym);
+        }
+        let s = buffer_to_snapshot(&buf);
+        assert!(s.contains("|\\{}\\{61,62}\\\\{|"));
+        let nbuf = snapshot_to_buffer(&s).unwrap();
+        assert!(snapshot_diff(&buf, &nbuf).is_empty());
+        assert!(snapshot_to_buffer("width=1,height=1\n|\\x|\nstyles:\n").is_err());
+    }
+
+    #[test]
+    fn it_escapes_symbols_joining_their_neighbours() {
+        let syms = [
+            "\u{301}", "e", "\u{301}", "👨", "\u{200d}", "👩", "🇺", "🇸", "\n", "\r\n", "ᄀ", "ᅡ",
+        ];
+        let mut buf = Buffer::empty(Rect::new(0, 0, syms.len() as u16, 1));
+        for (i, sym) in syms.iter().enumerate() {
+            buf.content[i].set_symbol(sym);
+        }
+        let s = buffer_to_snapshot(&buf);
+        assert_eq!(s.lines().count(), 3);
+        let nbuf = snapshot_to_buffer(&s).unwrap();
+        assert!(snapshot_diff(&buf, &nbuf).is_empty());
+    }
+
+    #[test]
+    fn it_fails_on_a_missing_golden_file() {
+        let path = std::env::temp_dir().join("rp_no_such_golden.snap");
+        let e = check_snapshot(&sample(), path.to_str().unwrap()).unwrap_err();
+        assert!(e.contains("missing"));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn it_reports_different_cells() {
+        let buf = sample();
+        let mut nbuf = buf.clone();
+        nbuf.set_str(5, 1, "y", Style::default());
+        let ds = snapshot_diff(&buf, &nbuf);
+        assert_eq!(ds.len(), 1);
+        assert!(ds[0].starts_with("(5,1): expected \" \""));
+    }
+}
@@ -5,13 +5,19 @@
 //! It is also the most common component in RustPixel
 //! It provides drawing methods such as set_border，draw_line, draw_circle
 //! Refer to util/shape.rs for an example of how to draw a line
+//! opacity and blend set how a sprite is composited over the sprites below,
+//! see Buffer::merge_blend
+//! An Animator attached to a sprite plays clips of a .ssf asset,
+//! see sprite/animator.rs
 
 use crate::{
-    asset::{AssetManager, AssetState, AssetType},
+    asset::{missing_buffer, AssetManager, AssetState, AssetType},
+    event::EventBus,
     render::buffer::Buffer,
+    render::palette::Palette,
     render::cell::cellsym,
     // render::image::*,
-    render::style::{Color, Style},
+    render::style::{BlendMode, Color, Style},
     util::shape::{circle, line, prepare_line},
     util::{FPoint, Rect},
 };
@@ -22,6 +28,9 @@ use bitflags::bitflags;
 mod sprites;
 pub use sprites::Sprites;
 
+mod animator;
+pub use animator::{AnimClip, AnimCondition, AnimEvent, AnimTransition, Animator, PlayMode};
+
 /// Defines some common tabs symbol (in text mode)
 pub const SYMBOL_LINE: [&str; 37] = [
     "│", "║", "┃", "─", "═", "━", "┐", "╮", "╗", "┓", "┌", "╭", "╔", "┏", "┘", "╯", "╝", "┛", "└",
@@ -103,22 +112,34 @@ macro_rules! asset2sprite {
 }
 
 pub trait Widget {
-    fn render(&mut self, am: &mut AssetManager, buf: &mut Buffer);
+    fn render(&mut self, am: &mut AssetManager, pal: &Palette, buf: &mut Buffer);
 }
 
+/// asset type, location, frame index, offset x, offset y
+pub type AssetRequest = (AssetType, String, usize, u16, u16);
+
 #[derive(Clone)]
 pub struct Sprite {
     pub content: Buffer,
     pub angle: f64,
-    pub asset_request: Option<(AssetType, String, usize, u16, u16)>,
+    pub asset_request: Option<AssetRequest>,
+    // last request drawn and the asset version, redrawn when the asset reloads
+    pub asset_drawn: Option<(AssetRequest, u32)>,
+    // 0.0 transparent ~ 1.0 opaque
+    pub opacity: f32,
+    pub blend: BlendMode,
+    // position in world coordinates, used when drawn through a Camera
+    pub world_pos: Option<(i32, i32)>,
+    // plays clips of a sequence frame asset, see animate
+    pub animator: Option<Animator>,
     render_weight: i32,
 }
 
 impl Widget for Sprite {
-    fn render(&mut self, am: &mut AssetManager, buf: &mut Buffer) {
+    fn render(&mut self, am: &mut AssetManager, pal: &Palette, buf: &mut Buffer) {
         if !self.is_hidden() {
             self.check_asset_request(am);
-            buf.merge(&self.content, true);
+            buf.merge_blend(&self.content, true, self.opacity, self.blend, pal);
         }
     }
 }
@@ -131,6 +152,11 @@ impl Sprite {
             content: buffer,
             angle: 0.0,
             asset_request: None,
+            asset_drawn: None,
+            opacity: 1.0,
+            blend: BlendMode::Normal,
+            world_pos: None,
+            animator: None,
             render_weight: 1,
         }
     }
@@ -161,12 +187,31 @@ impl Sprite {
     }
 
     pub fn check_asset_request(&mut self, am: &mut AssetManager) {
+        if am.watching && self.asset_request.is_none() {
+            if let Some((req, v)) = &self.asset_drawn {
+                if am.version(&req.1).is_some_and(|nv| nv != *v) {
+                    // reloaded, the new image may be smaller
+                    self.content.reset();
+                    self.asset_request = Some(req.clone());
+                }
+            }
+        }
         if let Some(req) = &self.asset_request {
             if let Some(ast) = am.get(&req.1) {
                 match ast.get_state() {
                     AssetState::Ready => {
                         ast.set_sprite(self, req.2, req.3, req.4);
-                        self.asset_request = None;
+                        let v = ast.get_base().version;
+                        self.asset_drawn = self.asset_request.take().map(|r| (r, v));
+                    }
+                    // the request is kept, a reloaded asset replaces the marker
+                    AssetState::Failed(_) => {
+                        let mut area = self.content.area;
+                        if area.width == 0 || area.height == 0 {
+                            area.width = 7;
+                            area.height = 1;
+                        }
+                        self.content = missing_buffer(area);
                     }
                     _ => {}
                 }
@@ -174,6 +219,53 @@ impl Sprite {
         }
     }
 
+    pub fn set_animator(&mut self, an: Animator) {
+        self.animator = Some(an);
+    }
+
+    /// advances the animator and draws its current frame,
+    /// while crossfading the new frame is blended over the previous one
+    pub fn animate(&mut self, am: &mut AssetManager, pal: &Palette, bus: &mut EventBus, dt: f32) {
+        let Some(an) = &mut self.animator else {
+            return;
+        };
+        an.update(dt, bus);
+        if !an.take_dirty() {
+            return;
+        }
+        let (atype, loc, frame) = (an.asset_type, an.location.clone(), an.frame());
+        let Some((prev, weight)) = an.blend_from() else {
+            self.set_content_by_asset(am, atype, &loc, frame, 0, 0);
+            return;
+        };
+        am.load(atype, &loc);
+        if let Some(ast) = am.get(&loc) {
+            if ast.get_state() != AssetState::Ready {
+                return;
+            }
+            let bs = ast.get_base();
+            let n = bs.frame_count.max(1);
+            let (pb, cb) = (&bs.parsed_buffers[prev % n], &bs.parsed_buffers[frame % n]);
+            self.content.reset();
+            let _ = self.content.blit(0, 0, pb, pb.area);
+            let mut top = Buffer::empty(self.content.area);
+            let _ = top.blit(0, 0, cb, cb.area);
+            self.content.merge_blend(&top, true, weight, BlendMode::Normal, pal);
+        }
+    }
+
+    pub fn set_opacity(&mut self, o: f32) {
+        self.opacity = o.clamp(0.0, 1.0);
+    }
+
+    pub fn set_blend(&mut self, b: BlendMode) {
+        self.blend = b;
+    }
+
+    pub fn set_world_pos(&mut self, x: i32, y: i32) {
+        self.world_pos = Some((x, y));
+    }
+
     pub fn set_angle(&mut self, a: f64) {
         self.angle = a;
     }
@@ -0,0 +1,459 @@
+// RustPixel
+// copyright zhouxin@tuyoogame.com 2022~2024
+
+//! Animator plays named clips of a sequence frame asset (.ssf) on a Sprite.
+//!
+//! A clip is a range of frames of the asset, each with its own duration in
+//! seconds, played in loop, ping-pong or once mode. Frames of a clip can carry
+//! named events, they are emitted as AnimEvent through context.events when
+//! the frame is reached. A clip played once emits an "end" event when done.
+//!
+//! Transitions form a state machine between clips: a transition fires when
+//! its clip ends, a trigger is set or a bool parameter matches, and can
+//! crossfade from the last frame of the old clip over blend seconds.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let mut an = Animator::new("hero", AssetType::ImgSsf, "games/x/assets/hero.ssf");
+//! an.add_clip(AnimClip::new("idle", 0, 3, 0.2, PlayMode::Loop));
+//! an.add_clip(AnimClip::new("run", 4, 11, 0.08, PlayMode::Loop).with_event(2, "step"));
+//! an.add_clip(AnimClip::new("jump", 12, 15, 0.1, PlayMode::Once));
+//! an.add_transition("*", "jump", AnimCondition::Trigger("jump".to_string()), 0.0);
+//! an.add_transition("jump", "idle", AnimCondition::End, 0.1);
+//! an.add_transition("idle", "run", AnimCondition::Param("moving".to_string(), true), 0.1);
+//! an.add_transition("run", "idle", AnimCondition::Param("moving".to_string(), false), 0.1);
+//! an.play("idle");
+//! sprite.set_animator(an);
+//!
+//! // model update
+//! sprite.animator.as_mut().unwrap().set_param("moving", true);
+//! sprite.animate(&mut ctx.asset_manager, &ctx.palette, &mut ctx.events, dt);
+//! // handle_event
+//! for e in ctx.events.drain::<AnimEvent>("render") { ... }
+//! ```
+
+use crate::{asset::AssetType, event::EventBus};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PlayMode {
+    Loop,
+    // plays forward and backward in turn
+    PingPong,
+    // stops at the last frame
+    Once,
+}
+
+/// emitted through EventBus when a frame with an event is reached
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnimEvent {
+    // id of the Animator
+    pub animator: String,
+    pub clip: String,
+    pub name: String,
+    // frame position in the clip, 0 is the first frame
+    pub frame: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct AnimClip {
+    pub name: String,
+    // frame indexes in the asset
+    pub frames: Vec<usize>,
+    // seconds of each frame
+    pub durations: Vec<f32>,
+    pub mode: PlayMode,
+    // (frame position in the clip, event name)
+    pub events: Vec<(usize, String)>,
+}
+
+impl AnimClip {
+    /// frames from..=to of the asset, to < from plays backward
+    pub fn new(name: &str, from: usize, to: usize, frame_time: f32, mode: PlayMode) -> Self {
+        let frames: Vec<usize> = if from <= to {
+            (from..=to).collect()
+        } else {
+            (to..=from).rev().collect()
+        };
+        let durations = vec![frame_time; frames.len()];
+        Self {
+            name: name.to_string(),
+            frames,
+            durations,
+            mode,
+            events: vec![],
+        }
+    }
+
+    /// sets per-frame durations, frames not covered keep theirs
+    pub fn with_durations(mut self, ds: &[f32]) -> Self {
+        for (d, v) in self.durations.iter_mut().zip(ds) {
+            *d = *v;
+        }
+        self
+    }
+
+    pub fn with_event(mut self, frame: usize, name: &str) -> Self {
+        self.events.push((frame, name.to_string()));
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AnimCondition {
+    // the clip played once has ended
+    End,
+    // set by Animator::trigger, consumed by the next update
+    Trigger(String),
+    Param(String, bool),
+}
+
+#[derive(Debug, Clone)]
+pub struct AnimTransition {
+    // clip name, "*" matches any clip
+    pub from: String,
+    pub to: String,
+    pub cond: AnimCondition,
+    // crossfade seconds
+    pub blend: f32,
+}
+
+#[derive(Clone)]
+pub struct Animator {
+    pub id: String,
+    pub asset_type: AssetType,
+    pub location: String,
+    pub clips: Vec<AnimClip>,
+    pub transitions: Vec<AnimTransition>,
+    // playback speed, 1.0 is normal
+    pub speed: f32,
+    params: HashMap<String, bool>,
+    triggers: Vec<String>,
+    current: Option<usize>,
+    pos: usize,
+    // 1 forward, -1 backward in ping-pong mode
+    dir: isize,
+    elapsed: f32,
+    finished: bool,
+    // (previous asset frame, blend seconds, seconds left)
+    blending: Option<(usize, f32, f32)>,
+    // frame or blend changed since the sprite was drawn
+    dirty: bool,
+    pending: Vec<AnimEvent>,
+}
+
+impl Animator {
+    pub fn new(id: &str, asset_type: AssetType, location: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            asset_type,
+            location: location.to_string(),
+            clips: vec![],
+            transitions: vec![],
+            speed: 1.0,
+            params: HashMap::new(),
+            triggers: vec![],
+            current: None,
+            pos: 0,
+            dir: 1,
+            elapsed: 0.0,
+            finished: false,
+            blending: None,
+            dirty: false,
+            pending: vec![],
+        }
+    }
+
+    /// adds a clip, replacing the one with the same name.
+    /// Missing durations repeat the last one, extra ones are dropped,
+    /// a clip without frames is finished as soon as it plays
+    pub fn add_clip(&mut self, mut clip: AnimClip) {
+        let last = clip.durations.last().copied().unwrap_or(0.0);
+        clip.durations.resize(clip.frames.len(), last);
+        match self.clips.iter().position(|c| c.name == clip.name) {
+            Some(i) => self.clips[i] = clip,
+            None => self.clips.push(clip),
+        }
+    }
+
+    pub fn add_transition(&mut self, from: &str, to: &str, cond: AnimCondition, blend: f32) {
+        self.transitions.push(AnimTransition {
+            from: from.to_string(),
+            to: to.to_string(),
+            cond,
+            blend,
+        });
+    }
+
+    pub fn set_speed(&mut self, s: f32) {
+        self.speed = s.max(0.0);
+    }
+
+    pub fn set_param(&mut self, name: &str, v: bool) {
+        self.params.insert(name.to_string(), v);
+    }
+
+    pub fn param(&self, name: &str) -> bool {
+        *self.params.get(name).unwrap_or(&false)
+    }
+
+    pub fn trigger(&mut self, name: &str) {
+        self.triggers.push(name.to_string());
+    }
+
+    /// starts clip name from its first frame,
+    /// a clip already playing and not finished goes on
+    pub fn play(&mut self, name: &str) {
+        self.crossfade(name, 0.0);
+    }
+
+    /// like play, fading from the current frame in secs seconds
+    pub fn crossfade(&mut self, name: &str, secs: f32) {
+        let Some(ci) = self.clips.iter().position(|c| c.name == name) else {
+            return;
+        };
+        if self.current == Some(ci) && !self.finished {
+            return;
+        }
+        let prev = self.current.map(|_| self.frame());
+        self.blending = match prev {
+            Some(p) if secs > 0.0 => Some((p, secs, secs)),
+            _ => None,
+        };
+        self.current = Some(ci);
+        self.pos = 0;
+        self.dir = 1;
+        self.elapsed = 0.0;
+        self.finished = false;
+        self.dirty = true;
+        self.push_events();
+    }
+
+    pub fn clip(&self) -> Option<&str> {
+        self.current.map(|c| self.clips[c].name.as_str())
+    }
+
+    /// frame index in the asset to draw
+    pub fn frame(&self) -> usize {
+        match self.current {
+            Some(c) => self.clips[c].frames.get(self.pos).copied().unwrap_or(0),
+            None => 0,
+        }
+    }
+
+    /// frame position in the current clip
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// previous frame and the weight of the current one, while crossfading
+    pub fn blend_from(&self) -> Option<(usize, f32)> {
+        self.blending
+            .map(|(p, total, left)| (p, 1.0 - left / total))
+    }
+
+    /// true once after the frame or the blend changed
+    pub fn take_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.dirty)
+    }
+
+    fn push_events(&mut self) {
+        let Some(c) = self.current else { return };
+        let clip = &self.clips[c];
+        for (f, name) in &clip.events {
+            if *f == self.pos {
+                self.pending.push(AnimEvent {
+                    animator: self.id.clone(),
+                    clip: clip.name.clone(),
+                    name: name.clone(),
+                    frame: self.pos,
+                });
+            }
+        }
+    }
+
+    // moves to the next frame by the play mode
+    fn step(&mut self) {
+        let Some(c) = self.current else { return };
+        let len = self.clips[c].frames.len();
+        if len == 0 {
+            self.finished = true;
+            return;
+        }
+        match self.clips[c].mode {
+            PlayMode::Loop => self.pos = (self.pos + 1) % len,
+            PlayMode::PingPong => {
+                if len > 1 {
+                    let next = self.pos as isize + self.dir;
+                    if next < 0 || next >= len as isize {
+                        self.dir = -self.dir;
+                    }
+                    self.pos = (self.pos as isize + self.dir) as usize;
+                }
+            }
+            PlayMode::Once => {
+                if self.pos + 1 >= len {
+                    self.finished = true;
+                    self.pending.push(AnimEvent {
+                        animator: self.id.clone(),
+                        clip: self.clips[c].name.clone(),
+                        name: "end".to_string(),
+                        frame: self.pos,
+                    });
+                    return;
+                }
+                self.pos += 1;
+            }
+        }
+        self.dirty = true;
+        self.push_events();
+    }
+
+    fn check(&self, cond: &AnimCondition) -> bool {
+        match cond {
+            AnimCondition::End => self.finished,
+            AnimCondition::Trigger(t) => self.triggers.contains(t),
+            AnimCondition::Param(p, v) => self.param(p) == *v,
+        }
+    }
+
+    /// advances dt seconds, runs transitions and emits the events reached
+    pub fn update(&mut self, dt: f32, bus: &mut EventBus) {
+        if let Some((p, total, left)) = self.blending {
+            let left = left - dt;
+            self.blending = if left > 0.0 {
+                Some((p, total, left))
+            } else {
+                None
+            };
+            self.dirty = true;
+        }
+        if let Some(c) = self.current {
+            self.elapsed += dt * self.speed;
+            while !self.finished {
+                // clips may be changed through the pub fields
+                let Some(&d) = self.clips[c].durations.get(self.pos) else {
+                    self.finished = true;
+                    break;
+                };
+                if d <= 0.0 || self.elapsed < d {
+                    break;
+                }
+                self.elapsed -= d;
+                self.step();
+            }
+            let name = self.clips[c].name.clone();
+            let next = self
+                .transitions
+                .iter()
+                .find(|t| (t.from == name || t.from == "*") && t.to != name && self.check(&t.cond))
+                .map(|t| (t.to.clone(), t.blend));
+            if let Some((to, blend)) = next {
+                self.crossfade(&to, blend);
+            }
+        }
+        self.triggers.clear();
+        for e in self.pending.drain(..) {
+            bus.emit(e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // idle ping-pongs over 0..=2, a jump trigger plays 10..=12 once
+    fn hero() -> (Animator, EventBus) {
+        let mut bus = EventBus::new();
+        bus.subscribe::<AnimEvent>("t");
+        let mut an = Animator::new("hero", AssetType::ImgSsf, "hero.ssf");
+        an.add_clip(AnimClip::new("idle", 0, 2, 0.1, PlayMode::PingPong));
+        an.add_clip(
+            AnimClip::new("jump", 10, 12, 0.1, PlayMode::Once)
+                .with_durations(&[0.2])
+                .with_event(1, "air"),
+        );
+        an.add_transition("*", "jump", AnimCondition::Trigger("jump".to_string()), 0.0);
+        an.add_transition("jump", "idle", AnimCondition::End, 0.2);
+        an.play("idle");
+        (an, bus)
+    }
+
+    #[test]
+    fn it_plays_ping_pong_clips() {
+        let (mut an, mut bus) = hero();
+        let mut frames = vec![];
+        for _ in 0..5 {
+            an.update(0.1, &mut bus);
+            frames.push(an.frame());
+        }
+        assert_eq!(frames, vec![1, 2, 1, 0, 1]);
+    }
+
+    #[test]
+    fn it_switches_on_triggers_and_plays_at_speed() {
+        let (mut an, mut bus) = hero();
+        an.trigger("jump");
+        an.update(0.0, &mut bus);
+        assert_eq!(an.clip(), Some("jump"));
+        // double speed, the first frame lasts 0.2s
+        an.set_speed(2.0);
+        let mut frames = vec![];
+        for _ in 0..3 {
+            an.update(0.05, &mut bus);
+            frames.push(an.frame());
+        }
+        assert_eq!(frames, vec![10, 11, 12]);
+    }
+
+    #[test]
+    fn it_fades_back_at_the_end_and_emits_events() {
+        let (mut an, mut bus) = hero();
+        an.trigger("jump");
+        an.update(0.0, &mut bus);
+        an.set_speed(2.0);
+        for _ in 0..4 {
+            an.update(0.05, &mut bus);
+        }
+        // ends and fades back to idle from frame 12
+        assert_eq!(an.clip(), Some("idle"));
+        assert_eq!(an.blend_from(), Some((12, 0.0)));
+        let names: Vec<String> = bus
+            .drain::<AnimEvent>("t")
+            .into_iter()
+            .map(|e| e.name)
+            .collect();
+        assert_eq!(names, vec!["air", "end"]);
+    }
+
+    #[test]
+    fn it_fixes_clips_built_through_their_fields() {
+        let (mut an, mut bus) = hero();
+        let mut run = AnimClip::new("run", 20, 22, 0.1, PlayMode::Loop);
+        run.durations = vec![0.1];
+        an.add_clip(run);
+        an.add_clip(AnimClip {
+            name: "none".to_string(),
+            frames: vec![],
+            durations: vec![0.1, 0.1],
+            mode: PlayMode::Loop,
+            events: vec![],
+        });
+        an.add_transition("none", "run", AnimCondition::End, 0.0);
+        an.play("none");
+        an.update(0.1, &mut bus);
+        assert_eq!(an.clip(), Some("run"));
+        let mut frames = vec![];
+        for _ in 0..4 {
+            an.update(0.1, &mut bus);
+            frames.push(an.frame());
+        }
+        assert_eq!(frames, vec![21, 22, 20, 21]);
+    }
+}
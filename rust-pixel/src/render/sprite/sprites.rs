@@ -11,6 +11,9 @@
 
 use crate::{
     asset::AssetManager,
+    event::EventBus,
+    render::camera::Camera,
+    render::palette::Palette,
     render::sprite::Sprite,
     render::panel::Frame,
     util::Point,
@@ -129,10 +132,25 @@ impl Sprites {
         }
     }
 
+    /// advances the animators of all sprites, see Sprite::animate
+    pub fn animate_all(&mut self, am: &mut AssetManager, pal: &Palette, bus: &mut EventBus, dt: f32) {
+        for s in &mut self.sprites {
+            s.animate(am, pal, bus, dt);
+        }
+    }
+
     pub fn render_all(&mut self, am: &mut AssetManager, frame: &mut Frame) {
         self.update_render_index();
         for v in &self.render_index {
             frame.render_widget(am, &mut self.sprites[v.0]);
         }
     }
+
+    /// draws all sprites through a camera, they are placed in world coordinates
+    pub fn render_all_camera(&mut self, am: &mut AssetManager, frame: &mut Frame, cam: &Camera) {
+        self.update_render_index();
+        for v in &self.render_index {
+            frame.render_camera(am, cam, &mut self.sprites[v.0]);
+        }
+    }
 }
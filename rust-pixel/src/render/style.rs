@@ -10,6 +10,7 @@
 
 #[cfg(not(any(target_os = "android", target_os = "ios", target_arch = "wasm32")))]
 use crate::render::image::io_error;
+use crate::render::palette::Palette;
 use bitflags::bitflags;
 #[cfg(not(any(target_os = "android", target_os = "ios", target_arch = "wasm32")))]
 use crossterm::{
@@ -17,6 +18,78 @@ use crossterm::{
     style::{Attribute as CAttribute, Color as CColor, SetAttribute},
 };
 use serde::{Deserialize, Serialize};
+use std::env;
+
+/// 4x4 ordered dithering thresholds, 0.0 ~ 15.0
+pub const BAYER4: [f32; 16] = [
+    0.0, 8.0, 2.0, 10.0, 12.0, 4.0, 14.0, 6.0, 3.0, 11.0, 1.0, 9.0, 15.0, 7.0, 13.0, 5.0,
+];
+
+// named colors in the order of the first 16 palette entries,
+// 16 color terminals only understand these
+const ANSI16: [Color; 16] = [
+    Color::Black,
+    Color::Red,
+    Color::Green,
+    Color::Yellow,
+    Color::Blue,
+    Color::Magenta,
+    Color::Cyan,
+    Color::Gray,
+    Color::DarkGray,
+    Color::LightRed,
+    Color::LightGreen,
+    Color::LightYellow,
+    Color::LightBlue,
+    Color::LightMagenta,
+    Color::LightCyan,
+    Color::White,
+];
+
+/// colors a terminal can show
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorDepth {
+    TrueColor,
+    Ansi256,
+    Ansi16,
+}
+
+impl ColorDepth {
+    /// guesses the depth of the terminal from COLORTERM and TERM
+    pub fn detect() -> Self {
+        Self::from_env(
+            &env::var("COLORTERM").unwrap_or_default(),
+            &env::var("TERM").unwrap_or_default(),
+        )
+    }
+
+    pub fn from_env(colorterm: &str, term: &str) -> Self {
+        let ct = colorterm.to_lowercase();
+        let term = term.to_lowercase();
+        if ct.contains("truecolor") || ct.contains("24bit") || term.contains("direct") {
+            ColorDepth::TrueColor
+        } else if term.contains("256") {
+            ColorDepth::Ansi256
+        } else if matches!(term.as_str(), "" | "dumb" | "linux" | "vt100" | "ansi")
+            || term.starts_with("vt")
+        {
+            ColorDepth::Ansi16
+        } else {
+            ColorDepth::Ansi256
+        }
+    }
+}
+
+// index of the palette entry in range nearest to rgb
+fn nearest(rgb: (u8, u8, u8), range: std::ops::Range<usize>) -> usize {
+    let dist = |c: &[u8; 3]| {
+        let dr = c[0] as i32 - rgb.0 as i32;
+        let dg = c[1] as i32 - rgb.1 as i32;
+        let db = c[2] as i32 - rgb.2 as i32;
+        dr * dr + dg * dg + db * db
+    };
+    range.min_by_key(|i| dist(&COLOR_RGB[*i])).unwrap()
+}
 
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
@@ -68,6 +141,83 @@ impl Color {
         };
         (COLOR_RGB[cidx][0], COLOR_RGB[cidx][1], COLOR_RGB[cidx][2])
     }
+
+    /// scales the color towards black, level 0.0 keeps it, 1.0 gives black.
+    /// Indexed colors are taken from pal
+    pub fn darken_in(self, pal: &Palette, level: f32) -> Color {
+        let k = 1.0 - level.clamp(0.0, 1.0);
+        let (r, g, b) = pal.rgb(self);
+        Color::Rgb(
+            (r as f32 * k) as u8,
+            (g as f32 * k) as u8,
+            (b as f32 * k) as u8,
+        )
+    }
+
+    /// draws self over dst with the blend mode and opacity in [0, 1].
+    /// Indexed colors are taken from pal
+    pub fn blend_in(self, pal: &Palette, dst: Color, mode: BlendMode, opacity: f32) -> Color {
+        let a = opacity.clamp(0.0, 1.0);
+        let s = pal.rgb(self);
+        let d = pal.rgb(dst);
+        let mix = |s: u8, d: u8| -> u8 {
+            let (s, d) = (s as f32, d as f32);
+            let v = match mode {
+                BlendMode::Normal => s * a + d * (1.0 - a),
+                BlendMode::Additive => d + s * a,
+                BlendMode::Multiply => d * (1.0 - a + a * s / 255.0),
+            };
+            v.round().clamp(0.0, 255.0) as u8
+        };
+        Color::Rgb(mix(s.0, d.0), mix(s.1, d.1), mix(s.2, d.2))
+    }
+
+    /// nearest color of the 256 color palette, for terminals without true color.
+    /// The first 16 colors depend on the terminal theme, so they are skipped
+    pub fn to_indexed(self) -> Color {
+        match self {
+            Color::Rgb(r, g, b) => Color::Indexed(nearest((r, g, b), 16..256) as u8),
+            c => c,
+        }
+    }
+
+    /// the color to send to a terminal of depth.
+    /// With dither an ordered dithering offset by the cell position (x, y)
+    /// is added before picking the nearest palette color
+    pub fn quantize(self, depth: ColorDepth, x: u16, y: u16, dither: bool) -> Color {
+        let rgb = |c: Color, step: f32| {
+            let (r, g, b) = c.get_rgb();
+            if !dither {
+                return (r, g, b);
+            }
+            let d = ((BAYER4[(y % 4 * 4 + x % 4) as usize] + 0.5) / 16.0 - 0.5) * step;
+            let f = |v: u8| (v as f32 + d).round().clamp(0.0, 255.0) as u8;
+            (f(r), f(g), f(b))
+        };
+        match (depth, self) {
+            (ColorDepth::TrueColor, c) => c,
+            (ColorDepth::Ansi256, Color::Rgb(..)) => {
+                Color::Indexed(nearest(rgb(self, 40.0), 16..256) as u8)
+            }
+            (ColorDepth::Ansi256, c) => c,
+            (ColorDepth::Ansi16, Color::Indexed(i)) if i < 16 => ANSI16[i as usize],
+            (ColorDepth::Ansi16, Color::Rgb(..) | Color::Indexed(_)) => {
+                ANSI16[nearest(rgb(self, 128.0), 0..16)]
+            }
+            (ColorDepth::Ansi16, c) => c,
+        }
+    }
+}
+
+/// how a sprite is composited over the sprites drawn below it
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BlendMode {
+    #[default]
+    Normal,
+    /// adds its colors, for light, fire or glow
+    Additive,
+    /// multiplies its colors, for shadows or tinting
+    Multiply,
 }
 
 bitflags! {
@@ -570,4 +720,66 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn it_blends_colors() {
+        let pal = Palette::xterm();
+        let red = Color::Rgb(200, 0, 0);
+        let blue = Color::Rgb(0, 0, 100);
+        assert_eq!(red.blend_in(&pal, blue, BlendMode::Normal, 1.0), red);
+        assert_eq!(red.blend_in(&pal, blue, BlendMode::Normal, 0.5), Color::Rgb(100, 0, 50));
+        assert_eq!(red.blend_in(&pal, blue, BlendMode::Additive, 1.0), Color::Rgb(200, 0, 100));
+        assert_eq!(
+            Color::Rgb(255, 128, 0).blend_in(&pal, Color::Rgb(100, 100, 100), BlendMode::Multiply, 1.0),
+            Color::Rgb(100, 50, 0)
+        );
+        assert_eq!(Color::Rgb(255, 0, 0).to_indexed(), Color::Indexed(196));
+        assert_eq!(Color::Indexed(3).to_indexed(), Color::Indexed(3));
+    }
+
+    #[test]
+    fn it_takes_indexed_colors_from_the_palette() {
+        let pal = Palette::by_name("gameboy").unwrap();
+        let (r, g, b) = pal.index(1);
+        let half = Color::Rgb(r / 2, g / 2, b / 2);
+        assert_eq!(Color::Indexed(1).darken_in(&pal, 0.5), half);
+        assert_ne!(Color::Indexed(1).darken_in(&Palette::xterm(), 0.5), half);
+        assert_eq!(
+            Color::Indexed(1).blend_in(&pal, Color::Black, BlendMode::Normal, 1.0),
+            Color::Rgb(r, g, b)
+        );
+    }
+
+    #[test]
+    fn it_detects_color_depth() {
+        assert_eq!(ColorDepth::from_env("truecolor", "xterm"), ColorDepth::TrueColor);
+        assert_eq!(ColorDepth::from_env("", "xterm-256color"), ColorDepth::Ansi256);
+        assert_eq!(ColorDepth::from_env("", "linux"), ColorDepth::Ansi16);
+    }
+
+    #[test]
+    fn it_quantizes_colors_to_the_depth() {
+        let orange = Color::Rgb(250, 130, 10);
+        assert_eq!(orange.quantize(ColorDepth::TrueColor, 0, 0, false), orange);
+        assert_eq!(orange.quantize(ColorDepth::Ansi256, 0, 0, false), Color::Indexed(208));
+        assert_eq!(
+            Color::Rgb(200, 20, 20).quantize(ColorDepth::Ansi16, 0, 0, false),
+            Color::LightRed
+        );
+        assert_eq!(Color::Indexed(4).quantize(ColorDepth::Ansi16, 0, 0, false), Color::Blue);
+    }
+
+    #[test]
+    fn it_dithers_when_quantizing() {
+        // a mid gray dithers to different entries over a 4x4 block
+        let gray = Color::Rgb(160, 160, 160);
+        let mut seen = vec![];
+        for i in 0..16 {
+            let c = gray.quantize(ColorDepth::Ansi16, i % 4, i / 4, true);
+            if !seen.contains(&c) {
+                seen.push(c);
+            }
+        }
+        assert!(seen.len() > 1);
+    }
 }
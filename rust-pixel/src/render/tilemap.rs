@@ -0,0 +1,413 @@
+// RustPixel
+// copyright zhouxin@tuyoogame.com 2022~2024
+
+//! Tilemap stores a level as layers of tile ids and draws them into a Buffer.
+//!
+//! A tile id refers to a TileDef in the tileset: symbol index and texture
+//! in graphical mode (see cellsym), a text symbol for terminal mode, colors,
+//! collision flags and free form properties. Id 0 is an empty tile.
+//! Layers are drawn bottom to top, the top non-empty tile of a cell wins.
+//!
+//! Layers are stored in chunks of CHUNK_SIZE x CHUNK_SIZE tiles, allocated
+//! when first written, so big sparse maps stay small.
+//! Changing a tile marks its chunk dirty, draw only redraws dirty chunks,
+//! so drawing into a sprite that keeps its content is cheap.
+//!
+//! Maps made with the Tiled editor (https://www.mapeditor.org) are imported
+//! by from_tiled_json and from_tmx. A Tiled tile id becomes
+//! tex * 256 + sym, so a tileset image laid out like assets/pix/c64.png
+//! maps directly to the symbols. Tile properties "solid", "hazard" and
+//! "trigger" (bool) set flags, "fg" (color index) and "text" set the colors
+//! and the terminal symbol, others are kept in properties.
+//! Tilesets must be embedded in the map, external tilesets are an error.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let mut tm = Tilemap::from_tiled_json(&json)?;
+//! tm.draw(&mut self.map_sprite.content);
+//! if tm.flags_at(x, y).contains(TileFlags::SOLID) { ... }
+//! ```
+
+#[cfg(any(feature = "sdl", target_arch = "wasm32"))]
+use crate::render::cell::cellsym;
+use crate::render::{buffer::Buffer, style::Color};
+use bitflags::bitflags;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+mod tiled;
+
+pub const CHUNK_SIZE: u16 = 16;
+
+bitflags! {
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct TileFlags: u8 {
+        const SOLID   = 0b0000_0001;
+        const HAZARD  = 0b0000_0010;
+        const TRIGGER = 0b0000_0100;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TileDef {
+    // symbol index and texture in graphical mode
+    pub sym: u8,
+    pub tex: u8,
+    // symbol in text mode
+    pub text: String,
+    pub fg: Color,
+    // background in text mode
+    pub bg: Color,
+    pub flags: TileFlags,
+    pub properties: HashMap<String, String>,
+}
+
+impl TileDef {
+    pub fn new(sym: u8, tex: u8, text: &str, fg: Color) -> Self {
+        Self {
+            sym,
+            tex,
+            text: text.to_string(),
+            fg,
+            bg: Color::Reset,
+            flags: TileFlags::empty(),
+            properties: HashMap::new(),
+        }
+    }
+
+    pub fn with_flags(mut self, flags: TileFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TileLayer {
+    pub name: String,
+    pub visible: bool,
+    // chunk rows of the map, None until a tile is set in it
+    chunks: Vec<Option<Vec<u32>>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tilemap {
+    // size in tiles
+    pub width: u16,
+    pub height: u16,
+    pub tileset: HashMap<u32, TileDef>,
+    pub layers: Vec<TileLayer>,
+    chunks_w: u16,
+    dirty: Vec<bool>,
+}
+
+impl Tilemap {
+    pub fn new(width: u16, height: u16) -> Self {
+        let chunks_w = width.div_ceil(CHUNK_SIZE);
+        let chunks_h = height.div_ceil(CHUNK_SIZE);
+        Self {
+            width,
+            height,
+            tileset: HashMap::new(),
+            layers: vec![],
+            chunks_w,
+            dirty: vec![true; chunks_w as usize * chunks_h as usize],
+        }
+    }
+
+    pub fn add_tile(&mut self, id: u32, def: TileDef) {
+        self.tileset.insert(id, def);
+        self.mark_all_dirty();
+    }
+
+    /// adds an empty layer on top, returns its index
+    pub fn add_layer(&mut self, name: &str) -> usize {
+        self.layers.push(TileLayer {
+            name: name.to_string(),
+            visible: true,
+            chunks: vec![None; self.dirty.len()],
+        });
+        self.layers.len() - 1
+    }
+
+    pub fn layer_index(&self, name: &str) -> Option<usize> {
+        self.layers.iter().position(|l| l.name == name)
+    }
+
+    pub fn set_layer_visible(&mut self, layer: usize, visible: bool) {
+        self.layers[layer].visible = visible;
+        self.mark_all_dirty();
+    }
+
+    // chunk index and index in chunk
+    fn locate(&self, x: u16, y: u16) -> Option<(usize, usize)> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        let c = (y / CHUNK_SIZE) as usize * self.chunks_w as usize + (x / CHUNK_SIZE) as usize;
+        let i = (y % CHUNK_SIZE) as usize * CHUNK_SIZE as usize + (x % CHUNK_SIZE) as usize;
+        Some((c, i))
+    }
+
+    /// tile id at (x, y), 0 if empty or outside the map
+    pub fn get(&self, layer: usize, x: u16, y: u16) -> u32 {
+        match self.locate(x, y) {
+            Some((c, i)) => self.layers[layer].chunks[c].as_ref().map_or(0, |ch| ch[i]),
+            None => 0,
+        }
+    }
+
+    pub fn set(&mut self, layer: usize, x: u16, y: u16, id: u32) {
+        if let Some((c, i)) = self.locate(x, y) {
+            let ch = &mut self.layers[layer].chunks[c];
+            if ch.is_none() {
+                if id == 0 {
+                    return;
+                }
+                *ch = Some(vec![0; CHUNK_SIZE as usize * CHUNK_SIZE as usize]);
+            }
+            let ch = ch.as_mut().unwrap();
+            if ch[i] != id {
+                ch[i] = id;
+                self.dirty[c] = true;
+            }
+        }
+    }
+
+    pub fn fill(&mut self, layer: usize, x: u16, y: u16, w: u16, h: u16, id: u32) {
+        for ty in y..y.saturating_add(h) {
+            for tx in x..x.saturating_add(w) {
+                self.set(layer, tx, ty, id);
+            }
+        }
+    }
+
+    /// top visible tile of all layers at (x, y),
+    /// ids missing in the tileset are skipped like empty tiles
+    pub fn top_tile(&self, x: u16, y: u16) -> Option<&TileDef> {
+        self.layers
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(|(_, l)| l.visible)
+            .find_map(|(li, _)| self.tileset.get(&self.get(li, x, y)))
+    }
+
+    /// flags of all layers at (x, y), outside the map is solid
+    pub fn flags_at(&self, x: u16, y: u16) -> TileFlags {
+        if x >= self.width || y >= self.height {
+            return TileFlags::SOLID;
+        }
+        let mut f = TileFlags::empty();
+        for li in 0..self.layers.len() {
+            if let Some(t) = self.tileset.get(&self.get(li, x, y)) {
+                f |= t.flags;
+            }
+        }
+        f
+    }
+
+    pub fn is_solid(&self, x: u16, y: u16) -> bool {
+        self.flags_at(x, y).contains(TileFlags::SOLID)
+    }
+
+    /// property of the top tile having it at (x, y)
+    pub fn property(&self, x: u16, y: u16, key: &str) -> Option<&str> {
+        (0..self.layers.len())
+            .rev()
+            .filter_map(|li| self.tileset.get(&self.get(li, x, y)))
+            .find_map(|t| t.properties.get(key).map(|v| v.as_str()))
+    }
+
+    pub fn mark_all_dirty(&mut self) {
+        self.dirty.iter_mut().for_each(|d| *d = true);
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty.iter().any(|d| *d)
+    }
+
+    /// redraws dirty chunks into buf, map tile (0, 0) goes to the top-left
+    /// of buf, the part outside buf is skipped. Returns the chunks drawn
+    pub fn draw(&mut self, buf: &mut Buffer) -> usize {
+        let mut n = 0;
+        for c in 0..self.dirty.len() {
+            if !self.dirty[c] {
+                continue;
+            }
+            self.dirty[c] = false;
+            n += 1;
+            let x0 = (c % self.chunks_w as usize) as u16 * CHUNK_SIZE;
+            let y0 = (c / self.chunks_w as usize) as u16 * CHUNK_SIZE;
+            let x1 = x0.saturating_add(CHUNK_SIZE).min(self.width).min(buf.area.width);
+            let y1 = y0.saturating_add(CHUNK_SIZE).min(self.height).min(buf.area.height);
+            for y in y0..y1 {
+                for x in x0..x1 {
+                    let i = y as usize * buf.area.width as usize + x as usize;
+                    let cell = &mut buf.content[i];
+                    match self.top_tile(x, y) {
+                        None => cell.reset(),
+                        Some(t) => {
+                            #[cfg(any(feature = "sdl", target_arch = "wasm32"))]
+                            {
+                                cell.set_symbol(cellsym(t.sym));
+                                cell.set_fg(t.fg);
+                                cell.set_bg(Color::Indexed(t.tex));
+                            }
+                            #[cfg(not(any(feature = "sdl", target_arch = "wasm32")))]
+                            {
+                                cell.set_symbol(&t.text);
+                                cell.set_fg(t.fg);
+                                cell.set_bg(t.bg);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        n
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::Rect;
+
+    // ground of "." tiles, one solid "#" wall at (20, 5)
+    fn level() -> (Tilemap, usize) {
+        let mut tm = Tilemap::new(40, 20);
+        tm.add_tile(1, TileDef::new(160, 1, ".", Color::Indexed(8)));
+        tm.add_tile(
+            2,
+            TileDef::new(102, 1, "#", Color::Indexed(15)).with_flags(TileFlags::SOLID),
+        );
+        let ground = tm.add_layer("ground");
+        let walls = tm.add_layer("walls");
+        tm.fill(ground, 0, 0, 40, 20, 1);
+        tm.set(walls, 20, 5, 2);
+        (tm, walls)
+    }
+
+    #[test]
+    fn it_stores_layers_and_flags() {
+        let (tm, walls) = level();
+        assert_eq!(tm.get(walls, 20, 5), 2);
+        assert!(tm.is_solid(20, 5) && !tm.is_solid(19, 5) && tm.is_solid(40, 0));
+    }
+
+    #[test]
+    fn it_redraws_dirty_chunks() {
+        let (mut tm, walls) = level();
+        let mut buf = Buffer::empty(Rect::new(0, 0, 40, 20));
+        assert_eq!(tm.draw(&mut buf), 6);
+        assert_eq!(tm.draw(&mut buf), 0);
+        tm.set(walls, 20, 5, 0);
+        tm.set(walls, 21, 5, 2);
+        assert_eq!(tm.draw(&mut buf), 1);
+        #[cfg(not(any(feature = "sdl", target_arch = "wasm32")))]
+        {
+            assert_eq!(buf.get(20, 5).symbol, ".");
+            assert_eq!(buf.get(21, 5).symbol, "#");
+        }
+    }
+
+    #[test]
+    fn it_draws_chunks_at_the_end_of_the_coordinate_range() {
+        let mut tm = Tilemap::new(u16::MAX, 1);
+        tm.add_tile(1, TileDef::new(160, 1, ".", Color::Indexed(8)));
+        let l = tm.add_layer("ground");
+        let mut buf = Buffer::empty(Rect::new(0, 0, 40, 1));
+        assert_eq!(tm.draw(&mut buf), 4096);
+        tm.set(l, u16::MAX - 1, 0, 1);
+        assert_eq!(tm.draw(&mut buf), 1);
+    }
+
+    #[test]
+    fn it_shows_layers_below_hidden_layers_and_unknown_tiles() {
+        let (mut tm, walls) = level();
+        tm.set_layer_visible(walls, false);
+        assert_eq!(tm.top_tile(20, 5).unwrap().text, ".");
+        tm.set_layer_visible(walls, true);
+        tm.set(walls, 20, 5, 99);
+        assert_eq!(tm.top_tile(20, 5).unwrap().text, ".");
+    }
+
+    const TILED_JSON: &str = r##"{"width":3,"height":2,"tilewidth":16,"tileheight":16,
+        "layers":[{"type":"tilelayer","name":"ground","width":3,"height":2,
+                   "visible":true,"data":[1,2,1,0,257,1]}],
+        "tilesets":[{"firstgid":1,"tiles":[{"id":1,"properties":[
+            {"name":"solid","type":"bool","value":true},
+            {"name":"text","type":"string","value":"#"},
+            {"name":"door","type":"string","value":"exit"}]}]}]}"##;
+
+    const TILED_TMX: &str = r##"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.10" orientation="orthogonal" width="3" height="2" tilewidth="16" tileheight="16">
+ <tileset firstgid="1" name="c64" tilewidth="16" tileheight="16" tilecount="1024" columns="16">
+  <tile id="1">
+   <properties>
+    <property name="solid" type="bool" value="true"/>
+    <property name="text" value="#"/>
+    <property name="door" value="exit"/>
+   </properties>
+  </tile>
+ </tileset>
+ <layer id="1" name="ground" width="3" height="2">
+  <data encoding="csv">
+1,2,1,
+0,257,1
+</data>
+ </layer>
+</map>"##;
+
+    fn check_imported(tm: &Tilemap) {
+        assert_eq!((tm.width, tm.height), (3, 2));
+        assert_eq!(tm.get(0, 1, 0), 2);
+        assert!(tm.is_solid(1, 0) && !tm.is_solid(0, 0));
+        assert_eq!(tm.property(1, 0, "door"), Some("exit"));
+        let t = tm.top_tile(1, 1).unwrap();
+        assert_eq!((t.sym, t.tex), (0, 1));
+        assert_eq!(tm.top_tile(1, 0).unwrap().text, "#");
+        assert!(tm.top_tile(0, 1).is_none());
+    }
+
+    #[test]
+    fn it_imports_tiled_json() {
+        check_imported(&Tilemap::from_tiled_json(TILED_JSON).unwrap());
+        assert!(Tilemap::from_tiled_json("{}").is_err());
+    }
+
+    #[test]
+    fn it_imports_tmx() {
+        check_imported(&Tilemap::from_tmx(TILED_TMX).unwrap());
+    }
+
+    #[test]
+    fn it_keeps_tmx_properties_to_their_tile() {
+        // tile 0 has no properties, the solid property belongs to the layer
+        let tmx = TILED_TMX
+            .replace("</tile>", "</tile>\n  <tile id=\"0\"/>")
+            .replace(
+                "<data ",
+                r#"<properties><property name="solid" value="true"/></properties><data "#,
+            );
+        let tm = Tilemap::from_tmx(&tmx).unwrap();
+        check_imported(&tm);
+        assert!(!tm.is_solid(0, 0));
+    }
+
+    #[test]
+    fn it_unescapes_tmx_attributes() {
+        let tmx = TILED_TMX.replace(r#"value="exit""#, r#"value="a &amp; &quot;b&quot; &lt;""#);
+        let tm = Tilemap::from_tmx(&tmx).unwrap();
+        assert_eq!(tm.property(1, 0, "door"), Some(r#"a & "b" <"#));
+    }
+
+    #[test]
+    fn it_rejects_external_tilesets() {
+        let json = TILED_JSON.replace(r#"{"firstgid":1,"#, r#"{"firstgid":1,"source":"c64.tsj","#);
+        assert!(Tilemap::from_tiled_json(&json).unwrap_err().contains("c64.tsj"));
+        let tmx = TILED_TMX.replace(r#"name="c64""#, r#"source="c64.tsx""#);
+        assert!(Tilemap::from_tmx(&tmx).unwrap_err().contains("c64.tsx"));
+    }
+}
@@ -0,0 +1,233 @@
+// RustPixel
+// copyright zhouxin@tuyoogame.com 2022~2024
+
+//! Imports orthogonal Tiled maps, saved as JSON (.tmj/.json) or TMX.
+//! Only tile layers are imported, TMX layer data must use csv encoding,
+//! the default of Tiled. Flip bits of tile ids are ignored.
+//! Tilesets saved in their own file (.tsx/.tsj) are not loaded,
+//! importing a map using one fails, embed the tileset in the map instead.
+
+use super::{TileDef, TileFlags, Tilemap};
+use crate::render::style::Color;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde_json::Value;
+use std::collections::HashMap;
+
+// tile ids of Tiled keep flip flags in the high bits
+const TILED_FLIP_MASK: u32 = 0x1fff_ffff;
+
+lazy_static! {
+    static ref TAG_RE: Regex = Regex::new(r"<(/?)([A-Za-z_]+)([^>]*?)(/?)>").unwrap();
+    static ref ATTR_RE: Regex = Regex::new(r#"([A-Za-z_]+)="([^"]*)""#).unwrap();
+}
+
+// a map read from a Tiled file, before tiles are resolved
+#[derive(Default)]
+struct TiledMap {
+    width: u16,
+    height: u16,
+    // (name, visible, global tile ids row by row)
+    layers: Vec<(String, bool, Vec<u32>)>,
+    // global tile id -> properties
+    properties: HashMap<u32, Vec<(String, String)>>,
+    // firstgid of each tileset
+    firsts: Vec<u32>,
+}
+
+impl TiledMap {
+    fn into_tilemap(self) -> Result<Tilemap, String> {
+        let mut tm = Tilemap::new(self.width, self.height);
+        for (name, visible, data) in &self.layers {
+            if data.len() != self.width as usize * self.height as usize {
+                return Err(format!("tiled: layer {} has {} tiles", name, data.len()));
+            }
+            let li = tm.add_layer(name);
+            tm.layers[li].visible = *visible;
+            for (i, gid) in data.iter().enumerate() {
+                let id = gid & TILED_FLIP_MASK;
+                if id == 0 {
+                    continue;
+                }
+                tm.tileset.entry(id).or_insert_with(|| {
+                    let first = self.firsts.iter().filter(|f| **f <= id).max().unwrap_or(&1);
+                    tile_def(id - first, self.properties.get(&id))
+                });
+                let x = (i % self.width as usize) as u16;
+                let y = (i / self.width as usize) as u16;
+                tm.set(li, x, y, id);
+            }
+        }
+        Ok(tm)
+    }
+}
+
+// local id of a tile in its tileset = tex * 256 + sym
+fn tile_def(local: u32, props: Option<&Vec<(String, String)>>) -> TileDef {
+    let mut t = TileDef::new(
+        (local % 256) as u8,
+        (local / 256) as u8,
+        "█",
+        Color::Indexed(15),
+    );
+    for (k, v) in props.into_iter().flatten() {
+        let on = v == "true";
+        match k.as_str() {
+            "solid" => t.flags.set(TileFlags::SOLID, on),
+            "hazard" => t.flags.set(TileFlags::HAZARD, on),
+            "trigger" => t.flags.set(TileFlags::TRIGGER, on),
+            "text" => t.text = v.clone(),
+            "fg" => {
+                if let Ok(c) = v.parse::<u8>() {
+                    t.fg = Color::Indexed(c);
+                }
+            }
+            "bg" => {
+                if let Ok(c) = v.parse::<u8>() {
+                    t.bg = Color::Indexed(c);
+                }
+            }
+            _ => {
+                t.properties.insert(k.clone(), v.clone());
+            }
+        }
+    }
+    t
+}
+
+// attribute values of TMX files escape the xml special chars
+fn xml_unescape(v: &str) -> String {
+    v.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+fn external_tileset(source: &str) -> String {
+    format!("tiled: external tileset {} is not supported, embed it in the map", source)
+}
+
+fn json_u16(v: &Value, key: &str) -> Result<u16, String> {
+    v[key]
+        .as_u64()
+        .map(|n| n as u16)
+        .ok_or_else(|| format!("tiled: missing {}", key))
+}
+
+impl Tilemap {
+    /// imports a map saved by Tiled in JSON format
+    pub fn from_tiled_json(s: &str) -> Result<Self, String> {
+        let v: Value = serde_json::from_str(s).map_err(|e| format!("tiled: {}", e))?;
+        let mut m = TiledMap {
+            width: json_u16(&v, "width")?,
+            height: json_u16(&v, "height")?,
+            ..Default::default()
+        };
+        for ts in v["tilesets"].as_array().into_iter().flatten() {
+            if let Some(src) = ts["source"].as_str() {
+                return Err(external_tileset(src));
+            }
+            let first = ts["firstgid"].as_u64().unwrap_or(1) as u32;
+            m.firsts.push(first);
+            for t in ts["tiles"].as_array().into_iter().flatten() {
+                let id = first + t["id"].as_u64().unwrap_or(0) as u32;
+                let ps = m.properties.entry(id).or_default();
+                for p in t["properties"].as_array().into_iter().flatten() {
+                    let name = p["name"].as_str().unwrap_or("").to_string();
+                    let value = match &p["value"] {
+                        Value::String(s) => s.clone(),
+                        other => other.to_string(),
+                    };
+                    ps.push((name, value));
+                }
+            }
+        }
+        for l in v["layers"].as_array().into_iter().flatten() {
+            if l["type"] != "tilelayer" {
+                continue;
+            }
+            let data = l["data"]
+                .as_array()
+                .ok_or("tiled: layer data must be an array, set the format to csv")?
+                .iter()
+                .map(|d| d.as_u64().unwrap_or(0) as u32)
+                .collect();
+            m.layers.push((
+                l["name"].as_str().unwrap_or("").to_string(),
+                l["visible"].as_bool().unwrap_or(true),
+                data,
+            ));
+        }
+        m.into_tilemap()
+    }
+
+    /// imports a map saved by Tiled in TMX format
+    pub fn from_tmx(s: &str) -> Result<Self, String> {
+        let mut m = TiledMap::default();
+        let mut first = 1u32;
+        let mut tile: Option<u32> = None;
+        let mut layer: Option<(String, bool)> = None;
+        let mut data_start: Option<usize> = None;
+        for cap in TAG_RE.captures_iter(s) {
+            let closing = &cap[1] == "/";
+            let name = &cap[2];
+            let empty = &cap[4] == "/";
+            let attrs: HashMap<&str, String> = ATTR_RE
+                .captures_iter(cap.get(3).unwrap().as_str())
+                .map(|a| (a.get(1).unwrap().as_str(), xml_unescape(&a[2])))
+                .collect();
+            let attr = |k: &str| attrs.get(k).map_or("", |v| v.as_str());
+            let num = |k: &str| attrs.get(k).and_then(|v| v.parse::<u32>().ok());
+            match (name, closing) {
+                ("map", false) => {
+                    m.width = num("width").ok_or("tiled: missing width")? as u16;
+                    m.height = num("height").ok_or("tiled: missing height")? as u16;
+                }
+                ("tileset", false) => {
+                    if let Some(src) = attrs.get("source") {
+                        return Err(external_tileset(src));
+                    }
+                    first = num("firstgid").unwrap_or(1);
+                    m.firsts.push(first);
+                }
+                // <tile id=".."/> has no properties
+                ("tile", false) if !empty => tile = num("id").map(|id| first + id),
+                ("tile", true) | ("tileset", true) => tile = None,
+                ("property", false) => {
+                    if let Some(id) = tile {
+                        m.properties
+                            .entry(id)
+                            .or_default()
+                            .push((attr("name").to_string(), attr("value").to_string()));
+                    }
+                }
+                ("layer", false) => {
+                    tile = None;
+                    layer = Some((attr("name").to_string(), attr("visible") != "0"))
+                }
+                ("data", false) => {
+                    if attr("encoding") != "csv" {
+                        return Err("tiled: only csv layer data is supported".to_string());
+                    }
+                    data_start = Some(cap.get(0).unwrap().end());
+                }
+                ("data", true) => {
+                    let start = data_start.take().ok_or("tiled: bad data tag")?;
+                    let (name, visible) = layer.take().ok_or("tiled: data outside layer")?;
+                    let data = s[start..cap.get(0).unwrap().start()]
+                        .split(',')
+                        .map(|n| {
+                            n.trim()
+                                .parse::<u32>()
+                                .map_err(|_| format!("tiled: bad tile {}", n))
+                        })
+                        .collect::<Result<Vec<u32>, String>>()?;
+                    m.layers.push((name, visible, data));
+                }
+                _ => {}
+            }
+        }
+        m.into_tilemap()
+    }
+}
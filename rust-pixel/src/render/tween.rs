@@ -0,0 +1,498 @@
+// RustPixel
+// copyright zhouxin@tuyoogame.com 2022~2024
+
+//! Tweens animate sprite properties over time with easing curves.
+//!
+//! A tween moves one property of a Sprite (position, angle, fg color or
+//! opacity) from its value when the tween starts, or an explicit from value,
+//! to a target value. It can wait a delay first, repeat, and play back and
+//! forth (yoyo). Tweens are composed into sequences and parallel groups.
+//!
+//! Tweener runs named tweens on the sprites of a Sprites set by tag,
+//! and emits a TweenEvent through context.events when one completes.
+//! A single Tween can also be driven directly with Tween::update.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let drop = Tween::seq(vec![
+//!     Tween::to(TweenValue::Pos(10.0, 20.0), 0.4, Ease::BounceOut),
+//!     Tween::wait(0.1),
+//!     Tween::par(vec![
+//!         Tween::to(TweenValue::Fg(255, 255, 0), 0.2, Ease::QuadOut).yoyo().repeat(1),
+//!         Tween::to(TweenValue::Angle(90.0), 0.4, Ease::BackOut),
+//!     ]),
+//! ]);
+//! self.tweens.add("drop", "cell12", drop);
+//!
+//! // update
+//! self.tweens.update(&mut self.sprites, &mut ctx.events, dt);
+//! for e in ctx.events.drain::<TweenEvent>("render") { ... }
+//! ```
+
+use crate::{
+    event::EventBus,
+    render::sprite::{Sprite, Sprites},
+    render::style::Color,
+};
+use std::f32::consts::PI;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ease {
+    Linear,
+    QuadIn,
+    QuadOut,
+    QuadInOut,
+    CubicIn,
+    CubicOut,
+    CubicInOut,
+    ElasticIn,
+    ElasticOut,
+    ElasticInOut,
+    BounceIn,
+    BounceOut,
+    BounceInOut,
+    BackIn,
+    BackOut,
+    BackInOut,
+}
+
+fn bounce_out(t: f32) -> f32 {
+    let (n, d) = (7.5625, 2.75);
+    if t < 1.0 / d {
+        n * t * t
+    } else if t < 2.0 / d {
+        let t = t - 1.5 / d;
+        n * t * t + 0.75
+    } else if t < 2.5 / d {
+        let t = t - 2.25 / d;
+        n * t * t + 0.9375
+    } else {
+        let t = t - 2.625 / d;
+        n * t * t + 0.984375
+    }
+}
+
+impl Ease {
+    /// maps progress t in 0.0..=1.0 to the eased progress,
+    /// elastic and back curves overshoot below 0.0 or above 1.0
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        // back overshoot
+        let (c1, c2, c3) = (1.70158, 1.70158 * 1.525, 1.70158 + 1.0);
+        let c4 = 2.0 * PI / 3.0;
+        let c5 = 2.0 * PI / 4.5;
+        match self {
+            Ease::Linear => t,
+            Ease::QuadIn => t * t,
+            Ease::QuadOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Ease::QuadInOut => {
+                if t < 0.5 {
+                    2.0 * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(2) / 2.0
+                }
+            }
+            Ease::CubicIn => t * t * t,
+            Ease::CubicOut => 1.0 - (1.0 - t).powi(3),
+            Ease::CubicInOut => {
+                if t < 0.5 {
+                    4.0 * t * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
+                }
+            }
+            _ if t == 0.0 || t == 1.0 => t,
+            Ease::ElasticIn => -(2f32.powf(10.0 * t - 10.0)) * ((t * 10.0 - 10.75) * c4).sin(),
+            Ease::ElasticOut => 2f32.powf(-10.0 * t) * ((t * 10.0 - 0.75) * c4).sin() + 1.0,
+            Ease::ElasticInOut => {
+                if t < 0.5 {
+                    -(2f32.powf(20.0 * t - 10.0) * ((20.0 * t - 11.125) * c5).sin()) / 2.0
+                } else {
+                    2f32.powf(-20.0 * t + 10.0) * ((20.0 * t - 11.125) * c5).sin() / 2.0 + 1.0
+                }
+            }
+            Ease::BounceIn => 1.0 - bounce_out(1.0 - t),
+            Ease::BounceOut => bounce_out(t),
+            Ease::BounceInOut => {
+                if t < 0.5 {
+                    (1.0 - bounce_out(1.0 - 2.0 * t)) / 2.0
+                } else {
+                    (1.0 + bounce_out(2.0 * t - 1.0)) / 2.0
+                }
+            }
+            Ease::BackIn => c3 * t * t * t - c1 * t * t,
+            Ease::BackOut => 1.0 + c3 * (t - 1.0).powi(3) + c1 * (t - 1.0).powi(2),
+            Ease::BackInOut => {
+                if t < 0.5 {
+                    (2.0 * t).powi(2) * ((c2 + 1.0) * 2.0 * t - c2) / 2.0
+                } else {
+                    ((2.0 * t - 2.0).powi(2) * ((c2 + 1.0) * (t * 2.0 - 2.0) + c2) + 2.0) / 2.0
+                }
+            }
+        }
+    }
+}
+
+/// a sprite property and its value
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TweenValue {
+    // sprite position, world_pos if the sprite has one
+    Pos(f32, f32),
+    Angle(f64),
+    // fg color of all non blank cells
+    Fg(u8, u8, u8),
+    Opacity(f32),
+}
+
+impl TweenValue {
+    /// current value of the same property of sp
+    pub fn get(&self, sp: &Sprite) -> TweenValue {
+        match self {
+            TweenValue::Pos(..) => {
+                let a = sp.content.area;
+                let (x, y) = sp.world_pos.unwrap_or((a.x as i32, a.y as i32));
+                TweenValue::Pos(x as f32, y as f32)
+            }
+            TweenValue::Angle(_) => TweenValue::Angle(sp.angle),
+            TweenValue::Fg(..) => {
+                let (r, g, b) = sp
+                    .content
+                    .content
+                    .iter()
+                    .find(|c| !c.is_blank())
+                    .map_or((255, 255, 255), |c| c.fg_rgb());
+                TweenValue::Fg(r, g, b)
+            }
+            TweenValue::Opacity(_) => TweenValue::Opacity(sp.opacity),
+        }
+    }
+
+    pub fn set(self, sp: &mut Sprite) {
+        match self {
+            TweenValue::Pos(x, y) => {
+                let (x, y) = (x.round() as i32, y.round() as i32);
+                if sp.world_pos.is_some() {
+                    sp.set_world_pos(x, y);
+                } else {
+                    sp.set_pos(x.max(0) as u16, y.max(0) as u16);
+                }
+            }
+            TweenValue::Angle(a) => sp.set_angle(a),
+            TweenValue::Fg(r, g, b) => {
+                for c in sp.content.content.iter_mut().filter(|c| !c.is_blank()) {
+                    c.set_fg(Color::Rgb(r, g, b));
+                }
+            }
+            TweenValue::Opacity(o) => sp.set_opacity(o),
+        }
+    }
+
+    /// value between a and b at k, a is returned if the kinds differ
+    pub fn lerp(a: TweenValue, b: TweenValue, k: f32) -> TweenValue {
+        let f = |x: f32, y: f32| x + (y - x) * k;
+        let c = |x: u8, y: u8| f(x as f32, y as f32).round().clamp(0.0, 255.0) as u8;
+        match (a, b) {
+            (TweenValue::Pos(x0, y0), TweenValue::Pos(x1, y1)) => {
+                TweenValue::Pos(f(x0, x1), f(y0, y1))
+            }
+            (TweenValue::Angle(a0), TweenValue::Angle(a1)) => {
+                TweenValue::Angle(a0 + (a1 - a0) * k as f64)
+            }
+            (TweenValue::Fg(r0, g0, b0), TweenValue::Fg(r1, g1, b1)) => {
+                TweenValue::Fg(c(r0, r1), c(g0, g1), c(b0, b1))
+            }
+            (TweenValue::Opacity(o0), TweenValue::Opacity(o1)) => TweenValue::Opacity(f(o0, o1)),
+            _ => a,
+        }
+    }
+}
+
+/// tween of one property
+#[derive(Debug, Clone)]
+pub struct PropTween {
+    pub to: TweenValue,
+    // start value, the value of the sprite when the tween starts if None
+    pub from: Option<TweenValue>,
+    pub duration: f32,
+    pub ease: Ease,
+    pub delay: f32,
+    // extra runs after the first one, u32::MAX repeats forever
+    pub repeat: u32,
+    // every other run plays backward
+    pub yoyo: bool,
+    start: Option<TweenValue>,
+    // time in the current run, negative while waiting for the delay
+    time: f32,
+    runs: u32,
+}
+
+#[derive(Debug, Clone)]
+pub enum Tween {
+    Prop(PropTween),
+    // seconds left
+    Wait(f32),
+    // children played one after another, index of the current one
+    Seq(Vec<Tween>, usize),
+    // children played together, with their finished flags
+    Par(Vec<(Tween, bool)>),
+}
+
+impl Tween {
+    pub fn to(to: TweenValue, secs: f32, ease: Ease) -> Self {
+        Tween::Prop(PropTween {
+            to,
+            from: None,
+            duration: secs,
+            ease,
+            delay: 0.0,
+            repeat: 0,
+            yoyo: false,
+            start: None,
+            time: 0.0,
+            runs: 0,
+        })
+    }
+
+    pub fn wait(secs: f32) -> Self {
+        Tween::Wait(secs)
+    }
+
+    pub fn seq(ts: Vec<Tween>) -> Self {
+        Tween::Seq(ts, 0)
+    }
+
+    pub fn par(ts: Vec<Tween>) -> Self {
+        Tween::Par(ts.into_iter().map(|t| (t, false)).collect())
+    }
+
+    fn prop(mut self, f: impl FnOnce(&mut PropTween)) -> Self {
+        if let Tween::Prop(p) = &mut self {
+            f(p);
+        }
+        self
+    }
+
+    /// the following builders apply to property tweens only
+    pub fn from(self, v: TweenValue) -> Self {
+        self.prop(|p| p.from = Some(v))
+    }
+
+    pub fn delay(self, secs: f32) -> Self {
+        self.prop(|p| {
+            p.delay = secs;
+            p.time = -secs;
+        })
+    }
+
+    pub fn repeat(self, n: u32) -> Self {
+        self.prop(|p| p.repeat = n)
+    }
+
+    pub fn forever(self) -> Self {
+        self.repeat(u32::MAX)
+    }
+
+    pub fn yoyo(self) -> Self {
+        self.prop(|p| p.yoyo = true)
+    }
+
+    /// advances dt seconds and updates sp,
+    /// returns the seconds left over once the tween has finished
+    pub fn update(&mut self, sp: &mut Sprite, dt: f32) -> Option<f32> {
+        match self {
+            Tween::Prop(p) => {
+                p.time += dt;
+                if p.time < 0.0 {
+                    return None;
+                }
+                let start = *p
+                    .start
+                    .get_or_insert_with(|| p.from.unwrap_or(p.to.get(sp)));
+                loop {
+                    let backward = p.yoyo && p.runs % 2 == 1;
+                    let (a, b) = if backward {
+                        (p.to, start)
+                    } else {
+                        (start, p.to)
+                    };
+                    if p.time < p.duration {
+                        let k = p.ease.apply(p.time / p.duration);
+                        TweenValue::lerp(a, b, k).set(sp);
+                        return None;
+                    }
+                    if p.runs >= p.repeat || p.duration <= 0.0 {
+                        b.set(sp);
+                        return Some(p.time - p.duration.max(0.0));
+                    }
+                    p.time -= p.duration;
+                    p.runs += 1;
+                }
+            }
+            Tween::Wait(left) => {
+                *left -= dt;
+                if *left > 0.0 {
+                    None
+                } else {
+                    Some(-*left)
+                }
+            }
+            Tween::Seq(ts, i) => {
+                let mut dt = dt;
+                while *i < ts.len() {
+                    dt = ts[*i].update(sp, dt)?;
+                    *i += 1;
+                }
+                Some(dt)
+            }
+            Tween::Par(ts) => {
+                let mut left = dt;
+                for (t, done) in ts.iter_mut().filter(|(_, d)| !*d) {
+                    if let Some(l) = t.update(sp, dt) {
+                        *done = true;
+                        left = left.min(l);
+                    }
+                }
+                if ts.iter().all(|(_, d)| *d) {
+                    Some(left)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+/// emitted through EventBus when a tween of a Tweener completes
+#[derive(Debug, Clone, PartialEq)]
+pub struct TweenEvent {
+    pub name: String,
+    // tag of the sprite
+    pub sprite: String,
+}
+
+#[derive(Default)]
+pub struct Tweener {
+    // (name, sprite tag, tween)
+    pub tweens: Vec<(String, String, Tween)>,
+}
+
+impl Tweener {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// runs tween on the sprite tagged tag, replacing the tween with the same name
+    pub fn add(&mut self, name: &str, tag: &str, tween: Tween) {
+        self.cancel(name);
+        self.tweens.push((name.to_string(), tag.to_string(), tween));
+    }
+
+    /// stops a tween, the sprite keeps its current values
+    pub fn cancel(&mut self, name: &str) {
+        self.tweens.retain(|t| t.0 != name);
+    }
+
+    pub fn is_running(&self, name: &str) -> bool {
+        self.tweens.iter().any(|t| t.0 == name)
+    }
+
+    pub fn update(&mut self, sprites: &mut Sprites, bus: &mut EventBus, dt: f32) {
+        self.tweens.retain_mut(|(name, tag, tw)| {
+            let Some(idx) = sprites.tag_index.get(tag.as_str()) else {
+                return false;
+            };
+            if tw.update(&mut sprites.sprites[*idx], dt).is_none() {
+                return true;
+            }
+            bus.emit(TweenEvent {
+                name: name.clone(),
+                sprite: tag.clone(),
+            });
+            false
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::style::Style;
+
+    #[test]
+    fn it_eases_from_zero_to_one() {
+        for e in [
+            Ease::QuadInOut,
+            Ease::ElasticOut,
+            Ease::BounceOut,
+            Ease::BackIn,
+        ] {
+            assert!(e.apply(0.0).abs() < 1e-4 && (e.apply(1.0) - 1.0).abs() < 1e-4);
+        }
+        assert_eq!(Ease::QuadIn.apply(0.5), 0.25);
+        assert!(Ease::BackIn.apply(0.2) < 0.0);
+    }
+
+    // moves for 1s, waits 0.5s, then yoyos the color
+    // while fading out after a delay
+    fn moving() -> (Sprites, EventBus, Tweener) {
+        let mut sprites = Sprites::new("t");
+        let mut sp = Sprite::new(0, 0, 2, 1);
+        sp.content
+            .set_str(0, 0, "ab", Style::default().fg(Color::Rgb(0, 0, 0)));
+        sprites.add_by_tag(sp, "a");
+        let mut bus = EventBus::new();
+        bus.subscribe::<TweenEvent>("t");
+        let mut tw = Tweener::new();
+        tw.add(
+            "move",
+            "a",
+            Tween::seq(vec![
+                Tween::to(TweenValue::Pos(10.0, 4.0), 1.0, Ease::Linear),
+                Tween::wait(0.5),
+                Tween::par(vec![
+                    Tween::to(TweenValue::Fg(200, 100, 0), 0.5, Ease::Linear)
+                        .yoyo()
+                        .repeat(1),
+                    Tween::to(TweenValue::Opacity(0.0), 1.0, Ease::Linear).delay(0.25),
+                ]),
+            ]),
+        );
+        (sprites, bus, tw)
+    }
+
+    #[test]
+    fn it_plays_sequences_in_order() {
+        let (mut sprites, mut bus, mut tw) = moving();
+        tw.update(&mut sprites, &mut bus, 0.5);
+        assert_eq!(sprites[0].content.area.x, 5);
+        tw.update(&mut sprites, &mut bus, 1.25);
+        assert_eq!(sprites[0].content.area.y, 4);
+        assert_eq!(sprites[0].content.content[0].fg, Color::Rgb(100, 50, 0));
+    }
+
+    #[test]
+    fn it_plays_yoyo_and_delay_in_parallel_groups() {
+        let (mut sprites, mut bus, mut tw) = moving();
+        for dt in [0.5, 1.25, 0.5] {
+            tw.update(&mut sprites, &mut bus, dt);
+        }
+        // yoyo back half way
+        assert_eq!(sprites[0].content.content[0].fg, Color::Rgb(100, 50, 0));
+        assert_eq!(sprites[0].opacity, 0.5);
+        assert!(tw.is_running("move"));
+        assert_eq!(bus.pending::<TweenEvent>("t"), 0);
+    }
+
+    #[test]
+    fn it_emits_an_event_when_done() {
+        let (mut sprites, mut bus, mut tw) = moving();
+        for dt in [0.5, 1.25, 0.5, 0.5] {
+            tw.update(&mut sprites, &mut bus, dt);
+        }
+        assert_eq!(sprites[0].content.content[0].fg, Color::Rgb(0, 0, 0));
+        assert_eq!(sprites[0].opacity, 0.0);
+        assert!(!tw.is_running("move"));
+        assert_eq!(bus.pending::<TweenEvent>("t"), 1);
+    }
+}
@@ -81,8 +81,14 @@ pub fn get_file_name(fpath: &str) -> String {
 }
 
 /// RCG
+/// seed is the last seed passed to srand, it is saved by input records and snapshots.
+/// If fixed_seed is set, srand_now uses it instead of the current time,
+/// so that a session can be replayed with the same random sequence
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Rand {
     rng: Xoshiro256StarStar,
+    seed: u64,
+    fixed_seed: Option<u64>,
 }
 
 impl Default for Rand {
@@ -96,21 +102,40 @@ impl Rand {
     pub fn new() -> Self {
         Self {
             rng: Xoshiro256StarStar::seed_from_u64(0),
+            seed: 0,
+            fixed_seed: None,
         }
     }
 
     pub fn srand(&mut self, seed: u64) {
         self.rng = Xoshiro256StarStar::seed_from_u64(seed);
+        self.seed = seed;
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn set_fixed_seed(&mut self, seed: Option<u64>) {
+        self.fixed_seed = seed;
     }
 
     #[cfg(target_arch = "wasm32")]
     pub fn srand_now(&mut self) {
+        if let Some(fs) = self.fixed_seed {
+            self.srand(fs);
+            return;
+        }
         let seed: u64 = js_sys::Date::now() as u64;
         self.srand(seed);
     }
 
     #[cfg(not(target_arch = "wasm32"))]
     pub fn srand_now(&mut self) {
+        if let Some(fs) = self.fixed_seed {
+            self.srand(fs);
+            return;
+        }
         let start = SystemTime::now();
         let since_the_epoch = start
             .duration_since(UNIX_EPOCH)
@@ -222,7 +247,7 @@ pub struct ARect {
     pub h: u32,
 }
 
-#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Default, Serialize, Deserialize)]
 pub struct Point {
     pub x: u16,
     pub y: u16,
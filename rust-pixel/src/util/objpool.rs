@@ -57,27 +57,25 @@ where
         }
     }
 
-    pub fn create(&mut self, otype: u8, ps: &Vec<Point>) {
-        let mut find = false;
+    /// returns the id of the created object
+    pub fn create(&mut self, otype: u8, ps: &Vec<Point>) -> usize {
         // search for an available object
         for o in &mut self.pool {
             if !o.active {
                 o.obj.reset(otype, ps);
                 o.active = true;
-                find = true;
-                break;
+                return o.id;
             }
         }
         // if not found, create a new one and add to the pool
-        if !find {
-            let l = self.pool.len();
-            let bo = GObj::new(otype, ps);
-            self.pool.push(GameObject {
-                id: l,
-                obj: bo,
-                active: true,
-            });
-        }
+        let l = self.pool.len();
+        let bo = GObj::new(otype, ps);
+        self.pool.push(GameObject {
+            id: l,
+            obj: bo,
+            active: true,
+        });
+        l
     }
 
     // processing active object by calling custom closure
@@ -10,7 +10,13 @@ export const js_load_asset = (url) => {
     ;
 };
 
-import init, {PixelGame} from "./pkg/pixel.js";
+import init, {
+    PixelGame,
+    atlas_version,
+    atlas_page_width,
+    atlas_page_rgba,
+    atlas_glyph_frame,
+} from "./pkg/pixel.js";
 const wasm = await init();
 const sg = PixelGame.new();
 
@@ -19,6 +25,7 @@ window.onkeypress = (e) => { sg.key_event(0, e); };
 window.onmouseup = (e) => { sg.key_event(1, e); };
 window.onmousedown = (e) => { sg.key_event(2, e); };
 window.onmousemove = (e) => { sg.key_event(3, e); };
+window.onkeyup = (e) => { sg.key_event(4, e); };
 
 // creat pix object and sprites...
 const pix = new Pix(document.getElementById("canvas"));
@@ -37,6 +44,53 @@ for (let i=0; i<32; i++) {
     }
 }
 
+// glyph atlas pages (render/atlas.rs) come as 1024 + page * 256 + sym,
+// like sync_atlases of the sdl adapter, textures of the pages are made
+// again when the atlases change, all frames of a page are registered
+// before its image loads
+const ATLAS_TEXIDX = 1024;
+let atlasVersion = -1;
+let atlasPages = [];
+// [width, height] of the atlas glyph frames
+const atlasSizes = [];
+const atlasCell = (texidx) => {
+    const v = atlas_version();
+    if (v !== atlasVersion) {
+        atlasVersion = v;
+        atlasPages.forEach(t => t.free());
+        atlasPages = [];
+        drawCells.length = ATLAS_TEXIDX;
+        atlasSizes.length = 0;
+    }
+    const page = (texidx - ATLAS_TEXIDX) >> 8;
+    if (atlasPages[page] === undefined) {
+        atlasPages[page] = null;
+        const w = atlas_page_width(page);
+        const rgba = atlas_page_rgba(page);
+        if (w === 0 || rgba.length === 0) return undefined;
+        const c = document.createElement("canvas");
+        c.width = w;
+        c.height = rgba.length / 4 / w;
+        c.getContext("2d").putImageData(
+            new ImageData(new Uint8ClampedArray(rgba.buffer), w, c.height), 0, 0);
+        const tex = new pix.Texture(c.toDataURL());
+        atlasPages[page] = tex;
+        for (let sym = 0; sym < 256; sym++) {
+            const f = atlas_glyph_frame(page, sym);
+            if (f.length < 4 || f[2] === 0) continue;
+            const idx = ATLAS_TEXIDX + page * 256 + sym;
+            const name = "atlas" + v + "_" + idx;
+            pix.register(name, pix.makeCellFrame(tex, f[0], f[1], f[2], f[3],
+                                                 f[2] / 2, f[3] / 2, 0));
+            drawCells[idx] = new pix.Cell(name);
+            atlasSizes[idx] = [f[2], f[3]];
+        }
+    }
+    return drawCells[texidx];
+};
+
+// BlendMode of the web cells
+const BLEND_OPS = ["source-over", "lighter", "multiply"];
 const transform = new Pix.Transform();
 pix.utils.loop(function(timeStep) {
     sg.tick(timeStep);
@@ -60,13 +114,32 @@ pix.utils.loop(function(timeStep) {
         const ang = wbuf[base + 8] / 1000.0;
         const cpx = wbuf[base + 9] | 0;
         const cpy = wbuf[base + 10] | 0;
+        const alpha = wbuf[base + 11] / 255.0;
+        const blend = wbuf[base + 12];
+        const cell = texidx < ATLAS_TEXIDX ? drawCells[texidx] : atlasCell(texidx);
+        if (!cell) continue;
         transform.identity();
-        transform.translate(spx + cpx - 8, spy + cpy - 8);
-        if(ang != 0.0) transform.rotate(ang);
-        transform.translate(-cpx + 8, -cpy + 8);
-        transform.scale(1.0 / ratio_x, 1.0 / ratio_y);
-        drawCells[texidx].draw(transform, r / 255.0, g / 255.0, b / 255.0, 1.0);
+        if (texidx < ATLAS_TEXIDX) {
+            transform.translate(spx + cpx - 8, spy + cpy - 8);
+            if(ang != 0.0) transform.rotate(ang);
+            transform.translate(-cpx + 8, -cpy + 8);
+            transform.scale(1.0 / ratio_x, 1.0 / ratio_y);
+        } else {
+            // glyphs are fitted into w x h by the adapter, keep the top-left
+            // of the cell where a 16 x 16 symbol would be
+            const w = wbuf[base + 6];
+            const h = wbuf[base + 7];
+            const [fw, fh] = atlasSizes[texidx];
+            transform.translate(spx + (w - 16 / ratio_x) / 2 + cpx - 8,
+                                spy + (h - 16 / ratio_y) / 2 + cpy - 8);
+            if(ang != 0.0) transform.rotate(ang);
+            transform.translate(-cpx + 8, -cpy + 8);
+            transform.scale(w / fw, h / fh);
+        }
+        pix.setBlendMode(BLEND_OPS[blend]);
+        cell.draw(transform, r / 255.0, g / 255.0, b / 255.0, alpha);
     }
+    pix.setBlendMode(BLEND_OPS[0]);
     // only 1 draw call...
     pix.flush();
     return true;
@@ -36,6 +36,7 @@ const Pix = function(canvasElement, antialias) {
         let _width = 0;
         let _height = 0;
         let _clearColor = new Pix.Color(1, 1, 1, 0);
+    let _blendMode = "source-over";
 
         _gl.activeTexture(TEXTURE_EDITING);
         _gl.bindTexture(_gl.TEXTURE_2D, _texture);
@@ -417,6 +418,26 @@ const Pix = function(canvasElement, antialias) {
     this.scale = (x, y) => touchTransform().scale(x, y);
     this.setClearColor = color => _clearColor = color;
     this.clear = () => clear(_clearColor);
+    // "source-over", "lighter" (additive) or "multiply", like canvas 2d
+    this.setBlendMode = mode => {
+        if(_blendMode === mode)
+            return;
+
+        flush();
+
+        switch(mode) {
+            case "lighter":
+                _gl.blendFuncSeparate(_gl.SRC_ALPHA, _gl.ONE, _gl.ONE, _gl.ONE_MINUS_SRC_ALPHA);
+                break;
+            case "multiply":
+                _gl.blendFuncSeparate(_gl.DST_COLOR, _gl.ONE_MINUS_SRC_ALPHA, _gl.ONE, _gl.ONE_MINUS_SRC_ALPHA);
+                break;
+            default:
+                _gl.blendFuncSeparate(_gl.SRC_ALPHA, _gl.ONE_MINUS_SRC_ALPHA, _gl.ONE, _gl.ONE_MINUS_SRC_ALPHA);
+        }
+
+        _blendMode = mode;
+    };
     this.getWidth = () => canvasElement.width;
     this.getHeight = () => canvasElement.height;
     this.unregister = name => delete _sprites[name];
@@ -497,6 +518,7 @@ const Pix = function(canvasElement, antialias) {
     let _instanceBuffer = new Float32Array(_instanceBufferCapacity);
     let _instanceCount = 0;
     let _clearColor = new Pix.Color(1, 1, 1, 0);
+    let _blendMode = "source-over";
 
     _uboContents[8] = _uboContents[9] = _uboContents[10] = _uboContents[11] = 1;
 
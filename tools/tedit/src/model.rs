@@ -1,4 +1,4 @@
-use rust_pixel::event::{Event, KeyCode, MouseButton, MouseEventKind::*};
+use rust_pixel::event::{Event, KeyCode, KeyEventKind, MouseButton, MouseEventKind::*};
 //use log::info;
 #[cfg(feature = "sdl")]
 use crate::render::{SYMBOL_SDL, SYMBOL_SDL_LOW};
@@ -7,10 +7,16 @@ use std::any::Any;
 use rust_pixel::render::adapter::PIXEL_TEXTURE_FILES;
 use rust_pixel::{
     context::Context,
-    event::event_emit,
     game::Model,
 };
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum TeditEvent {
+    RedrawEdit,
+    RedrawPen,
+    Save,
+}
+
 pub const COLORW: u16 = 18;
 pub const COLORH: u16 = 15;
 pub const SYMW: u16 = 18;
@@ -92,8 +98,8 @@ impl TeditModel {
 }
 
 impl Model for TeditModel {
-    fn init(&mut self, _context: &mut Context) {
-        event_emit("Tedit.RedrawPen");
+    fn init(&mut self, context: &mut Context) {
+        context.events.emit(TeditEvent::RedrawPen);
     }
 
     fn handle_input(&mut self, context: &mut Context, _dt: f32) {
@@ -101,8 +107,8 @@ impl Model for TeditModel {
         for e in &es {
             match e {
                 Event::Key(key) => {
-                    if key.code == KeyCode::Char('s') {
-                        event_emit("Tedit.Save");
+                    if key.code == KeyCode::Char('s') && key.kind != KeyEventKind::Release {
+                        context.events.emit(TeditEvent::Save);
                     }
                 }
                 Event::Mouse(mou) => {
@@ -115,7 +121,7 @@ impl Model for TeditModel {
                                 } else {
                                     self.curpen = TeditPen::BACK(idx);
                                 }
-                                event_emit("Tedit.RedrawPen");
+                                context.events.emit(TeditEvent::RedrawPen);
                             }
                         }
                         Some(TeditArea::SYMBOL(idx)) => {
@@ -142,7 +148,7 @@ impl Model for TeditModel {
                                     }
                                     self.curpen = TeditPen::SYMBOL(sym);
                                 }
-                                event_emit("Tedit.RedrawPen");
+                                context.events.emit(TeditEvent::RedrawPen);
                             }
                         }
                         Some(TeditArea::EDIT(idx)) => {
@@ -152,14 +158,14 @@ impl Model for TeditModel {
                             {
                                 self.curx = idx % EDITW;
                                 self.cury = idx / EDITW;
-                                event_emit("Tedit.RedrawEdit");
-                                event_emit("Tedit.RedrawPen");
+                                context.events.emit(TeditEvent::RedrawEdit);
+                                context.events.emit(TeditEvent::RedrawPen);
                             }
                         }
                         Some(TeditArea::ButtonNextSym) => {
                             if mou.kind == Up(MouseButton::Left) {
                                 self.sym_tab_idx = (self.sym_tab_idx + 1) % self.sym_tab_count;
-                                event_emit("Tedit.RedrawPen");
+                                context.events.emit(TeditEvent::RedrawPen);
                             }
                         }
                         Some(TeditArea::ButtonNextColor) =>
@@ -181,12 +187,12 @@ impl Model for TeditModel {
                                     }
                                     _ => {}
                                 }
-                                event_emit("Tedit.RedrawPen");
+                                context.events.emit(TeditEvent::RedrawPen);
                             }
                         }
                         Some(TeditArea::ButtonSave) => {
                             if mou.kind == Up(MouseButton::Left) {
-                                event_emit("Tedit.Save");
+                                context.events.emit(TeditEvent::Save);
                             }
                         }
                         _ => {}
@@ -1,11 +1,11 @@
-use crate::model::{TeditModel, TeditPen, COLORH, COLORW, EDITH, EDITW, SYMH, SYMW};
+use crate::model::{TeditEvent, TeditModel, TeditPen, COLORH, COLORW, EDITH, EDITW, SYMH, SYMW};
 use log::info;
 #[cfg(feature = "sdl")]
 use rust_pixel::render::cell::cellsym;
 use rust_pixel::{
     asset::{AssetState, AssetType},
     context::Context,
-    event::{event_check, event_register, timer_fire, timer_register},
+    event::TimerEvent,
     game::{Model, Render},
     render::sprite::{Sprites, BorderType, Borders, Sprite},
     render::style::{Color, Style},
@@ -314,13 +314,6 @@ impl TeditRender {
         );
         s.add_by_tag(msg3, "MSG3");
 
-        event_register("Tedit.RedrawEdit", "draw_edit");
-        event_register("Tedit.RedrawPen", "draw_pen");
-        event_register("Tedit.Save", "save");
-
-        timer_register("Tedit.HelpTimer", 6.0, "help_timer");
-        timer_fire("Tedit.HelpTimer", 0u8);
-
         //let tf = TermFile::new("snake.out");
 
         //panic if panel too small...
@@ -511,6 +504,10 @@ impl Render for TeditRender {
     fn init<G: Model>(&mut self, context: &mut Context, _data: &mut G) {
         context.adapter.init(SYMW + 2 + EDITW + 2, EDITH + 3, 1.0, 1.0, "tedit".to_string());
         self.panel.init(context);
+        context.events.subscribe::<TeditEvent>("render");
+        context.events.subscribe::<TimerEvent>("render");
+        context.timers.register("Tedit.HelpTimer", 6.0);
+        context.timers.fire("Tedit.HelpTimer", 0u8);
         let l = self.main_scene.get_by_tag("EDIT");
         l.set_content_asset2sprite(
             &mut context.asset_manager,
@@ -526,22 +523,24 @@ impl Render for TeditRender {
     }
 
     fn handle_event<G: Model>(&mut self, context: &mut Context, model: &mut G, _dt: f32) {
-        if event_check("Tedit.RedrawEdit", "draw_edit") {
+        let es = context.events.drain::<TeditEvent>("render");
+        if es.contains(&TeditEvent::RedrawEdit) {
             self.draw_edit(context, model);
         }
 
-        if event_check("Tedit.RedrawPen", "draw_pen") {
+        if es.contains(&TeditEvent::RedrawPen) {
             self.draw_pen(context, model);
         }
 
-        if event_check("Tedit.Save", "save") {
+        if es.contains(&TeditEvent::Save) {
             self.save(context, model);
         }
     }
 
-    fn handle_timer<G: Model>(&mut self, _context: &mut Context, _model: &mut G, _dt: f32) {
-        if event_check("Tedit.HelpTimer", "help_timer") {
-            timer_fire("Tedit.HelpTimer", 0u8);
+    fn handle_timer<G: Model>(&mut self, context: &mut Context, _model: &mut G, _dt: f32) {
+        let es = context.events.drain::<TimerEvent>("render");
+        if es.contains(&TimerEvent("Tedit.HelpTimer".to_string())) {
+            context.timers.fire("Tedit.HelpTimer", 0u8);
         }
     }
 
@@ -0,0 +1,48 @@
+// RustPixel
+// copyright zhouxin@tuyoogame.com 2022~2024
+
+//! packs an assets folder into one file, see rust_pixel::asset::pack
+//!
+//! run it from the pixel root, so paths in the pack match the
+//! locations games load assets with:
+//! cargo run -p tpack games/snake/assets snake.pack
+
+use rust_pixel::asset::pack::{pack_dir, AssetPack};
+use std::env;
+use std::fs;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let (dir, out, prefix) = match args.len() {
+        3 => (&args[1], &args[2], &args[1]),
+        4 => (&args[1], &args[2], &args[3]),
+        _ => {
+            eprintln!("Usage: tpack <assets dir> <out.pack> [<path prefix>]");
+            std::process::exit(2);
+        }
+    };
+
+    let data = match pack_dir(dir, prefix) {
+        Ok(d) => d,
+        Err(e) => {
+            eprintln!("tpack: {}", e);
+            std::process::exit(1);
+        }
+    };
+    // read it back to check the pack
+    let pack = AssetPack::from_bytes(data.clone()).expect("tpack: bad pack");
+    for p in pack.paths() {
+        println!("{}", p);
+    }
+    fs::write(out, &data).expect("tpack: failed to write pack");
+    println!(
+        "{} files, {} bytes -> {}",
+        pack.entries.len(),
+        data.len(),
+        out
+    );
+    println!(
+        "mount it with: ctx.asset_manager.mount(embed_pack!(\"{}\").unwrap());",
+        out
+    );
+}